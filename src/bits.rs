@@ -0,0 +1,222 @@
+use alloc::vec::Vec;
+use core::cmp::min;
+
+// Bit stream
+//------------------------------------------------------------------------------
+
+// Growable sequence of bits for building custom segments, and the one the encoder writes its data
+// stream to. Bits are stored msb first: bit i of the stream is bit 7 - i % 8 of byte i / 8, which
+// is the order QR codewords are read in. Bits past the end of the last byte read as 0
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+pub struct BitStream {
+    data: Vec<u8>,
+    bit_len: usize,
+}
+
+impl BitStream {
+    // Capacity is a hint in bits, the stream grows past it as needed
+    pub fn new(bit_capacity: usize) -> Self {
+        Self { data: Vec::with_capacity(bit_capacity.div_ceil(8)), bit_len: 0 }
+    }
+
+    pub fn len(&self) -> usize {
+        self.bit_len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bit_len == 0
+    }
+
+    // Backing bytes, the last one padded with 0s when the length isn't a multiple of 8
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn into_data(self) -> Vec<u8> {
+        self.data
+    }
+
+    pub fn put(&mut self, bit: bool) {
+        if self.bit_len & 7 == 0 {
+            self.data.push(0);
+        }
+        if bit {
+            let last = self.data.len() - 1;
+            self.data[last] |= 0x80 >> (self.bit_len & 7);
+        }
+        self.bit_len += 1;
+    }
+
+    pub fn get(&self, i: usize) -> bool {
+        debug_assert!(i < self.bit_len, "Index out of bounds: Index {i}, Len {}", self.bit_len);
+
+        (self.data[i >> 3] >> (7 - (i & 7))) & 1 == 1
+    }
+
+    // Appends the low n bits of value, most significant first, e.g. mode and char count
+    // indicators. A field can start anywhere in a byte and straddle as many bytes as it needs
+    pub fn push_bits(&mut self, value: u32, n: usize) {
+        debug_assert!(n <= 32, "Field is wider than 32 bits: {n}");
+        debug_assert!(
+            n == 32 || value >> n == 0,
+            "Value doesn't fit in bit length: Value {value}, Length {n}"
+        );
+
+        // Fills the free low bits of the last byte, then whole bytes
+        let mut remaining = n;
+        while remaining > 0 {
+            let offset = self.bit_len & 7;
+            if offset == 0 {
+                self.data.push(0);
+            }
+            let take = min(8 - offset, remaining);
+            let chunk = (value >> (remaining - take)) & ((1 << take) - 1);
+            let last = self.data.len() - 1;
+            self.data[last] |= (chunk << (8 - offset - take)) as u8;
+            self.bit_len += take;
+            remaining -= take;
+        }
+    }
+
+    // Mirror of push_bits, reads n bits starting at bit start into the low bits of the result
+    pub fn get_bits(&self, start: usize, n: usize) -> u32 {
+        debug_assert!(n <= 32, "Field is wider than 32 bits: {n}");
+        debug_assert!(
+            start + n <= self.bit_len,
+            "Range out of bounds: Start {start}, Len {n}, Stream len {}",
+            self.bit_len
+        );
+
+        let (mut res, mut i) = (0_u32, start);
+        while i < start + n {
+            let offset = i & 7;
+            let take = min(8 - offset, start + n - i);
+            let chunk = (self.data[i >> 3] as u32 >> (8 - offset - take)) & ((1 << take) - 1);
+            res = (res << take) | chunk;
+            i += take;
+        }
+        res
+    }
+
+    // Appends whole bytes, each msb first. The stream needn't be byte aligned
+    pub fn extend(&mut self, bytes: &[u8]) {
+        if self.bit_len & 7 == 0 {
+            self.data.extend_from_slice(bytes);
+            self.bit_len += bytes.len() << 3;
+            return;
+        }
+
+        for &b in bytes {
+            (0..8).rev().for_each(|i| self.put((b >> i) & 1 == 1));
+        }
+    }
+}
+
+// Wraps bytes as a stream of all their bits, e.g. codewords read back from a grid
+impl From<Vec<u8>> for BitStream {
+    fn from(data: Vec<u8>) -> Self {
+        let bit_len = data.len() << 3;
+        Self { data, bit_len }
+    }
+}
+
+#[cfg(test)]
+mod bit_stream_tests {
+    use alloc::vec;
+
+    use super::BitStream;
+
+    #[test]
+    fn test_put_get() {
+        let bits = [true, false, true, true, false, false, true, false, true, true];
+        let mut bs = BitStream::new(16);
+        bits.iter().for_each(|&b| bs.put(b));
+
+        assert_eq!(bs.len(), 10);
+        assert_eq!(bs.data(), &[0b1011_0010, 0b1100_0000]);
+        for (i, &b) in bits.iter().enumerate() {
+            assert_eq!(bs.get(i), b, "{i}");
+        }
+    }
+
+    #[test]
+    fn test_extend_aligned() {
+        let mut bs = BitStream::new(0);
+        bs.extend(&[0xA5, 0x3C]);
+        assert_eq!(bs.len(), 16);
+        assert_eq!(bs.data(), &[0xA5, 0x3C]);
+    }
+
+    // Every byte straddles two bytes of the stream once it's offset by 3 bits
+    #[test]
+    fn test_extend_across_byte_boundary() {
+        let mut bs = BitStream::new(19);
+        [true, false, true].into_iter().for_each(|b| bs.put(b));
+        bs.extend(&[0xFF, 0x0F]);
+
+        assert_eq!(bs.len(), 19);
+        assert_eq!(bs.data(), &[0b1011_1111, 0b1110_0001, 0b1110_0000]);
+        assert!(bs.get(10) && !bs.get(11) && bs.get(18));
+    }
+
+    // Mode and count indicator widths packed back to back, so fields after the first start mid
+    // byte and the 13 bit ones span three bytes
+    #[test]
+    fn test_push_get_bits() {
+        let fields = [
+            (0b1010, 4),
+            (0b1_0110_0101, 9),
+            (0b1_1100_1010_0111, 13),
+            (0b0111, 4),
+            (0b0_0110_1101_1001, 13),
+            (0b0_1111_0001, 9),
+        ];
+        let mut bs = BitStream::new(52);
+        fields.iter().for_each(|&(v, n)| bs.push_bits(v, n));
+        assert_eq!(bs.len(), 52);
+        assert_eq!(&bs.data()[..3], &[0b1010_1011, 0b0010_1111, 0b0010_1001]);
+
+        let mut start = 0;
+        for &(v, n) in fields.iter() {
+            assert_eq!(bs.get_bits(start, n), v, "Start {start}, Len {n}");
+            start += n;
+        }
+    }
+
+    #[test]
+    fn test_push_bits_every_offset() {
+        for offset in 0..8 {
+            for n in [4, 9, 13] {
+                let value = 0b1_0110_1100_1011 >> (13 - n);
+                let mut bs = BitStream::new(offset + n);
+                bs.push_bits(0, offset);
+                bs.push_bits(value, n);
+                assert_eq!(bs.get_bits(offset, n), value, "Offset {offset}, Len {n}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_push_bits_32() {
+        let mut bs = BitStream::new(35);
+        bs.push_bits(0b101, 3);
+        bs.push_bits(0xDEAD_BEEF, 32);
+        assert_eq!(bs.get_bits(3, 32), 0xDEAD_BEEF);
+        assert_eq!(bs.get_bits(0, 3), 0b101);
+    }
+
+    #[test]
+    fn test_from_bytes() {
+        let bs = BitStream::from(vec![0xA5, 0x3C]);
+        assert_eq!(bs.len(), 16);
+        assert_eq!(bs.get_bits(4, 8), 0x53);
+        assert_eq!(bs.into_data(), vec![0xA5, 0x3C]);
+    }
+
+    #[test]
+    fn test_empty() {
+        let bs = BitStream::default();
+        assert!(bs.is_empty());
+        assert_eq!(bs.data(), &[] as &[u8]);
+    }
+}
@@ -1,29 +1,100 @@
-use std::ops::Deref;
+use alloc::{
+    borrow::Cow,
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
 
+#[cfg(feature = "std")]
+use crate::reader::QRReader;
 use crate::{
-    codec::{encode, encode_with_version},
-    ec::{ecc, error_correction_capacity},
+    codec::{
+        analyze_segments, encode, encode_to_bits, encode_with_channel_ec_levels, encode_with_eci,
+        encode_with_mode, encode_with_version, replace_padding_codewords, Mode, SegmentStat,
+        UTF8_ECI,
+    },
+    ec::{error_correction_capacity, Encoder},
     error::{QRError, QRResult},
-    mask::{apply_best_mask, MaskPattern},
-    metadata::{ECLevel, Palette, Version},
+    mask::{compute_mask_penalties_with_config, MaskPattern, MaskSelectionConfig},
+    metadata::{ECLevel, Palette, Version, PALETTE},
     qr::QR,
 };
 
 pub struct QRBuilder<'a> {
-    data: &'a [u8],
+    data: Cow<'a, [u8]>,
     version: Option<Version>,
+    min_version: Option<Version>,
+    max_version: Option<Version>,
     ec_level: ECLevel,
+    channel_ec_levels: Option<[ECLevel; 3]>,
     palette: Palette,
+    colors: [[u8; 3]; 8],
+    mode: Option<Mode>,
+    eci: Option<u32>,
     mask: Option<MaskPattern>,
+    mask_selection: MaskSelectionConfig,
+    pad_bytes: Option<[u8; 2]>,
+    skip_mask: bool,
+    maximize_ec: bool,
 }
 
 impl<'a> QRBuilder<'a> {
     pub fn new(data: &'a [u8]) -> Self {
-        Self { data, version: None, ec_level: ECLevel::M, palette: Palette::Mono, mask: None }
+        Self {
+            data: Cow::Borrowed(data),
+            version: None,
+            min_version: None,
+            max_version: None,
+            ec_level: ECLevel::M,
+            channel_ec_levels: None,
+            palette: Palette::Mono,
+            colors: PALETTE,
+            mode: None,
+            eci: None,
+            mask: None,
+            mask_selection: MaskSelectionConfig::default(),
+            pad_bytes: None,
+            skip_mask: false,
+            maximize_ec: false,
+        }
+    }
+
+    // Text with non ascii chars is prefixed with a utf8 eci so readers don't fall back to latin1.
+    // Plain ascii reads the same either way and skips the header
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(data: &'a str) -> Self {
+        let mut builder = Self::new(data.as_bytes());
+        if !data.is_ascii() {
+            builder.eci = Some(UTF8_ECI);
+        }
+        builder
+    }
+
+    // Scheme and host are case insensitive, so uppercasing them keeps that prefix in alphanumeric
+    // mode. Userinfo, path, query and fragment are left untouched
+    pub fn url(url: &str) -> Self {
+        let mut res = url.to_string();
+        if let Some(scheme_end) = url.find("://") {
+            let auth_start = scheme_end + 3;
+            let auth_end =
+                url[auth_start..].find(['/', '?', '#']).map_or(url.len(), |i| auth_start + i);
+            let host_start =
+                url[auth_start..auth_end].rfind('@').map_or(auth_start, |i| auth_start + i + 1);
+            res[..scheme_end].make_ascii_uppercase();
+            res[host_start..auth_end].make_ascii_uppercase();
+        }
+
+        let mut builder = Self::new(&[]);
+        if !res.is_ascii() {
+            builder.eci = Some(UTF8_ECI);
+        }
+        builder.data = Cow::Owned(res.into_bytes());
+        builder
     }
 
     pub fn data(&mut self, data: &'a [u8]) -> &mut Self {
-        self.data = data;
+        self.data = Cow::Borrowed(data);
         self
     }
 
@@ -37,18 +108,98 @@ impl<'a> QRBuilder<'a> {
         self
     }
 
+    // Auto selection won't go below this version, short data is padded out to fill it. An explicit
+    // version takes precedence
+    pub fn min_version(&mut self, version: Version) -> &mut Self {
+        self.min_version = Some(version);
+        self
+    }
+
+    pub fn unset_min_version(&mut self) -> &mut Self {
+        self.min_version = None;
+        self
+    }
+
+    // Auto selection won't go above this version, e.g. for scanners that can't resolve dense
+    // codes. Data needing more errors with DataTooLong. An explicit version takes precedence. Build
+    // fails with InvalidVersion when the minimum is above it or of the other kind, and for micro
+    // versions, which auto selection never picks
+    pub fn max_version(&mut self, version: Version) -> &mut Self {
+        self.max_version = Some(version);
+        self
+    }
+
+    pub fn unset_max_version(&mut self) -> &mut Self {
+        self.max_version = None;
+        self
+    }
+
     pub fn ec_level(&mut self, ec_level: ECLevel) -> &mut Self {
         self.ec_level = ec_level;
         self
     }
 
+    // Poly only, gives each channel its own ec level in place of ec_level, e.g. a critical red
+    // channel at H. Capacity is the sum of each channel's mono capacity
+    pub fn channel_ec_levels(&mut self, ec_levels: [ECLevel; 3]) -> &mut Self {
+        self.channel_ec_levels = Some(ec_levels);
+        self
+    }
+
     pub fn palette(&mut self, palette: Palette) -> &mut Self {
         self.palette = palette;
         self
     }
 
+    // Custom colors indexed like PALETTE. Build fails with InvalidPalette when the colors the
+    // palette draws can't be told apart on every rgb channel
+    pub fn colors(&mut self, colors: [[u8; 3]; 8]) -> &mut Self {
+        self.colors = colors;
+        self
+    }
+
+    // Encodes all data as a single segment of this mode, failing on the first byte it can't hold
+    pub fn mode(&mut self, mode: Mode) -> &mut Self {
+        self.mode = Some(mode);
+        self
+    }
+
+    pub fn unset_mode(&mut self) -> &mut Self {
+        self.mode = None;
+        self
+    }
+
+    // Spends slack in the chosen version on the highest ec level that still fits it. Build fails
+    // with InvalidECLevel when channel ec levels are also set, as those are taken as given
+    pub fn maximize_ec(&mut self) -> &mut Self {
+        self.maximize_ec = true;
+        self
+    }
+
     pub fn mask(&mut self, mask: MaskPattern) -> &mut Self {
         self.mask = Some(mask);
+        self.skip_mask = false;
+        self
+    }
+
+    // Weights used to rank masks when none is set explicitly
+    pub fn mask_selection(&mut self, config: MaskSelectionConfig) -> &mut Self {
+        self.mask_selection = config;
+        self
+    }
+
+    // Alternates this pair instead of 0xEC 0x11 over unused capacity. Readers stop at the
+    // terminator, so the code reads the same
+    pub fn pad_bytes(&mut self, a: u8, b: u8) -> &mut Self {
+        self.pad_bytes = Some([a, b]);
+        self
+    }
+
+    // Leaves data modules unmasked and format info as the reserved placeholder. Meant for studying
+    // the raw grid, the output is not a scannable code
+    pub fn no_mask(&mut self) -> &mut Self {
+        self.mask = None;
+        self.skip_mask = true;
         self
     }
 
@@ -85,93 +236,260 @@ mod qrbuilder_util_tests {
     }
 }
 
+#[derive(Debug, Clone)]
+pub struct BuildReport {
+    pub data_capacity: usize,
+    // Codewords the code can correct, the weakest channel's for poly codes
+    pub err_corr_capacity: usize,
+    // Per channel in rgb order, one entry for mono
+    pub channel_err_corr_capacities: Vec<usize>,
+    pub data_len: usize,
+    pub encoded_len: usize,
+    pub mask_penalties: Vec<(MaskPattern, u32)>,
+}
+
 impl<'a> QRBuilder<'a> {
-    pub fn build(&self) -> QRResult<QR> {
-        let data_len = self.data.len();
+    // Runs encoding and version selection without constructing the grid, returning the version
+    // the data would be built with
+    pub fn fits(&self) -> QRResult<Version> {
+        let (_, _, _, version) = self.validate_and_encode()?;
+        Ok(version)
+    }
+
+    // Every check that doesn't need the grid, shared by fits and build so fits only succeeds
+    // when build would
+    fn validate_and_encode(&self) -> QRResult<(Vec<u8>, usize, usize, Version)> {
+        if self.data.is_empty() {
+            return Err(QRError::EmptyData);
+        }
+        self.palette.validate_contrast(&self.colors)?;
+        if self.channel_ec_levels.is_some() && !matches!(self.palette, Palette::Poly) {
+            return Err(QRError::InvalidPalette);
+        }
+        if self.channel_ec_levels.is_some() && self.maximize_ec {
+            return Err(QRError::InvalidECLevel);
+        }
+        if let Some(Version::Micro(_)) = self.max_version {
+            return Err(QRError::InvalidVersion);
+        }
+        // Bounds of different kinds don't order, micro versions all sort below normal ones
+        if let (Some(min), Some(max)) = (self.min_version, self.max_version) {
+            let same_kind = matches!(
+                (min, max),
+                (Version::Micro(_), Version::Micro(_)) | (Version::Normal(_), Version::Normal(_))
+            );
+            if !same_kind || min > max {
+                return Err(QRError::InvalidVersion);
+            }
+        }
+
+        let (encoded_data, encoded_len, pad_start, version) =
+            self.encode_data(self.ec_level, self.version)?;
+
+        // Micro only has 4 masks, checked once the version is resolved
+        if let Some(m) = self.mask {
+            let mask_count = if let Version::Micro(_) = version { 4 } else { 8 };
+            if *m >= mask_count {
+                return Err(QRError::InvalidMaskingPattern);
+            }
+        }
+
+        Ok((encoded_data, encoded_len, pad_start, version))
+    }
 
-        println!("\nGenerating QR {}...", self.metadata());
+    // Segments the data is split into at the version it would be built with, to explain why that
+    // version was chosen
+    pub fn analyze(&self) -> QRResult<Vec<SegmentStat>> {
+        let version = self.fits()?;
+        analyze_segments(&self.data, self.ec_level, version, self.palette, self.mode, self.eci)
+    }
+
+    // Bitstream before ecc with fields separated by spaces, e.g. "0100 00001101 ...", for debugging
+    pub fn encode_to_bits(&self) -> QRResult<String> {
         if self.data.is_empty() {
             return Err(QRError::EmptyData);
         }
 
+        encode_to_bits(&self.data, self.ec_level, self.version, self.palette, self.mode, self.eci)
+    }
+
+    pub fn build(&self) -> QRResult<QR> {
+        self.build_with_report().map(|(qr, _)| qr)
+    }
+
+    // Builds, then reads the grid straight back and fails unless it decodes to the input, e.g.
+    // to catch encoder bugs before a code is printed
+    #[cfg(feature = "std")]
+    pub fn build_verified(&self) -> QRResult<QR> {
+        let qr = self.build()?;
+        self.verify(&qr)?;
+        Ok(qr)
+    }
+
+    // Errors with RoundTripMismatch when the grid decodes to anything other than the input
+    #[cfg(feature = "std")]
+    pub fn verify(&self, qr: &QR) -> QRResult<()> {
+        progress!("Verifying QR...");
+        let decoded = QRReader::read_bytes(qr)?;
+        if decoded != *self.data {
+            return Err(QRError::RoundTripMismatch);
+        }
+        Ok(())
+    }
+
+    pub fn build_with_report(&self) -> QRResult<(QR, BuildReport)> {
+        let data_len = self.data.len();
+
+        progress!("\nGenerating QR {}...", self.metadata());
+
         // Encode data optimally
-        println!("Encoding data...");
-        let (encoded_data, encoded_len, version) = match self.version {
-            Some(v) => encode_with_version(self.data, self.ec_level, v, self.palette)?,
-            None => encode(self.data, self.ec_level, self.palette)?,
-        };
+        progress!("Encoding data...");
+        let (mut encoded_data, mut encoded_len, mut pad_start, version) =
+            self.validate_and_encode()?;
 
-        let version_capacity = version.bit_capacity(self.ec_level, self.palette) >> 3;
-        let err_corr_cap = error_correction_capacity(version, self.ec_level);
+        // Capacity shrinks as ec level grows, so stop at the first level that doesn't fit
+        let mut ec_level = self.channel_ec_levels.map_or(self.ec_level, |l| l[0]);
+        if self.maximize_ec {
+            for l in self.ec_level as u8 + 1..=ECLevel::H as u8 {
+                let next_level = ECLevel::from(l);
+                match self.encode_data(next_level, Some(version)) {
+                    Ok((data, len, start, _)) => {
+                        progress!("Upgrading ec level to {next_level:?}...");
+                        (encoded_data, encoded_len, pad_start, ec_level) =
+                            (data, len, start, next_level);
+                    }
+                    Err(_) => break,
+                }
+            }
+        }
+
+        if let Some(pad) = self.pad_bytes {
+            replace_padding_codewords(&mut encoded_data, pad_start, pad, version);
+        }
+
+        // Poly splits the data into three channels by their capacity, each with its own ecc
+        let ec_levels = match self.palette {
+            Palette::Mono => vec![ec_level],
+            Palette::Poly => self.channel_ec_levels.unwrap_or([ec_level; 3]).to_vec(),
+        };
+        let version_capacity =
+            ec_levels.iter().map(|&l| version.bit_capacity(l, Palette::Mono)).sum::<usize>() >> 3;
+        let channel_err_corr_capacities =
+            ec_levels.iter().map(|&l| error_correction_capacity(version, l)).collect::<Vec<_>>();
+        let err_corr_cap = channel_err_corr_capacities.iter().copied().min().unwrap_or(0);
 
-        // Compute error correction codewords
-        println!("Computing ecc...");
-        let (data_blocks, ecc_blocks) = ecc(&encoded_data, version, self.ec_level);
+        let mut payload = Vec::with_capacity(version.total_codewords(self.palette));
+        let mut remaining = &encoded_data[..];
+        for &l in ec_levels.iter() {
+            let channel_len = match self.palette {
+                Palette::Mono => remaining.len(),
+                Palette::Poly => version.bit_capacity(l, Palette::Mono) >> 3,
+            };
+            let (channel_data, rest) = remaining.split_at(channel_len);
+            remaining = rest;
 
-        // Interleave data and error correction codewords
-        println!("Interleaving and chaining data & ecc...");
-        let mut payload = Self::interleave(&data_blocks);
-        payload.extend(Self::interleave(&ecc_blocks));
+            progress!("Computing ecc, interleaving and chaining data & ecc...");
+            payload.extend(Encoder::new(version, l)?.encode_payload(channel_data));
+        }
 
         // Construct QR
-        println!("Constructing QR...");
-        let mut qr = QR::new(version, self.ec_level, self.palette);
+        progress!("Constructing QR...");
+        let mut qr = QR::new(version, ec_level, self.palette);
+        if let Some(ec_levels) = self.channel_ec_levels {
+            qr.set_channel_ec_levels(ec_levels);
+        }
+        qr.set_colors(self.colors);
 
-        println!("Drawing functional patterns...");
+        progress!("Drawing functional patterns...");
         qr.draw_all_function_patterns();
 
-        println!("Drawing encoding region...");
+        progress!("Drawing encoding region...");
         qr.draw_encoding_region(&payload);
 
-        let mask = match self.mask {
+        progress!("Ranking masks...");
+        let mask_penalties = compute_mask_penalties_with_config(&qr, &self.mask_selection);
+
+        match self.mask {
+            _ if self.skip_mask => progress!("Skipping mask..."),
             Some(m) => {
-                println!("Apply mask {m:?}...");
+                progress!("Apply mask {m:?}...");
                 qr.mask(m);
-                m
+                qr.record_mask_penalty();
             }
             None => {
-                println!("Finding & applying best mask...");
-                apply_best_mask(&mut qr)
+                progress!("Applying best mask...");
+                qr.mask(mask_penalties[0].0);
+                qr.record_mask_penalty();
             }
-        };
+        }
 
-        println!("\x1b[1;32mQR generated successfully!\n \x1b[0m");
+        progress!("\x1b[1;32mQR generated successfully!\n \x1b[0m");
 
         let total_modules = version.width() * version.width();
         let dark_modules = qr.count_dark_modules();
         let light_modules = total_modules - dark_modules;
 
-        println!("Report:");
-        println!("{}", qr.metadata());
-        println!("Data capacity: {}, Error Capacity: {}", version_capacity, err_corr_cap);
-        println!(
+        progress!("Report:");
+        progress!("{}", qr.metadata());
+        progress!("Data capacity: {}, Error Capacity: {}", version_capacity, err_corr_cap);
+        progress!(
             "Data size: {}, Encoded size: {}, Compression: {}%",
             data_len,
             encoded_len,
             encoded_len * 100 / data_len
         );
-        println!(
+        progress!(
             "Dark Cells: {}, Light Cells: {}, Balance: {}\n",
             dark_modules,
             light_modules,
             dark_modules * 100 / total_modules
         );
 
-        Ok(qr)
+        let report = BuildReport {
+            data_capacity: version_capacity,
+            err_corr_capacity: err_corr_cap,
+            channel_err_corr_capacities,
+            data_len,
+            encoded_len,
+            mask_penalties,
+        };
+
+        Ok((qr, report))
     }
 
-    pub fn interleave<T: Copy, V: Deref<Target = [T]>>(blocks: &[V]) -> Vec<T> {
-        let max_block_size = blocks.iter().map(|b| b.len()).max().expect("Blocks is empty");
-        let total_size = blocks.iter().map(|b| b.len()).sum::<usize>();
-        let mut res = Vec::with_capacity(total_size);
-        for i in 0..max_block_size {
-            for b in blocks {
-                if i < b.len() {
-                    res.push(b[i]);
-                }
+    fn encode_data(
+        &self,
+        ec_level: ECLevel,
+        version: Option<Version>,
+    ) -> QRResult<(Vec<u8>, usize, usize, Version)> {
+        if let Some(v) = version.filter(|v| !v.supports_ec_level(ec_level)) {
+            return Err(QRError::UnsupportedECLevel(v, ec_level));
+        }
+        if self.channel_ec_levels.is_some() && matches!(version, Some(Version::Micro(_))) {
+            return Err(QRError::InvalidVersion);
+        }
+
+        let encode_at = |version| match (self.channel_ec_levels, self.eci, self.mode, version) {
+            (Some(l), e, m, v) => encode_with_channel_ec_levels(&self.data, l, v, m, e),
+            (None, Some(e), m, v) => encode_with_eci(&self.data, ec_level, v, self.palette, m, e),
+            (None, None, Some(m), v) => encode_with_mode(&self.data, ec_level, v, self.palette, m),
+            (None, None, None, Some(v)) => {
+                encode_with_version(&self.data, ec_level, v, self.palette)
+            }
+            (None, None, None, None) => encode(&self.data, ec_level, self.palette),
+        };
+
+        // Data that fits below the minimum also fits in it, so re-encode there. Auto selection
+        // picks the smallest version that fits, so anything past the maximum can't fit under it
+        match encode_at(version)? {
+            (_, _, _, v) if version.is_none() && self.max_version.is_some_and(|m| v > m) => {
+                Err(QRError::DataTooLong)
+            }
+            (_, _, _, v) if version.is_none() && self.min_version.is_some_and(|m| v < m) => {
+                encode_at(self.min_version)
             }
+            res => Ok(res),
         }
-        res
     }
 }
 
@@ -181,17 +499,17 @@ mod builder_tests {
 
     use crate::{
         builder::QRBuilder,
-        metadata::{ECLevel, Version},
+        codec::{Mode, SegmentStat},
+        ec::error_correction_capacity,
+        error::QRError,
+        mask::{compute_total_penalty, MaskPattern, MaskSelectionConfig},
+        metadata::{Color, ECLevel, Palette, Version, PALETTE},
+        qr::Module,
     };
+    #[cfg(feature = "std")]
+    use crate::{deqr::DeQR, reader::QRReader};
 
-    #[test]
-    fn test_interleave() {
-        let blocks = vec![vec![1, 2, 3], vec![4, 5, 6], vec![7, 8, 9, 0]];
-        let interleaved = QRBuilder::interleave(&blocks);
-        let exp_interleaved = vec![1, 4, 7, 2, 5, 8, 3, 6, 9, 0];
-        assert_eq!(interleaved, exp_interleaved);
-    }
-
+    #[cfg(feature = "std")]
     #[test_case("Hello, world!🌎".to_string(), Version::Normal(1), ECLevel::L)]
     #[test_case("TEST".to_string(), Version::Normal(1), ECLevel::M)]
     #[test_case("12345".to_string(), Version::Normal(1), ECLevel::Q)]
@@ -229,6 +547,64 @@ mod builder_tests {
         assert_eq!(data, content);
     }
 
+    #[test]
+    fn test_build_with_report_mask_penalties() {
+        let data = "Hello, world!🌎".as_bytes();
+        let (qr, report) = QRBuilder::new(data)
+            .version(Version::Normal(3))
+            .ec_level(ECLevel::Q)
+            .build_with_report()
+            .unwrap();
+
+        let penalties = report.mask_penalties;
+        assert!(penalties.windows(2).all(|w| w[0].1 <= w[1].1));
+        assert_eq!(qr.mask_pattern(), Some(penalties[0].0));
+        assert_eq!(penalties[0].1, compute_total_penalty(&qr));
+        let mut masks = penalties.iter().map(|(m, _)| **m).collect::<Vec<_>>();
+        masks.sort();
+        assert_eq!(masks, [0, 1, 2, 3, 4, 5, 6, 7]);
+    }
+
+    #[test_case(None, MaskSelectionConfig::default())]
+    #[test_case(Some(MaskPattern::new(3)), MaskSelectionConfig::default())]
+    #[test_case(None, MaskSelectionConfig { block_weight: 10, ..Default::default() })]
+    fn test_mask_penalty(mask: Option<MaskPattern>, config: MaskSelectionConfig) {
+        let mut builder = QRBuilder::new(b"Hello, world!");
+        builder.version(Version::Normal(2)).mask_selection(config);
+        if let Some(m) = mask {
+            builder.mask(m);
+        }
+        let qr = builder.build().unwrap();
+        assert_eq!(qr.mask_penalty(), Some(compute_total_penalty(&qr)));
+
+        let unmasked = QRBuilder::new(b"Hello, world!").no_mask().build().unwrap();
+        assert_eq!(unmasked.mask_penalty(), None);
+    }
+
+    #[test]
+    fn test_build_no_mask() {
+        let data = "Hello, world!🌎".as_bytes();
+        let version = Version::Normal(3);
+        let masked = QRBuilder::new(data).version(version).build().unwrap();
+        let unmasked = QRBuilder::new(data).version(version).no_mask().build().unwrap();
+        assert_eq!(unmasked.mask_pattern(), None);
+
+        let mask_function = masked.mask_pattern().unwrap().mask_functions_for(version);
+        let w = version.width() as i16;
+        for r in 0..w {
+            for c in 0..w {
+                match (masked.get(r, c), unmasked.get(r, c)) {
+                    (Module::Data(m), Module::Data(u)) => {
+                        assert_eq!(m == u, !mask_function(r, c), "{r} {c}")
+                    }
+                    (Module::Format(_), Module::Format(u)) => assert_eq!(u, Color::Dark),
+                    (m, u) => assert_eq!(m, u, "{r} {c}"),
+                }
+            }
+        }
+    }
+
+    #[cfg(feature = "std")]
     #[test]
     #[should_panic]
     fn test_builder_data_overflow() {
@@ -241,4 +617,474 @@ mod builder_tests {
             .unwrap()
             .render(10);
     }
+
+    #[test_case(17, Some(Version::Normal(1)), ECLevel::L, Palette::Mono)]
+    #[test_case(55, Some(Version::Normal(1)), ECLevel::L, Palette::Poly)]
+    #[test_case(2953, None, ECLevel::L, Palette::Mono)]
+    #[test_case(1273, None, ECLevel::H, Palette::Mono)]
+    #[test_case(8865, None, ECLevel::L, Palette::Poly)]
+    fn test_fits_boundary(
+        max_len: usize,
+        version: Option<Version>,
+        ec_level: ECLevel,
+        palette: Palette,
+    ) {
+        let data = "a".repeat(max_len + 1);
+        let mut qr_builder = QRBuilder::new(&data.as_bytes()[..max_len]);
+        qr_builder.ec_level(ec_level).palette(palette);
+        if let Some(v) = version {
+            qr_builder.version(v);
+        }
+
+        let exp_version = version.unwrap_or(Version::Normal(40));
+        assert_eq!(qr_builder.fits(), Ok(exp_version));
+        assert_eq!(qr_builder.data(data.as_bytes()).fits(), Err(QRError::DataTooLong));
+    }
+
+    #[test]
+    fn test_fits_matches_build() {
+        let data = "Hello, world!🌎 1234567890".repeat(4);
+        let qr_builder = QRBuilder::new(data.as_bytes());
+        let version = qr_builder.fits().unwrap();
+        assert_eq!(qr_builder.build().unwrap().metadata().version(), Some(version));
+    }
+
+    #[test]
+    fn test_analyze_mixed() {
+        let qr_builder = QRBuilder::new(b"0123456789012345HELLO WORLD HELLO WORLDhello, world");
+        let stats = qr_builder.analyze().unwrap();
+        let exp_stats = vec![
+            SegmentStat { mode: Mode::Numeric, char_count: 16, bits: 4 + 10 + 54 },
+            SegmentStat { mode: Mode::Alphanumeric, char_count: 23, bits: 4 + 9 + 127 },
+            SegmentStat { mode: Mode::Byte, char_count: 12, bits: 4 + 8 + 96 },
+        ];
+        assert_eq!(stats, exp_stats);
+
+        let version = qr_builder.fits().unwrap();
+        let total_bits: usize = stats.iter().map(|s| s.bits).sum();
+        assert!(total_bits <= version.bit_capacity(ECLevel::M, Palette::Mono));
+    }
+
+    #[test]
+    fn test_analyze_forced_mode() {
+        let mut qr_builder = QRBuilder::new(b"0123456789012345HELLO");
+        qr_builder.mode(Mode::Byte);
+        let stats = qr_builder.analyze().unwrap();
+        assert_eq!(stats, vec![SegmentStat { mode: Mode::Byte, char_count: 21, bits: 180 }]);
+    }
+
+    #[test]
+    fn test_fits_empty_data() {
+        assert_eq!(QRBuilder::new(b"").fits(), Err(QRError::EmptyData));
+    }
+
+    #[test]
+    fn test_fits_validates_like_build() {
+        let mut qr_builder = QRBuilder::new(b"Hello, world!");
+        qr_builder.channel_ec_levels([ECLevel::L, ECLevel::M, ECLevel::H]).palette(Palette::Mono);
+        assert_eq!(qr_builder.fits(), Err(QRError::InvalidPalette));
+        assert_eq!(qr_builder.build().unwrap_err(), QRError::InvalidPalette);
+
+        let mut qr_builder = QRBuilder::new(b"12345");
+        qr_builder.version(Version::Micro(2)).ec_level(ECLevel::L).mask(MaskPattern::new(5));
+        assert_eq!(qr_builder.fits(), Err(QRError::InvalidMaskingPattern));
+        assert_eq!(qr_builder.build().unwrap_err(), QRError::InvalidMaskingPattern);
+    }
+
+    #[test]
+    fn test_encode_to_bits_byte_mode() {
+        let bits = QRBuilder::new("Hello, world!🌎".as_bytes()).encode_to_bits().unwrap();
+        let mut fields = bits.split(' ');
+        assert_eq!(fields.next(), Some("0100"));
+        assert_eq!(fields.next(), Some("00010001"));
+        assert_eq!(fields.next(), Some("01001000"));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_maximize_ec() {
+        let data = "Hello".as_bytes();
+        let qr = QRBuilder::new(data).ec_level(ECLevel::L).maximize_ec().build().unwrap();
+
+        assert_eq!(qr.version(), Version::Normal(1));
+        assert_eq!(qr.ec_level(), ECLevel::H);
+
+        let mut deqr = DeQR::from_qr(&qr);
+        let (ec_level, _) = deqr.read_format_info().unwrap();
+        assert_eq!(ec_level, ECLevel::H);
+        assert_eq!(QRReader::read(&qr).unwrap(), "Hello");
+    }
+
+    #[cfg(feature = "std")]
+    #[test_case("a".repeat(17), ECLevel::L)]
+    #[test_case("a".repeat(14), ECLevel::M)]
+    #[test_case("a".repeat(11), ECLevel::Q)]
+    fn test_maximize_ec_no_slack(data: String, exp_ec_level: ECLevel) {
+        let qr = QRBuilder::new(data.as_bytes())
+            .version(Version::Normal(1))
+            .ec_level(ECLevel::L)
+            .maximize_ec()
+            .build()
+            .unwrap();
+
+        assert_eq!(qr.ec_level(), exp_ec_level);
+        assert_eq!(QRReader::read(&qr).unwrap(), data);
+    }
+
+    // 200 bytes overflow version 5 at H on every channel, but fit once two channels drop to L
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_channel_ec_levels_capacity() {
+        let data = "a".repeat(200);
+        let mut builder = QRBuilder::new(data.as_bytes());
+        builder.version(Version::Normal(5)).palette(Palette::Poly);
+
+        let res = builder.ec_level(ECLevel::H).build();
+        assert_eq!(res.unwrap_err(), QRError::DataTooLong);
+
+        let ec_levels = [ECLevel::H, ECLevel::L, ECLevel::L];
+        let qr = builder.channel_ec_levels(ec_levels).build().unwrap();
+        assert_eq!(qr.channel_ec_levels(), Some(ec_levels));
+        assert_eq!(qr.ec_level(), ECLevel::H);
+
+        let mut deqr = DeQR::from_qr(&qr);
+        deqr.read_format_info().unwrap();
+        assert_eq!(deqr.channel_ec_levels(), Some(ec_levels));
+        assert_eq!(QRReader::read(&qr).unwrap(), data);
+    }
+
+    #[test]
+    fn test_channel_ec_levels_report() {
+        let ec_levels = [ECLevel::H, ECLevel::L, ECLevel::Q];
+        let (_, report) = QRBuilder::new(b"Hello, world!")
+            .version(Version::Normal(5))
+            .palette(Palette::Poly)
+            .channel_ec_levels(ec_levels)
+            .build_with_report()
+            .unwrap();
+
+        let exp_caps = ec_levels.map(|l| error_correction_capacity(Version::Normal(5), l));
+        assert_eq!(report.channel_err_corr_capacities, exp_caps);
+        assert_eq!(report.err_corr_capacity, exp_caps[1]);
+    }
+
+    #[test]
+    fn test_channel_ec_levels_maximize_ec() {
+        let res = QRBuilder::new(b"Hello")
+            .palette(Palette::Poly)
+            .channel_ec_levels([ECLevel::H, ECLevel::L, ECLevel::L])
+            .maximize_ec()
+            .build();
+        assert_eq!(res.unwrap_err(), QRError::InvalidECLevel);
+    }
+
+    #[test]
+    fn test_channel_ec_levels_mono() {
+        let res = QRBuilder::new(b"Hello")
+            .channel_ec_levels([ECLevel::H, ECLevel::L, ECLevel::L])
+            .build();
+        assert_eq!(res.unwrap_err(), QRError::InvalidPalette);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_colors() {
+        let data = "Hello, world!🌎";
+        let mut colors = PALETTE;
+        colors[0b000] = [250, 240, 230];
+        colors[0b111] = [20, 10, 40];
+        colors[0b011] = [200, 30, 60];
+
+        let qr = QRBuilder::new(data.as_bytes()).palette(Palette::Poly).colors(colors).build();
+        let qr = qr.unwrap();
+        assert_eq!(qr.colors(), colors);
+
+        let img = qr.render_color(2);
+        assert_eq!(img.get_pixel(0, 0).0, colors[0b000]);
+        assert_eq!(img.get_pixel(8, 8).0, colors[0b111]);
+        assert_eq!(QRReader::read_from_rgb(&img, qr.version()).unwrap(), data);
+    }
+
+    #[test]
+    fn test_colors_low_contrast() {
+        // Red reads too dark on the red plane to be told apart from black there
+        let mut colors = PALETTE;
+        colors[0b011] = [100, 0, 0];
+
+        let mut qr_builder = QRBuilder::new(b"Hello");
+        qr_builder.palette(Palette::Poly).colors(colors);
+        assert_eq!(qr_builder.fits(), Err(QRError::InvalidPalette));
+        assert_eq!(qr_builder.build().unwrap_err(), QRError::InvalidPalette);
+
+        // Mono never draws red
+        assert!(qr_builder.palette(Palette::Mono).build().is_ok());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_build_verified() {
+        let data = "Hello, world!🌎";
+        let builder = QRBuilder::from_str(data);
+        let qr = builder.build_verified().unwrap();
+        assert_eq!(QRReader::read(&qr).unwrap(), data);
+
+        // A code for other data decodes fine but doesn't match
+        let other = QRBuilder::new(b"Hello, mars!").build().unwrap();
+        assert_eq!(builder.verify(&other), Err(QRError::RoundTripMismatch));
+
+        // Flipped data modules are beyond what the grid reader recovers
+        let mut tampered = qr.clone();
+        tampered.corrupt_random(4, 7);
+        assert!(builder.verify(&tampered).is_err());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_build_verified_non_utf8() {
+        let data = [0xff, 0x00, 0x80, 0x81];
+        let qr = QRBuilder::new(&data).build_verified().unwrap();
+        assert_eq!(QRReader::read_bytes(&qr).unwrap(), data);
+        assert_eq!(QRReader::read(&qr), Err(QRError::InvalidUTF8Sequence));
+    }
+
+    #[test]
+    fn test_mask_out_of_range_for_micro() {
+        let res = QRBuilder::new(b"12345")
+            .version(Version::Micro(2))
+            .ec_level(ECLevel::L)
+            .mask(MaskPattern::new(5))
+            .build();
+        assert_eq!(res.unwrap_err(), QRError::InvalidMaskingPattern);
+    }
+
+    #[test]
+    fn test_mask_in_range_for_normal() {
+        let qr = QRBuilder::new(b"12345")
+            .version(Version::Normal(1))
+            .mask(MaskPattern::new(5))
+            .build()
+            .unwrap();
+        assert_eq!(qr.mask_pattern(), Some(MaskPattern::new(5)));
+    }
+
+    #[test]
+    fn test_forced_numeric_mode_invalid_char() {
+        let res = QRBuilder::new(b"123A5").mode(Mode::Numeric).build();
+        assert_eq!(res.unwrap_err(), QRError::InvalidChar(3));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_forced_byte_mode() {
+        let data = "12345";
+        let qr = QRBuilder::new(data.as_bytes()).mode(Mode::Byte).build().unwrap();
+        assert_eq!(QRReader::read(&qr).unwrap(), data);
+    }
+
+    #[test]
+    fn test_from_str_ascii_skips_eci() {
+        let bits = QRBuilder::from_str("Hello, world!").encode_to_bits().unwrap();
+        assert!(!bits.starts_with("0111 "), "{bits}");
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_from_str_non_ascii_utf8_eci() {
+        let data = "café";
+        let builder = QRBuilder::from_str(data);
+        let bits = builder.encode_to_bits().unwrap();
+        assert!(bits.starts_with("0111 00011010 0100 "), "{bits}");
+
+        let qr = builder.build().unwrap();
+        assert_eq!(QRReader::read(&qr).unwrap(), data);
+    }
+
+    #[test_case("https://example.com/Some/Path?q=AbC", "HTTPS://EXAMPLE.COM/Some/Path?q=AbC")]
+    #[test_case("http://User:Pw@Example.com:8080#Top", "HTTP://User:Pw@EXAMPLE.COM:8080#Top")]
+    #[test_case("https://example.com?Q=x", "HTTPS://EXAMPLE.COM?Q=x")]
+    #[test_case("example.com/Path", "example.com/Path")]
+    fn test_url_uppercases_scheme_and_host(url: &str, exp_data: &str) {
+        let builder = QRBuilder::url(url);
+        assert_eq!(&*builder.data, exp_data.as_bytes());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_url_smaller_than_byte_mode() {
+        let url = "https://www.example.com/docs/Getting-Started?ref=qr";
+        let (url_qr, url_report) = QRBuilder::url(url).build_with_report().unwrap();
+        let (_, byte_report) =
+            QRBuilder::new(url.as_bytes()).mode(Mode::Byte).build_with_report().unwrap();
+        assert!(
+            url_report.encoded_len < byte_report.encoded_len,
+            "Url {}, Byte {}",
+            url_report.encoded_len,
+            byte_report.encoded_len
+        );
+
+        let decoded = QRReader::read(&url_qr).unwrap();
+        assert_eq!(decoded, "HTTPS://WWW.EXAMPLE.COM/docs/Getting-Started?ref=qr");
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_min_version() {
+        let data = "Hello, world!";
+        let qr = QRBuilder::new(data.as_bytes()).min_version(Version::Normal(5)).build().unwrap();
+        assert_eq!(qr.version(), Version::Normal(5));
+        assert_eq!(QRReader::read(&qr).unwrap(), data);
+
+        // Data needing more than the minimum still picks the smallest version that fits
+        let data = "Hello, world!".repeat(10);
+        let qr = QRBuilder::new(data.as_bytes()).min_version(Version::Normal(2)).build().unwrap();
+        let exp_version = QRBuilder::new(data.as_bytes()).fits().unwrap();
+        assert!(exp_version > Version::Normal(2));
+        assert_eq!(qr.version(), exp_version);
+    }
+
+    #[test]
+    fn test_min_version_data_overflow() {
+        let data = "a".repeat(2400);
+        let res = QRBuilder::new(data.as_bytes()).min_version(Version::Normal(5)).build();
+        assert_eq!(res.unwrap_err(), QRError::DataTooLong);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_max_version() {
+        let data = "Hello, world!".repeat(10);
+        let exp_version = QRBuilder::new(data.as_bytes()).fits().unwrap();
+        assert!(exp_version > Version::Normal(2));
+
+        let qr = QRBuilder::new(data.as_bytes()).max_version(exp_version).build().unwrap();
+        assert_eq!(qr.version(), exp_version);
+        assert_eq!(QRReader::read(&qr).unwrap(), data);
+
+        let res = QRBuilder::new(data.as_bytes()).max_version(Version::Normal(2)).build();
+        assert_eq!(res.unwrap_err(), QRError::DataTooLong);
+
+        // Explicit version takes precedence
+        let qr = QRBuilder::new(data.as_bytes())
+            .max_version(Version::Normal(2))
+            .version(Version::Normal(10))
+            .build()
+            .unwrap();
+        assert_eq!(qr.version(), Version::Normal(10));
+    }
+
+    #[test_case(Version::Normal(5), Version::Normal(2))]
+    #[test_case(Version::Micro(3), Version::Micro(2))]
+    #[test_case(Version::Micro(2), Version::Normal(2))]
+    #[test_case(Version::Normal(1), Version::Micro(4))]
+    fn test_min_max_version_invalid(min: Version, max: Version) {
+        let mut builder = QRBuilder::new(b"Hello");
+        builder.min_version(min).max_version(max);
+        assert_eq!(builder.build().unwrap_err(), QRError::InvalidVersion);
+        assert_eq!(builder.fits().unwrap_err(), QRError::InvalidVersion);
+    }
+
+    #[test_case(Version::Micro(1))]
+    #[test_case(Version::Micro(4))]
+    fn test_max_version_micro(max: Version) {
+        let mut builder = QRBuilder::new(b"1");
+        builder.max_version(max);
+        assert_eq!(builder.build().unwrap_err(), QRError::InvalidVersion);
+        assert_eq!(builder.fits().unwrap_err(), QRError::InvalidVersion);
+    }
+
+    #[test]
+    fn test_min_max_version_equal() {
+        let version = Version::Normal(3);
+        let qr = QRBuilder::new(b"Hello").min_version(version).max_version(version).build();
+        assert_eq!(qr.unwrap().version(), version);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_pad_bytes() {
+        let data = "Hello, world!";
+        let version = Version::Normal(1);
+        let qr = QRBuilder::new(data.as_bytes())
+            .version(version)
+            .ec_level(ECLevel::L)
+            .pad_bytes(0xAA, 0x55)
+            .build()
+            .unwrap();
+
+        // Single block, so the data codewords lead the payload unchanged
+        let data_len = version.data_codewords_per_block(ECLevel::L).0;
+        let codewords = qr.codewords();
+        assert_eq!(codewords[data_len - 4..data_len], [0xAA, 0x55, 0xAA, 0x55]);
+        assert_eq!(QRReader::read(&qr).unwrap(), data);
+    }
+
+    // Data fills all but 2 terminator bits, so the last data codeword can equal a standard pad
+    // codeword and must not be replaced
+    #[cfg(feature = "std")]
+    #[test_case("123")]
+    #[test_case("059")]
+    fn test_pad_bytes_full_data(tail: &str) {
+        let data = format!("123456789012345678901234567890123456789012345{tail}");
+        let qr = QRBuilder::new(data.as_bytes())
+            .version(Version::Normal(2))
+            .ec_level(ECLevel::Q)
+            .pad_bytes(0xAA, 0x55)
+            .build()
+            .unwrap();
+        assert_eq!(QRReader::read(&qr).unwrap(), data);
+    }
+
+    // Numeric payloads leaving 0 to 3 spare bits, where the terminator has to be cut short
+    #[cfg(feature = "std")]
+    #[test_case(34, ECLevel::M, Version::Normal(1), 0)]
+    #[test_case(41, ECLevel::L, Version::Normal(1), 1)]
+    #[test_case(48, ECLevel::Q, Version::Normal(2), 2)]
+    #[test_case(26, ECLevel::Q, Version::Normal(1), 3)]
+    fn test_truncated_terminator(
+        digits: usize,
+        ec_level: ECLevel,
+        exp_version: Version,
+        spare: usize,
+    ) {
+        let data = "1234567890".repeat(5)[..digits].to_string();
+        let mut builder = QRBuilder::new(data.as_bytes());
+        builder.ec_level(ec_level);
+
+        let bits = builder.encode_to_bits().unwrap();
+        let capacity = exp_version.bit_capacity(ec_level, Palette::Mono);
+        let term = bits.split(' ').nth(2 + digits.div_ceil(3)).unwrap_or_default();
+        assert_eq!(term, "0".repeat(spare), "{bits}");
+        assert_eq!(bits.replace(' ', "").len(), capacity);
+
+        let qr = builder.build().unwrap();
+        assert_eq!(qr.version(), exp_version);
+        assert_eq!(QRReader::read(&qr).unwrap(), data);
+    }
+
+    #[test_case(Version::Micro(1), ECLevel::M)]
+    #[test_case(Version::Micro(1), ECLevel::Q)]
+    #[test_case(Version::Micro(1), ECLevel::H)]
+    #[test_case(Version::Micro(2), ECLevel::Q)]
+    #[test_case(Version::Micro(2), ECLevel::H)]
+    #[test_case(Version::Micro(3), ECLevel::Q)]
+    #[test_case(Version::Micro(3), ECLevel::H)]
+    #[test_case(Version::Micro(4), ECLevel::H)]
+    fn test_micro_unsupported_ec_level(version: Version, ec_level: ECLevel) {
+        let res = QRBuilder::new(b"123").version(version).ec_level(ec_level).build();
+        assert_eq!(res.unwrap_err(), QRError::UnsupportedECLevel(version, ec_level));
+    }
+
+    #[test_case(Version::Micro(1), ECLevel::L)]
+    #[test_case(Version::Micro(2), ECLevel::L)]
+    #[test_case(Version::Micro(2), ECLevel::M)]
+    #[test_case(Version::Micro(3), ECLevel::L)]
+    #[test_case(Version::Micro(3), ECLevel::M)]
+    #[test_case(Version::Micro(4), ECLevel::L)]
+    #[test_case(Version::Micro(4), ECLevel::M)]
+    #[test_case(Version::Micro(4), ECLevel::Q)]
+    fn test_micro_supported_ec_level(version: Version, ec_level: ECLevel) {
+        let qr = QRBuilder::new(b"1").version(version).ec_level(ec_level).build().unwrap();
+        assert_eq!(qr.version(), version);
+        assert_eq!(qr.ec_level(), ec_level);
+    }
 }
@@ -1,8 +1,10 @@
 use crate::{
+    bits::BitStream,
     error::{QRError, QRResult},
     metadata::{ECLevel, Palette, Version},
 };
-use std::{
+use alloc::{string::String, vec, vec::Vec};
+use core::{
     cmp::{min, Ordering},
     mem::swap,
 };
@@ -157,18 +159,43 @@ impl Mode {
 
     pub fn encoded_len(&self, len: usize) -> usize {
         match *self {
-            Self::Numeric => (len * 10 + 2) / 3,
-            Self::Alphanumeric => (len * 11 + 1) / 2,
+            Self::Numeric => (len * 10).div_ceil(3),
+            Self::Alphanumeric => (len * 11).div_ceil(2),
             Self::Byte => len * 8,
         }
     }
+
+    // Narrowest mode holding every byte of the run. An empty run fits any, so it's numeric
+    pub fn detect(data: &[u8]) -> Mode {
+        [Self::Numeric, Self::Alphanumeric]
+            .into_iter()
+            .find(|m| data.iter().all(|&b| m.contains(b)))
+            .unwrap_or(Self::Byte)
+    }
+
+    // Indicator value and bit length. Normal versions use the 4 bit values, micro versions number
+    // the modes from 0 in version - 1 bits, so M1 has no indicator
+    pub fn indicator_bits(self, version: Version) -> (u16, usize) {
+        match version {
+            Version::Micro(_) => {
+                let index = match self {
+                    Self::Numeric => 0,
+                    Self::Alphanumeric => 1,
+                    Self::Byte => 2,
+                };
+                (index, version.mode_len())
+            }
+            Version::Normal(_) => (self as u16, 4),
+        }
+    }
 }
 
 #[cfg(test)]
 mod mode_tests {
+    use test_case::test_case;
 
     use super::Mode::*;
-    use crate::codec::Mode;
+    use crate::{codec::Mode, metadata::Version};
 
     #[test]
     fn test_comparison() {
@@ -318,6 +345,39 @@ mod mode_tests {
         assert!(!Alphanumeric.contains(b'('));
     }
 
+    #[test]
+    fn test_alphanumeric_alphabet() {
+        let alphabet = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ $%*+-./:";
+        for (i, &b) in alphabet.iter().enumerate() {
+            assert!(Alphanumeric.contains(b), "{}", b as char);
+            assert_eq!(Mode::alphanumeric_digit(b), i as u16);
+            assert_eq!(Alphanumeric.byte(i as u8), b);
+        }
+        assert_eq!((0..=255).filter(|b| Alphanumeric.contains(*b)).count(), 45);
+    }
+
+    #[test_case(b"", Numeric)]
+    #[test_case(b"0123456789", Numeric)]
+    #[test_case(b"HELLO WORLD", Alphanumeric)]
+    #[test_case(b"123ABC$%*+-./:", Alphanumeric)]
+    #[test_case(b"Hello", Byte)]
+    #[test_case(b"123a", Byte)]
+    #[test_case("🌎".as_bytes(), Byte)]
+    fn test_detect(data: &[u8], exp_mode: Mode) {
+        assert_eq!(Mode::detect(data), exp_mode);
+    }
+
+    #[test_case(Numeric, Version::Normal(1), (0b0001, 4))]
+    #[test_case(Alphanumeric, Version::Normal(10), (0b0010, 4))]
+    #[test_case(Byte, Version::Normal(40), (0b0100, 4))]
+    #[test_case(Numeric, Version::Micro(1), (0, 0))]
+    #[test_case(Alphanumeric, Version::Micro(2), (1, 1))]
+    #[test_case(Byte, Version::Micro(3), (0b10, 2))]
+    #[test_case(Byte, Version::Micro(4), (0b010, 3))]
+    fn test_indicator_bits(mode: Mode, version: Version, exp: (u16, usize)) {
+        assert_eq!(mode.indicator_bits(version), exp);
+    }
+
     #[test]
     fn test_encoded_len() {
         assert_eq!(Numeric.encoded_len(3), 10);
@@ -343,6 +403,14 @@ impl<'a> Segment<'a> {
         Self { mode, data }
     }
 
+    // Fails with the offset of the first byte the mode can't hold
+    pub fn checked(mode: Mode, data: &'a [u8]) -> QRResult<Self> {
+        match data.iter().position(|b| !mode.contains(*b)) {
+            Some(offset) => Err(QRError::InvalidChar(offset)),
+            None => Ok(Self::new(mode, data)),
+        }
+    }
+
     pub fn bit_len(&self, version: Version) -> usize {
         let mode_len = version.mode_len();
         let char_count_len = version.char_count_bit_len(self.mode);
@@ -351,6 +419,20 @@ impl<'a> Segment<'a> {
     }
 }
 
+// Bits a segment takes in the stream, including its mode and char count headers
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub struct SegmentStat {
+    pub mode: Mode,
+    pub char_count: usize,
+    pub bits: usize,
+}
+
+impl From<(&Segment<'_>, Version)> for SegmentStat {
+    fn from((seg, version): (&Segment<'_>, Version)) -> Self {
+        Self { mode: seg.mode, char_count: seg.data.len(), bits: seg.bit_len(version) }
+    }
+}
+
 #[cfg(test)]
 mod segment_tests {
     use super::Segment;
@@ -433,11 +515,11 @@ mod segment_tests {
 
 #[derive(Debug, Clone)]
 struct EncodedBlob {
-    data: Vec<u8>,
-    bit_offset: usize,
+    bits: BitStream,
     version: Version,
     bit_capacity: usize,
     bit_cursor: usize,
+    field_lens: Vec<usize>,
 }
 
 // EncodedBlob methods for encoding
@@ -446,28 +528,46 @@ struct EncodedBlob {
 impl EncodedBlob {
     fn new(version: Version, bit_capacity: usize) -> Self {
         Self {
-            data: Vec::with_capacity((bit_capacity + 7) >> 3),
-            bit_offset: 0,
+            bits: BitStream::new(bit_capacity),
             version,
             bit_capacity,
             bit_cursor: 0,
+            field_lens: Vec::new(),
         }
     }
 
     pub fn bit_len(&self) -> usize {
-        match self.bit_offset {
-            0 => self.data.len() * 8,
-            o => (self.data.len() - 1) * 8 + o,
-        }
+        self.bits.len()
+    }
+
+    fn bit_offset(&self) -> usize {
+        self.bits.len() & 7
     }
 
+    // Micro mode indicators are shorter and count up from 0 for numeric, M1 has none
     fn push_header(&mut self, mode: Mode, char_count: usize) {
-        self.push_bits(4, mode as u16);
+        let (mode_bits, mode_len) = mode.indicator_bits(self.version);
+        self.push_bits(mode_len, mode_bits);
         let char_count_bit_len = self.version.char_count_bit_len(mode);
         debug_assert!(char_count < (1 << char_count_bit_len), "Char count exceeds bit length");
         self.push_bits(char_count_bit_len, char_count as u16);
     }
 
+    // Designator takes 8, 16 or 24 bits, marked by leading 0, 10 or 110
+    fn push_eci(&mut self, eci: u32) {
+        debug_assert!(eci <= MAX_ECI, "Eci designator exceeds 6 digits: {eci}");
+
+        self.push_bits(4, ECI_MODE_BITS);
+        match eci {
+            0..=0x7f => self.push_bits(8, eci as u16),
+            0x80..=0x3fff => self.push_bits(16, 0x8000 | eci as u16),
+            _ => {
+                self.push_bits(8, 0xc0 | (eci >> 16) as u16);
+                self.push_bits(16, eci as u16);
+            }
+        }
+    }
+
     fn push_segment(&mut self, seg: Segment) {
         match seg.mode {
             Mode::Numeric => self.push_numeric_data(seg.data),
@@ -479,7 +579,7 @@ impl EncodedBlob {
     fn push_numeric_data(&mut self, data: &[u8]) {
         self.push_header(Mode::Numeric, data.len());
         for chunk in data.chunks(3) {
-            let len = (chunk.len() * 10 + 2) / 3;
+            let len = (chunk.len() * 10).div_ceil(3);
             let data = Mode::Numeric.encode_chunk(chunk);
             self.push_bits(len, data);
         }
@@ -488,7 +588,7 @@ impl EncodedBlob {
     fn push_alphanumeric_data(&mut self, data: &[u8]) {
         self.push_header(Mode::Alphanumeric, data.len());
         for chunk in data.chunks(2) {
-            let len = (chunk.len() * 11 + 1) / 2;
+            let len = (chunk.len() * 11).div_ceil(2);
             let data = Mode::Alphanumeric.encode_chunk(chunk);
             self.push_bits(len, data);
         }
@@ -505,7 +605,7 @@ impl EncodedBlob {
     pub fn push_terminator(&mut self) {
         let bit_len = self.bit_len();
         if bit_len < self.bit_capacity {
-            let term_len = min(4, self.bit_capacity - bit_len);
+            let term_len = min(self.version.terminator_len(), self.bit_capacity - bit_len);
             self.push_bits(term_len, 0);
         }
     }
@@ -515,26 +615,31 @@ impl EncodedBlob {
         self.push_padding_codewords();
     }
 
+    // Pads to the next codeword, which is the end of capacity for the 4 bit codeword of M1 and M3
     fn push_padding_bits(&mut self) {
-        if self.bit_offset > 0 {
-            let padding_bits_len = 8 - self.bit_offset;
+        if self.bit_offset() > 0 {
+            let padding_bits_len = min(8 - self.bit_offset(), self.bit_capacity - self.bit_len());
             self.push_bits(padding_bits_len, 0);
         }
     }
 
+    // A trailing 4 bit codeword is left as 0s rather than half a padding codeword
     fn push_padding_codewords(&mut self) {
         debug_assert!(
-            self.bit_offset == 0,
+            self.bit_offset() == 0 || self.bit_len() == self.bit_capacity,
             "Bit offset should be zero before padding codewords: {}",
-            self.bit_offset
+            self.bit_offset()
         );
 
-        let remain_byte_capacity = (self.bit_capacity - self.bit_len()) >> 3;
-        PADDING_CODEWORDS.iter().copied().cycle().take(remain_byte_capacity).for_each(|pc| {
+        let remain_capacity = self.bit_capacity - self.bit_len();
+        PADDING_CODEWORDS.iter().copied().cycle().take(remain_capacity >> 3).for_each(|pc| {
             self.push_bits(8, pc as u16);
         });
+        self.push_bits(remain_capacity & 7, 0);
     }
 
+    // Appends the low bit_len bits of bits, most significant first, recording the field width for
+    // to_bit_string
     fn push_bits(&mut self, bit_len: usize, bits: u16) {
         debug_assert!(
             bit_len >= (16 - bits.leading_zeros()) as usize,
@@ -551,23 +656,23 @@ impl EncodedBlob {
             return;
         }
 
-        let shifted_len = self.bit_offset + bit_len;
+        self.field_lens.push(bit_len);
+        self.bits.push_bits(bits as u32, bit_len);
+    }
 
-        if self.bit_offset == 0 {
-            self.data.push(0);
-        }
-        let last = self.data.len() - 1;
-        if shifted_len <= 8 {
-            self.data[last] |= (bits << (8 - shifted_len)) as u8;
-        } else if shifted_len <= 16 {
-            self.data[last] |= (bits >> (shifted_len - 8)) as u8;
-            self.data.push((bits << (16 - shifted_len)) as u8);
-        } else if shifted_len < 24 {
-            self.data[last] |= (bits >> (shifted_len - 8)) as u8;
-            self.data.push((bits >> (shifted_len - 16)) as u8);
-            self.data.push((bits << (24 - shifted_len)) as u8);
+    fn to_bit_string(&self) -> String {
+        let mut res = String::with_capacity(self.bit_len() + self.field_lens.len());
+        let mut i = 0;
+        for len in self.field_lens.iter() {
+            if i > 0 {
+                res.push(' ');
+            }
+            for j in i..i + len {
+                res.push(if self.bits.get(j) { '1' } else { '0' });
+            }
+            i += len;
         }
-        self.bit_offset = shifted_len & 7;
+        res
     }
 }
 
@@ -608,32 +713,32 @@ mod encoded_blob_encode_tests {
         let bit_capacity = version.bit_capacity(ec_level, palette);
         let mut eb = EncodedBlob::new(version, bit_capacity);
         eb.push_bits(0, 0);
-        assert_eq!(eb.data, vec![]);
+        assert_eq!(eb.bits.data(), vec![]);
         eb.push_bits(4, 0b1101);
-        assert_eq!(eb.data, vec![0b11010000]);
+        assert_eq!(eb.bits.data(), vec![0b11010000]);
         eb.push_bits(4, 0b0010);
-        assert_eq!(eb.data, vec![0b11010010]);
+        assert_eq!(eb.bits.data(), vec![0b11010010]);
         eb.push_bits(8, 0b00110100);
-        assert_eq!(eb.data, vec![0b11010010, 0b00110100]);
+        assert_eq!(eb.bits.data(), vec![0b11010010, 0b00110100]);
         eb.push_bits(9, 0b100011010);
-        assert_eq!(eb.data, vec![0b11010010, 0b00110100, 0b10001101, 0b00000000]);
+        assert_eq!(eb.bits.data(), vec![0b11010010, 0b00110100, 0b10001101, 0b00000000]);
         eb.push_bits(7, 0b0100011);
-        assert_eq!(eb.data, vec![0b11010010, 0b00110100, 0b10001101, 0b00100011]);
+        assert_eq!(eb.bits.data(), vec![0b11010010, 0b00110100, 0b10001101, 0b00100011]);
         eb.push_bits(16, 0b01001000_11010010);
         assert_eq!(
-            eb.data,
+            eb.bits.data(),
             vec![0b11010010, 0b00110100, 0b10001101, 0b00100011, 0b01001000, 0b11010010]
         );
         eb.push_bits(1, 0b0);
         assert_eq!(
-            eb.data,
+            eb.bits.data(),
             vec![
                 0b11010010, 0b00110100, 0b10001101, 0b00100011, 0b01001000, 0b11010010, 0b00000000
             ]
         );
         eb.push_bits(11, 0b01101001000);
         assert_eq!(
-            eb.data,
+            eb.bits.data(),
             vec![
                 0b11010010, 0b00110100, 0b10001101, 0b00100011, 0b01001000, 0b11010010, 0b00110100,
                 0b10000000
@@ -641,7 +746,7 @@ mod encoded_blob_encode_tests {
         );
         eb.push_bits(14, 0b11010010001101);
         assert_eq!(
-            eb.data,
+            eb.bits.data(),
             vec![
                 0b11010010, 0b00110100, 0b10001101, 0b00100011, 0b01001000, 0b11010010, 0b00110100,
                 0b10001101, 0b00100011, 0b01000000
@@ -649,7 +754,7 @@ mod encoded_blob_encode_tests {
         );
         eb.push_bits(16, 0b0010001101001000);
         assert_eq!(
-            eb.data,
+            eb.bits.data(),
             vec![
                 0b11010010, 0b00110100, 0b10001101, 0b00100011, 0b01001000, 0b11010010, 0b00110100,
                 0b10001101, 0b00100011, 0b01001000, 0b11010010, 0b00000000
@@ -680,11 +785,14 @@ mod encoded_blob_encode_tests {
         let bit_capacity = version.bit_capacity(ec_level, palette);
         let mut eb = EncodedBlob::new(version, bit_capacity);
         eb.push_header(Mode::Numeric, 0b11_1111_1111);
-        assert_eq!(eb.data, vec![0b00011111, 0b11111100]);
+        assert_eq!(eb.bits.data(), vec![0b00011111, 0b11111100]);
         eb.push_header(Mode::Alphanumeric, 0b1_1111_1111);
-        assert_eq!(eb.data, vec![0b00011111, 0b11111100, 0b10111111, 0b11100000]);
+        assert_eq!(eb.bits.data(), vec![0b00011111, 0b11111100, 0b10111111, 0b11100000]);
         eb.push_header(Mode::Byte, 0b11111111);
-        assert_eq!(eb.data, vec![0b00011111, 0b11111100, 0b10111111, 0b11101001, 0b11111110]);
+        assert_eq!(
+            eb.bits.data(),
+            vec![0b00011111, 0b11111100, 0b10111111, 0b11101001, 0b11111110]
+        );
     }
 
     #[test]
@@ -695,12 +803,12 @@ mod encoded_blob_encode_tests {
         let bit_capacity = version.bit_capacity(ec_level, palette);
         let mut eb = EncodedBlob::new(version, bit_capacity);
         eb.push_header(Mode::Numeric, 0b1111_1111_1111);
-        assert_eq!(eb.data, vec![0b00011111, 0b11111111]);
+        assert_eq!(eb.bits.data(), vec![0b00011111, 0b11111111]);
         eb.push_header(Mode::Alphanumeric, 0b111_1111_1111);
-        assert_eq!(eb.data, vec![0b00011111, 0b11111111, 0b00101111, 0b11111110]);
+        assert_eq!(eb.bits.data(), vec![0b00011111, 0b11111111, 0b00101111, 0b11111110]);
         eb.push_header(Mode::Byte, 0b11111111_11111111);
         assert_eq!(
-            eb.data,
+            eb.bits.data(),
             vec![
                 0b00011111, 0b11111111, 0b00101111, 0b11111110, 0b10011111, 0b11111111, 0b11100000
             ]
@@ -715,12 +823,15 @@ mod encoded_blob_encode_tests {
         let bit_capacity = version.bit_capacity(ec_level, palette);
         let mut eb = EncodedBlob::new(version, bit_capacity);
         eb.push_header(Mode::Numeric, 0b11_1111_1111_1111);
-        assert_eq!(eb.data, vec![0b00011111, 0b11111111, 0b11000000]);
+        assert_eq!(eb.bits.data(), vec![0b00011111, 0b11111111, 0b11000000]);
         eb.push_header(Mode::Alphanumeric, 0b1_1111_1111_1111);
-        assert_eq!(eb.data, vec![0b00011111, 0b11111111, 0b11001011, 0b11111111, 0b11100000]);
+        assert_eq!(
+            eb.bits.data(),
+            vec![0b00011111, 0b11111111, 0b11001011, 0b11111111, 0b11100000]
+        );
         eb.push_header(Mode::Byte, 0b11111111_11111111);
         assert_eq!(
-            eb.data,
+            eb.bits.data(),
             vec![
                 0b00011111, 0b11111111, 0b11001011, 0b11111111, 0b11101001, 0b11111111, 0b11111110
             ]
@@ -736,12 +847,12 @@ mod encoded_blob_encode_tests {
         let mut eb = EncodedBlob::new(version, bit_capacity);
         eb.push_numeric_data("01234567".as_bytes());
         assert_eq!(
-            eb.data,
+            eb.bits.data(),
             vec![0b00010000, 0b00100000, 0b00001100, 0b01010110, 0b01100001, 0b10000000]
         );
         let mut eb = EncodedBlob::new(version, bit_capacity);
         eb.push_numeric_data("8".as_bytes());
-        assert_eq!(eb.data, vec![0b00010000, 0b00000110, 0b00]);
+        assert_eq!(eb.bits.data(), vec![0b00010000, 0b00000110, 0b00]);
     }
 
     #[test]
@@ -753,7 +864,7 @@ mod encoded_blob_encode_tests {
         let mut eb = EncodedBlob::new(version, bit_capacity);
         eb.push_alphanumeric_data("AC-42".as_bytes());
         assert_eq!(
-            eb.data,
+            eb.bits.data(),
             vec![0b00100000, 0b00101001, 0b11001110, 0b11100111, 0b00100001, 0b00000000]
         )
     }
@@ -766,7 +877,7 @@ mod encoded_blob_encode_tests {
         let bit_capacity = version.bit_capacity(ec_level, palette);
         let mut eb = EncodedBlob::new(version, bit_capacity);
         eb.push_byte_data("a".as_bytes());
-        assert_eq!(eb.data, vec![0b01000000, 0b00010110, 0b00010000])
+        assert_eq!(eb.bits.data(), vec![0b01000000, 0b00010110, 0b00010000])
     }
 
     #[test]
@@ -779,13 +890,13 @@ mod encoded_blob_encode_tests {
         let mut eb = EncodedBlob::new(version, bit_capacity);
         eb.push_bits(1, 0b1);
         eb.push_terminator();
-        assert_eq!(eb.data, vec![0b10000000]);
-        assert_eq!(eb.bit_offset, 5);
+        assert_eq!(eb.bits.data(), vec![0b10000000]);
+        assert_eq!(eb.bit_offset(), 5);
         for _ in 0..capacity - 1 {
             eb.push_bits(8, 0b11111111);
         }
         eb.push_terminator();
-        assert_eq!(eb.bit_offset, 0);
+        assert_eq!(eb.bit_offset(), 0);
     }
 
     #[test]
@@ -797,8 +908,8 @@ mod encoded_blob_encode_tests {
         let mut eb = EncodedBlob::new(version, bit_capacity);
         eb.push_bits(1, 0b1);
         eb.push_padding_bits();
-        assert_eq!(eb.data, vec![0b10000000]);
-        assert_eq!(eb.bit_offset, 0);
+        assert_eq!(eb.bits.data(), vec![0b10000000]);
+        assert_eq!(eb.bit_offset(), 0);
     }
 
     #[test]
@@ -813,7 +924,29 @@ mod encoded_blob_encode_tests {
         eb.push_padding_codewords();
         let mut output = vec![0b10000000];
         output.extend(PADDING_CODEWORDS.iter().cycle().take(18));
-        assert_eq!(eb.data, output);
+        assert_eq!(eb.bits.data(), output);
+    }
+
+    // No mode indicator, a 3 bit count and digits filling all 20 bits, so there's no terminator
+    // and the last codeword is the 4 bit one in the high nibble
+    #[test]
+    fn test_encode_micro_1() {
+        let (encoded, _, _, _) =
+            super::encode_with_version(b"12345", ECLevel::L, Version::Micro(1), Palette::Mono)
+                .unwrap();
+        assert_eq!(encoded, vec![0b1010_0011, 0b1101_1010, 0b1101_0000]);
+    }
+
+    // 2 bit mode, 5 bit count, 4 bit digit and a 7 bit terminator, then padding codewords up to
+    // the 4 bit codeword which stays 0
+    #[test]
+    fn test_encode_micro_3() {
+        let (encoded, _, _, _) =
+            super::encode_with_version(b"1", ECLevel::L, Version::Micro(3), Palette::Mono).unwrap();
+        let mut exp = vec![0b0000_0010, 0b0010_0000, 0b0000_0000];
+        exp.extend(PADDING_CODEWORDS.iter().cycle().take(7));
+        exp.push(0);
+        assert_eq!(encoded, exp);
     }
 }
 
@@ -825,17 +958,11 @@ pub fn encode(
     data: &[u8],
     ec_level: ECLevel,
     palette: Palette,
-) -> QRResult<(Vec<u8>, usize, Version)> {
-    let (version, segments) = find_optimal_version_and_segments(data, ec_level, palette)?;
-    let bit_capacity = version.bit_capacity(ec_level, palette);
-    let mut encoded_blob = EncodedBlob::new(version, bit_capacity);
-    for seg in segments {
-        encoded_blob.push_segment(seg);
-    }
-    let encoded_len = (encoded_blob.bit_len() + 7) >> 3;
-    encoded_blob.push_terminator();
-    encoded_blob.pad_remaining_capacity();
-    Ok((encoded_blob.data, encoded_len, encoded_blob.version))
+) -> QRResult<(Vec<u8>, usize, usize, Version)> {
+    let capacity = |v: Version| v.bit_capacity(ec_level, palette);
+    let (version, segments) = find_optimal_version_and_segments(data, capacity, 0)?;
+    let (eb, encoded_len, pad_start) = encode_segments(segments, version, capacity, None);
+    Ok((eb.bits.into_data(), encoded_len, pad_start, version))
 }
 
 // TODO: Write testcases
@@ -844,34 +971,197 @@ pub fn encode_with_version(
     ec_level: ECLevel,
     version: Version,
     palette: Palette,
-) -> QRResult<(Vec<u8>, usize, Version)> {
-    let capacity = version.bit_capacity(ec_level, palette);
+) -> QRResult<(Vec<u8>, usize, usize, Version)> {
+    let capacity = |v: Version| v.bit_capacity(ec_level, palette);
+    let segments = fit_segments(data, capacity, version, 0)?;
+    let (eb, encoded_len, pad_start) = encode_segments(segments, version, capacity, None);
+    Ok((eb.bits.into_data(), encoded_len, pad_start, version))
+}
+
+// Encodes like encode or encode_with_version, but returns the bitstream before ecc as a string
+// with fields (mode, char count, data chunks, terminator, padding) separated by spaces
+pub fn encode_to_bits(
+    data: &[u8],
+    ec_level: ECLevel,
+    version: Option<Version>,
+    palette: Palette,
+    mode: Option<Mode>,
+    eci: Option<u32>,
+) -> QRResult<String> {
+    let capacity = |v: Version| v.bit_capacity(ec_level, palette);
+    let (version, segments) = select_segments(data, capacity, version, mode, eci)?;
+    let (eb, _, _) = encode_segments(segments, version, capacity, eci);
+    Ok(eb.to_bit_string())
+}
+
+// Segments the data would be encoded as at the given version. The eci header, terminator and
+// padding belong to no segment and are left out
+pub fn analyze_segments(
+    data: &[u8],
+    ec_level: ECLevel,
+    version: Version,
+    palette: Palette,
+    mode: Option<Mode>,
+    eci: Option<u32>,
+) -> QRResult<Vec<SegmentStat>> {
+    let capacity = |v: Version| v.bit_capacity(ec_level, palette);
+    let (version, segments) = select_segments(data, capacity, Some(version), mode, eci)?;
+    Ok(segments.iter().map(|s| SegmentStat::from((s, version))).collect())
+}
+
+// Forces all data into a single segment of the given mode instead of optimal segmentation
+pub fn encode_with_mode(
+    data: &[u8],
+    ec_level: ECLevel,
+    version: Option<Version>,
+    palette: Palette,
+    mode: Mode,
+) -> QRResult<(Vec<u8>, usize, usize, Version)> {
+    let capacity = |v: Version| v.bit_capacity(ec_level, palette);
+    let (version, segments) = select_segments(data, capacity, version, Some(mode), None)?;
+    let (eb, encoded_len, pad_start) = encode_segments(segments, version, capacity, None);
+    Ok((eb.bits.into_data(), encoded_len, pad_start, version))
+}
+
+// Prefixes the stream with an ECI header telling readers how to interpret byte segments, e.g.
+// UTF8_ECI. Micro versions have no ECI mode
+pub fn encode_with_eci(
+    data: &[u8],
+    ec_level: ECLevel,
+    version: Option<Version>,
+    palette: Palette,
+    mode: Option<Mode>,
+    eci: u32,
+) -> QRResult<(Vec<u8>, usize, usize, Version)> {
+    let capacity = |v: Version| v.bit_capacity(ec_level, palette);
+    let (version, segments) = select_segments(data, capacity, version, mode, Some(eci))?;
+    let (eb, encoded_len, pad_start) = encode_segments(segments, version, capacity, Some(eci));
+    Ok((eb.bits.into_data(), encoded_len, pad_start, version))
+}
+
+// Poly code whose channels each carry their own ec level, so capacity is the sum of each
+// channel's mono capacity. Data is laid out as one stream split across channels in order
+pub fn encode_with_channel_ec_levels(
+    data: &[u8],
+    ec_levels: [ECLevel; 3],
+    version: Option<Version>,
+    mode: Option<Mode>,
+    eci: Option<u32>,
+) -> QRResult<(Vec<u8>, usize, usize, Version)> {
+    let capacity = |v: Version| ec_levels.iter().map(|&l| v.bit_capacity(l, Palette::Mono)).sum();
+    let (version, segments) = select_segments(data, capacity, version, mode, eci)?;
+    let (eb, encoded_len, pad_start) = encode_segments(segments, version, capacity, eci);
+    Ok((eb.bits.into_data(), encoded_len, pad_start, version))
+}
+
+// Swaps the standard pad codewords ending an encoded stream for another pair. Pad start is the
+// byte the encoder began padding at, since data can itself end in the standard pair. A trailing 4
+// bit codeword of M1 or M3 is left as 0s, as the encoder does
+pub fn replace_padding_codewords(
+    data: &mut [u8],
+    pad_start: usize,
+    pad: [u8; 2],
+    version: Version,
+) {
+    let pad_end = match version {
+        Version::Micro(1) | Version::Micro(3) => data.len() - 1,
+        _ => data.len(),
+    };
+    data[pad_start.min(pad_end)..pad_end]
+        .iter_mut()
+        .zip(pad.iter().cycle())
+        .for_each(|(d, p)| *d = *p);
+}
+
+// Capacity maps a version to the data bits it holds at the requested ec level(s) and palette
+fn select_segments<C: Fn(Version) -> usize + Copy>(
+    data: &[u8],
+    capacity: C,
+    version: Option<Version>,
+    mode: Option<Mode>,
+    eci: Option<u32>,
+) -> QRResult<(Version, Vec<Segment<'_>>)> {
+    if eci.is_some() && matches!(version, Some(Version::Micro(_))) {
+        return Err(QRError::InvalidVersion);
+    }
+    if eci.is_some_and(|e| e > MAX_ECI) {
+        return Err(QRError::InvalidEci);
+    }
+    let header_len = eci.map_or(0, eci_header_bit_len);
+
+    let Some(mode) = mode else {
+        return match version {
+            Some(v) => Ok((v, fit_segments(data, capacity, v, header_len)?)),
+            None => find_optimal_version_and_segments(data, capacity, header_len),
+        };
+    };
+
+    let seg = Segment::checked(mode, data)?;
+    let fits = |v: Version| header_len + seg.bit_len(v) <= capacity(v);
+    let version = match version {
+        Some(v) if fits(v) => v,
+        Some(_) => return Err(QRError::DataTooLong),
+        None => (1..=40).map(Version::Normal).find(|v| fits(*v)).ok_or(QRError::DataTooLong)?,
+    };
+    Ok((version, vec![seg]))
+}
+
+// Mode indicator and the 1, 2 or 3 byte designator
+fn eci_header_bit_len(eci: u32) -> usize {
+    match eci {
+        0..=0x7f => 12,
+        0x80..=0x3fff => 20,
+        _ => 28,
+    }
+}
+
+fn fit_segments<C: Fn(Version) -> usize>(
+    data: &[u8],
+    capacity: C,
+    version: Version,
+    header_len: usize,
+) -> QRResult<Vec<Segment<'_>>> {
+    let capacity = capacity(version) - header_len;
     let segments = compute_optimal_segments(data, version);
     let size: usize = segments.iter().map(|s| s.bit_len(version)).sum();
     if size > capacity {
         return Err(QRError::DataTooLong);
     }
-    let bit_capacity = version.bit_capacity(ec_level, palette);
+    Ok(segments)
+}
+
+// Returns the padded blob and the byte length of the data before terminator & padding
+fn encode_segments<C: Fn(Version) -> usize>(
+    segments: Vec<Segment>,
+    version: Version,
+    capacity: C,
+    eci: Option<u32>,
+) -> (EncodedBlob, usize, usize) {
+    let bit_capacity = capacity(version);
     let mut eb = EncodedBlob::new(version, bit_capacity);
+    if let Some(eci) = eci {
+        eb.push_eci(eci);
+    }
     for seg in segments {
         eb.push_segment(seg);
     }
     let encoded_len = (eb.bit_len() + 7) >> 3;
     eb.push_terminator();
+    let pad_start = (eb.bit_len() + 7) >> 3;
     eb.pad_remaining_capacity();
-    Ok((eb.data, encoded_len, eb.version))
+    (eb, encoded_len, pad_start)
 }
 
-fn find_optimal_version_and_segments(
+fn find_optimal_version_and_segments<C: Fn(Version) -> usize>(
     data: &[u8],
-    ec_level: ECLevel,
-    palette: Palette,
-) -> QRResult<(Version, Vec<Segment>)> {
+    capacity: C,
+    header_len: usize,
+) -> QRResult<(Version, Vec<Segment<'_>>)> {
     let mut segments = vec![];
     let mut size = 0;
     for v in 1..=40 {
         let version = Version::Normal(v);
-        let capacity = version.bit_capacity(ec_level, palette);
+        let capacity = capacity(version) - header_len;
         if v == 1 || v == 10 || v == 27 {
             segments = compute_optimal_segments(data, version);
             size = segments.iter().map(|s| s.bit_len(version)).sum();
@@ -884,7 +1174,7 @@ fn find_optimal_version_and_segments(
 }
 
 // Dynamic programming to compute optimum mode segments
-fn compute_optimal_segments(data: &[u8], version: Version) -> Vec<Segment> {
+fn compute_optimal_segments(data: &[u8], version: Version) -> Vec<Segment<'_>> {
     debug_assert!(!data.is_empty(), "Empty data");
 
     let len = data.len();
@@ -911,7 +1201,7 @@ fn compute_optimal_segments(data: &[u8], version: Version) -> Vec<Segment> {
                 }
                 let mut cost = 0;
                 if to_mode != from_mode {
-                    cost += (prev_cost[k] + 5) / 6 * 6;
+                    cost += prev_cost[k].div_ceil(6) * 6;
                     cost += (4 + version.char_count_bit_len(*to_mode)) * 6;
                 } else {
                     cost += prev_cost[k];
@@ -955,7 +1245,7 @@ fn trace_optimal_modes(min_path: Vec<Vec<usize>>, prev_cost: [usize; 3]) -> Vec<
 }
 
 // Build segments encode char modes
-fn build_segments(char_modes: Vec<Mode>, data: &[u8]) -> Vec<Segment> {
+fn build_segments(char_modes: Vec<Mode>, data: &[u8]) -> Vec<Segment<'_>> {
     let len = data.len();
     let mut segs: Vec<Segment> = vec![];
     let mut seg_start = 0;
@@ -976,9 +1266,12 @@ fn build_segments(char_modes: Vec<Mode>, data: &[u8]) -> Vec<Segment> {
 mod encode_tests {
     use test_case::test_case;
 
-    use super::{compute_optimal_segments, find_optimal_version_and_segments, Mode, Segment};
+    use super::{
+        compute_optimal_segments, encode_to_bits, find_optimal_version_and_segments, Mode, Segment,
+    };
     use crate::{
         codec::build_segments,
+        error::QRError,
         metadata::{ECLevel, Palette, Version},
     };
 
@@ -989,9 +1282,9 @@ mod encode_tests {
         char_modes.extend([Mode::Numeric; 5]);
         char_modes.extend([Mode::Byte; 3]);
         let segs = build_segments(char_modes, data.as_bytes());
-        let seg_1 = Segment::new(Mode::Alphanumeric, data[0..5].as_bytes());
-        let seg_2 = Segment::new(Mode::Numeric, data[5..10].as_bytes());
-        let seg_3 = Segment::new(Mode::Byte, data[10..].as_bytes());
+        let seg_1 = Segment::new(Mode::Alphanumeric, &data.as_bytes()[0..5]);
+        let seg_2 = Segment::new(Mode::Numeric, &data.as_bytes()[5..10]);
+        let seg_3 = Segment::new(Mode::Byte, &data.as_bytes()[10..]);
         assert_eq!(segs.len(), 3);
         assert_eq!(segs[0], seg_1);
         assert_eq!(segs[1], seg_2);
@@ -1019,8 +1312,8 @@ mod encode_tests {
         assert_eq!(segs.len(), chunks.len());
         for (seg, &(mode, start, end)) in segs.iter().zip(chunks.iter()) {
             let exp_seg = match end {
-                Some(e) => Segment::new(mode, data[start..e].as_bytes()),
-                None => Segment::new(mode, data[start..].as_bytes()),
+                Some(e) => Segment::new(mode, &data.as_bytes()[start..e]),
+                None => Segment::new(mode, &data.as_bytes()[start..]),
             };
             assert_eq!(*seg, exp_seg);
         }
@@ -1052,9 +1345,76 @@ mod encode_tests {
         ec_level: ECLevel,
         palette: Palette,
     ) {
-        let (version, _) =
-            find_optimal_version_and_segments(data.as_bytes(), ec_level, palette).unwrap();
+        let (version, _) = find_optimal_version_and_segments(
+            data.as_bytes(),
+            |v| v.bit_capacity(ec_level, palette),
+            0,
+        )
+        .unwrap();
+        assert_eq!(version, exp_version);
+    }
+
+    #[test]
+    fn test_encode_to_bits() {
+        let version = Some(Version::Normal(1));
+        let bits =
+            encode_to_bits(b"01234567", ECLevel::M, version, Palette::Mono, None, None).unwrap();
+        let exp_bits = "0001 0000001000 0000001100 0101011001 1000011 0000 000 ".to_string()
+            + &["11101100", "00010001"].repeat(5).join(" ");
+        assert_eq!(bits, exp_bits);
+    }
+
+    #[test_case("a".to_string() + &"1".repeat(30), Version::Normal(1), ECLevel::L)]
+    #[test_case("🌎".to_string() + &"1234567890".repeat(3), Version::Normal(2), ECLevel::L)]
+    fn test_optimal_segments_beat_single_byte_segment(
+        data: String,
+        exp_version: Version,
+        ec_level: ECLevel,
+    ) {
+        let palette = Palette::Mono;
+        let (version, segs) = find_optimal_version_and_segments(
+            data.as_bytes(),
+            |v| v.bit_capacity(ec_level, palette),
+            0,
+        )
+        .unwrap();
         assert_eq!(version, exp_version);
+        assert!(segs.len() > 1);
+
+        let byte_seg = Segment::new(Mode::Byte, data.as_bytes());
+        let byte_version = (1..=40)
+            .map(Version::Normal)
+            .find(|v| byte_seg.bit_len(*v) <= v.bit_capacity(ec_level, palette))
+            .unwrap();
+        assert_eq!(*byte_version, *version + 1);
+    }
+
+    #[test]
+    fn test_alphanumeric_alphabet_bits() {
+        let alphabet = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ $%*+-./:";
+        let version = Some(Version::Normal(3));
+        let mode = Some(Mode::Alphanumeric);
+        let bits =
+            encode_to_bits(alphabet, ECLevel::L, version, Palette::Mono, mode, None).unwrap();
+        let field_lens = bits.split(' ').map(|f| f.len()).collect::<Vec<_>>();
+
+        // Mode and char count, then 22 base 45 pairs in 11 bits and the odd char in 6 bits
+        assert_eq!(&field_lens[..2], &[4, 9]);
+        assert_eq!(&field_lens[2..24], &[11; 22]);
+        assert_eq!(field_lens[24], 6);
+        assert_eq!(&bits[..14], "0010 000101101");
+    }
+
+    #[test]
+    fn test_non_alphanumeric_char_forces_byte_mode() {
+        let data = b"HELLO;WORLD";
+        let segs = compute_optimal_segments(data, Version::Normal(1));
+        assert!(segs.iter().all(|s| s.mode == Mode::Byte || !s.data.contains(&b';')));
+        assert!(segs.iter().any(|s| s.mode == Mode::Byte));
+
+        let mode = Some(Mode::Alphanumeric);
+        let res = encode_to_bits(data, ECLevel::L, None, Palette::Mono, mode, None);
+        assert_eq!(res.unwrap_err(), QRError::InvalidChar(5));
     }
 
     #[test]
@@ -1063,7 +1423,12 @@ mod encode_tests {
         let data = "a".repeat(2954);
         let ec_level = ECLevel::L;
         let palette = Palette::Mono;
-        find_optimal_version_and_segments(data.as_bytes(), ec_level, palette).unwrap();
+        find_optimal_version_and_segments(
+            data.as_bytes(),
+            |v| v.bit_capacity(ec_level, palette),
+            0,
+        )
+        .unwrap();
     }
 }
 
@@ -1072,27 +1437,50 @@ mod encode_tests {
 
 impl EncodedBlob {
     fn from_data(data: Vec<u8>, version: Version) -> Self {
-        let bit_capacity = data.len() * 8;
-        Self { data, bit_offset: 0, version, bit_capacity, bit_cursor: 0 }
+        let bits = BitStream::from(data);
+        let bit_capacity = bits.len();
+        Self { bits, version, bit_capacity, bit_cursor: 0, field_lens: Vec::new() }
     }
 
-    fn take_segment(&mut self) -> Option<Vec<u8>> {
+    fn take_segment(&mut self) -> Option<(Mode, Vec<u8>)> {
         let (mode, char_count) = self.take_header()?;
         let byte_data = match mode {
             Mode::Numeric => self.take_numeric_data(char_count),
             Mode::Alphanumeric => self.take_alphanumeric_data(char_count),
             Mode::Byte => self.take_byte_data(char_count),
         };
-        Some(byte_data)
+        Some((mode, byte_data))
     }
 
     fn take_header(&mut self) -> Option<(Mode, usize)> {
-        let mode_bits = self.take_bits(4);
-        let mode = match mode_bits {
-            0 => return None,
-            1 => Mode::Numeric,
-            2 => Mode::Alphanumeric,
-            4 => Mode::Byte,
+        // Data filling the symbol exactly cuts the terminator short, or leaves no room for it
+        let term_len = self.version.terminator_len();
+        if self.bit_capacity - self.bit_cursor < term_len {
+            return None;
+        }
+
+        // Micro terminators are longer than the mode indicator, so they're peeked in full
+        if let Version::Micro(_) = self.version {
+            let cursor = self.bit_cursor;
+            if self.take_bits(term_len) == 0 {
+                return None;
+            }
+            self.bit_cursor = cursor;
+        }
+
+        let mode_bits = self.take_bits(self.version.mode_len());
+        let mode = match (self.version, mode_bits) {
+            (Version::Micro(_), 0) => Mode::Numeric,
+            (Version::Micro(_), 1) => Mode::Alphanumeric,
+            (Version::Micro(_), 2) => Mode::Byte,
+            (_, 0) => return None,
+            (_, ECI_MODE_BITS) => {
+                self.take_eci();
+                return self.take_header();
+            }
+            (_, 1) => Mode::Numeric,
+            (_, 2) => Mode::Alphanumeric,
+            (_, 4) => Mode::Byte,
             _ => unreachable!("Invalid Mode: {mode_bits}"),
         };
         let char_count_bit_len = self.version.char_count_bit_len(mode);
@@ -1100,6 +1488,17 @@ impl EncodedBlob {
         Some((mode, char_count.into()))
     }
 
+    // Designator is 8, 16 or 24 bits, marked by leading 0, 10 or 110. Data is read as utf8
+    // regardless, so the designator is dropped
+    fn take_eci(&mut self) -> u32 {
+        let first = self.take_bits(8) as u32;
+        match (first as u8).leading_ones() {
+            0 => first,
+            1 => ((first & 0x3f) << 8) | self.take_bits(8) as u32,
+            _ => ((first & 0x1f) << 16) | self.take_bits(16) as u32,
+        }
+    }
+
     fn take_numeric_data(&mut self, mut char_count: usize) -> Vec<u8> {
         let mut res = Vec::with_capacity(char_count);
         while char_count > 0 {
@@ -1135,6 +1534,7 @@ impl EncodedBlob {
         res
     }
 
+    // Mirror of push_bits, reads bit_len bits most significant first into the low bits of the result
     fn take_bits(&mut self, bit_len: usize) -> u16 {
         let remaining_bits = self.bit_capacity - self.bit_cursor;
         debug_assert!(
@@ -1142,32 +1542,17 @@ impl EncodedBlob {
             "Insufficient bits to take: Remaining bits {remaining_bits}, Bit len {bit_len}",
         );
 
-        let index = self.bit_cursor >> 3;
-        let offset = self.bit_cursor & 7;
-        let shifted_len = offset + bit_len;
-        let mut res = if index < self.data.len() {
-            ((self.data[index] << offset) >> offset) as u16
-        } else {
-            0
-        };
-        if shifted_len <= 8 {
-            res >>= 8 - shifted_len;
-        } else if shifted_len <= 16 && index + 1 < self.data.len() {
-            res <<= shifted_len - 8;
-            res |= (self.data[index + 1] >> (16 - shifted_len)) as u16;
-        } else if index + 2 < self.data.len() {
-            res <<= 8;
-            res |= self.data[index + 1] as u16;
-            res <<= shifted_len - 16;
-            res |= (self.data[index + 2] >> (24 - shifted_len)) as u16;
-        };
-        self.bit_cursor += min(bit_len, remaining_bits);
-        res
+        let bit_len = min(bit_len, remaining_bits);
+        let res = self.bits.get_bits(self.bit_cursor, bit_len);
+        self.bit_cursor += bit_len;
+        res as u16
     }
 }
 
 #[cfg(test)]
 mod encoded_blob_decode_tests {
+    use test_case::test_case;
+
     use crate::{
         codec::{encode_with_version, EncodedBlob, Mode},
         metadata::{ECLevel, Palette, Version},
@@ -1209,6 +1594,48 @@ mod encoded_blob_decode_tests {
         assert_eq!(bits, 0b0010);
     }
 
+    #[test]
+    fn test_push_take_bits_round_trip() {
+        let version = Version::Normal(1);
+        for offset in 0..8 {
+            for bit_len in 1..=16 {
+                let bits = 0b1011_0110_1100_1011_u16 >> (16 - bit_len);
+                let mut eb = EncodedBlob::new(version, 40);
+                eb.push_bits(offset, 0);
+                eb.push_bits(bit_len, bits);
+
+                let mut eb = EncodedBlob::from_data(eb.bits.into_data(), version);
+                assert_eq!(eb.take_bits(offset), 0);
+                assert_eq!(eb.take_bits(bit_len), bits, "Offset {offset}, Len {bit_len}");
+            }
+        }
+    }
+
+    // Mode and count indicator widths packed back to back, so every field after the first
+    // starts mid byte and the 13 bit ones span three bytes
+    #[test]
+    fn test_push_take_mixed_width_fields() {
+        let version = Version::Normal(1);
+        let fields = [
+            (4, 0b1010),
+            (9, 0b1_0110_0101),
+            (13, 0b1_1100_1010_0111),
+            (4, 0b0111),
+            (13, 0b0_0110_1101_1001),
+            (9, 0b0_1111_0001),
+        ];
+        let mut eb = EncodedBlob::new(version, 56);
+        for &(len, bits) in fields.iter() {
+            eb.push_bits(len, bits);
+        }
+        assert_eq!(eb.bit_len(), 52);
+
+        let mut eb = EncodedBlob::from_data(eb.bits.into_data(), version);
+        for &(len, bits) in fields.iter() {
+            assert_eq!(eb.take_bits(len), bits, "Len {len}");
+        }
+    }
+
     #[test]
     #[should_panic]
     fn test_take_bits_over_capacity() {
@@ -1252,6 +1679,26 @@ mod encoded_blob_decode_tests {
         assert_eq!(char_count, 0b11111111_11111111);
     }
 
+    // Largest count each width holds, pushed and taken back on both sides of every transition
+    #[test_case(Version::Normal(9))]
+    #[test_case(Version::Normal(10))]
+    #[test_case(Version::Normal(26))]
+    #[test_case(Version::Normal(27))]
+    fn test_header_round_trip_at_boundaries(version: Version) {
+        let modes = [Mode::Numeric, Mode::Alphanumeric, Mode::Byte];
+        let bit_capacity = version.bit_capacity(ECLevel::L, Palette::Mono);
+        let mut eb = EncodedBlob::new(version, bit_capacity);
+        for mode in modes {
+            eb.push_header(mode, (1 << version.char_count_bit_len(mode)) - 1);
+        }
+
+        let mut eb = EncodedBlob::from_data(eb.bits.into_data(), version);
+        for mode in modes {
+            let exp_count = (1 << version.char_count_bit_len(mode)) - 1;
+            assert_eq!(eb.take_header(), Some((mode, exp_count)));
+        }
+    }
+
     #[test]
     fn test_take_header_v27() {
         let data = vec![
@@ -1276,7 +1723,7 @@ mod encoded_blob_decode_tests {
         let version = Version::Normal(1);
         let ec_level = ECLevel::L;
         let palette = Palette::Mono;
-        let (encoded_data, len, version) =
+        let (encoded_data, len, _, version) =
             encode_with_version(data, ec_level, version, palette).unwrap();
         let mut eb = EncodedBlob::from_data(encoded_data, version);
         eb.take_header().unwrap();
@@ -1285,7 +1732,7 @@ mod encoded_blob_decode_tests {
         let numeric_data = eb.take_numeric_data(2);
         assert_eq!(numeric_data, "45".as_bytes().to_vec());
         let data = "6".as_bytes();
-        let (encoded_data, len, version) =
+        let (encoded_data, len, _, version) =
             encode_with_version(data, ECLevel::L, version, palette).unwrap();
         let mut eb = EncodedBlob::from_data(encoded_data, version);
         eb.take_header().unwrap();
@@ -1299,7 +1746,7 @@ mod encoded_blob_decode_tests {
         let version = Version::Normal(1);
         let ec_level = ECLevel::L;
         let palette = Palette::Mono;
-        let (encoded_data, len, version) =
+        let (encoded_data, len, _, version) =
             encode_with_version(data, ec_level, version, palette).unwrap();
         let mut eb = EncodedBlob::from_data(encoded_data, version);
         eb.take_header().unwrap();
@@ -1308,7 +1755,7 @@ mod encoded_blob_decode_tests {
         let alphanumeric_data = eb.take_alphanumeric_data(1);
         assert_eq!(alphanumeric_data, "-".as_bytes().to_vec());
         let data = "%".as_bytes();
-        let (encoded_data, len, version) =
+        let (encoded_data, len, _, version) =
             encode_with_version(data, ECLevel::L, version, palette).unwrap();
         let mut eb = EncodedBlob::from_data(encoded_data, version);
         eb.take_header().unwrap();
@@ -1322,7 +1769,7 @@ mod encoded_blob_decode_tests {
         let version = Version::Normal(1);
         let ec_level = ECLevel::L;
         let palette = Palette::Mono;
-        let (encoded_data, len, version) =
+        let (encoded_data, len, _, version) =
             encode_with_version(data, ec_level, version, palette).unwrap();
         let mut eb = EncodedBlob::from_data(encoded_data, version);
         eb.take_header().unwrap();
@@ -1338,19 +1785,22 @@ mod encoded_blob_decode_tests {
         let version = Version::Normal(2);
         let ec_level = ECLevel::L;
         let palette = Palette::Mono;
-        let (encoded_data, len, version) =
+        let (encoded_data, len, _, version) =
             encode_with_version(data, ec_level, version, palette).unwrap();
         let mut eb = EncodedBlob::from_data(encoded_data, version);
-        let seg_data = eb.take_segment().unwrap();
-        assert_eq!(seg_data, "abc".as_bytes().to_vec());
-        let seg_data = eb.take_segment().unwrap();
-        assert_eq!(seg_data, "ABCDEF".as_bytes().to_vec());
-        let seg_data = eb.take_segment().unwrap();
-        assert_eq!(seg_data, "1234567890123".as_bytes().to_vec());
-        let seg_data = eb.take_segment().unwrap();
-        assert_eq!(seg_data, "ABCDEF".as_bytes().to_vec());
-        let seg_data = eb.take_segment().unwrap();
-        assert_eq!(seg_data, "abc".as_bytes().to_vec());
+        let exp_segs = [
+            (Mode::Byte, "abc"),
+            (Mode::Alphanumeric, "ABCDEF"),
+            (Mode::Numeric, "1234567890123"),
+            (Mode::Alphanumeric, "ABCDEF"),
+            (Mode::Byte, "abc"),
+        ];
+        for (exp_mode, exp_data) in exp_segs {
+            let (mode, seg_data) = eb.take_segment().unwrap();
+            assert_eq!(mode, exp_mode);
+            assert_eq!(seg_data, exp_data.as_bytes().to_vec());
+        }
+        assert_eq!(eb.take_segment(), None);
     }
 }
 
@@ -1358,19 +1808,32 @@ mod encoded_blob_decode_tests {
 //------------------------------------------------------------------------------
 
 pub fn decode(data: &[u8], version: Version) -> Vec<u8> {
+    decode_with_segments(data, version).0
+}
+
+// Also returns the mode and char count of each segment in stream order
+pub fn decode_with_segments(data: &[u8], version: Version) -> (Vec<u8>, Vec<(Mode, usize)>) {
     let mut encoded_blob = EncodedBlob::from_data(data.to_vec(), version);
     let mut res = Vec::with_capacity(data.len());
-    while let Some(decoded_seg) = encoded_blob.take_segment() {
+    let mut segments = Vec::new();
+    while let Some((mode, decoded_seg)) = encoded_blob.take_segment() {
+        segments.push((mode, decoded_seg.len()));
         res.extend(decoded_seg);
     }
-    res
+    (res, segments)
 }
 
 #[cfg(test)]
 mod decode_tests {
-    use super::decode;
+    use test_case::test_case;
+
+    use super::{decode, decode_with_segments};
     use crate::{
-        codec::encode_with_version,
+        codec::{
+            encode, encode_to_bits, encode_with_eci, encode_with_mode, encode_with_version, Mode,
+            UTF8_ECI,
+        },
+        error::QRError,
         metadata::{ECLevel, Palette, Version},
     };
 
@@ -1380,11 +1843,79 @@ mod decode_tests {
         let version = Version::Normal(2);
         let ec_level = ECLevel::L;
         let palette = Palette::Mono;
-        let (encoded_data, len, version) =
+        let (encoded_data, len, _, version) =
             encode_with_version(data, ec_level, version, palette).unwrap();
         let decoded_data = decode(&encoded_data, version);
         assert_eq!(decoded_data, data);
     }
+
+    #[test]
+    fn test_decode_with_segments() {
+        let data = "hello 🌎 12345678901234567890".as_bytes();
+        let (encoded_data, _, _, version) = encode(data, ECLevel::L, Palette::Mono).unwrap();
+        let (decoded_data, segments) = decode_with_segments(&encoded_data, version);
+        assert_eq!(decoded_data, data);
+        assert_eq!(segments, vec![(Mode::Byte, 11), (Mode::Numeric, 20)]);
+    }
+
+    #[test]
+    fn test_decode_alphanumeric_alphabet() {
+        let data = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ $%*+-./:";
+        let (encoded_data, _, _, version) =
+            encode_with_mode(data, ECLevel::L, None, Palette::Mono, Mode::Alphanumeric).unwrap();
+        assert_eq!(decode(&encoded_data, version), data);
+    }
+
+    // Trailing groups of 1 or 2 digits take 4 or 7 bits and keep their leading zeros
+    #[test_case("1", &[4])]
+    #[test_case("12", &[7])]
+    #[test_case("123", &[10])]
+    #[test_case("1234", &[10, 4])]
+    #[test_case("00123", &[10, 7])]
+    #[test_case("007", &[10])]
+    #[test_case("1000001", &[10, 10, 4])]
+    fn test_decode_numeric_groups(data: &str, exp_chunk_lens: &[usize]) {
+        let data = data.as_bytes();
+        let (encoded_data, _, _, version) =
+            encode_with_mode(data, ECLevel::L, None, Palette::Mono, Mode::Numeric).unwrap();
+        let (decoded_data, segments) = decode_with_segments(&encoded_data, version);
+        assert_eq!(decoded_data, data);
+        assert_eq!(segments, vec![(Mode::Numeric, data.len())]);
+
+        // Mode and char count come first, then one field per group
+        let bits = encode_to_bits(data, ECLevel::L, None, Palette::Mono, Some(Mode::Numeric), None)
+            .unwrap();
+        let chunk_lens = bits.split(' ').skip(2).take(exp_chunk_lens.len()).map(|f| f.len());
+        assert_eq!(chunk_lens.collect::<Vec<_>>(), exp_chunk_lens);
+    }
+
+    #[test]
+    fn test_decode_skips_eci() {
+        let data = "café 🌎 1234567890".as_bytes();
+        let (encoded_data, _, _, version) =
+            encode_with_eci(data, ECLevel::L, None, Palette::Mono, None, UTF8_ECI).unwrap();
+        assert_eq!(encoded_data[0] >> 4, 0b0111);
+        assert_eq!(decode(&encoded_data, version), data);
+    }
+
+    // Designators past 127 take the 2 and 3 byte forms, which have to be skipped in full
+    #[test_case(127)]
+    #[test_case(128)]
+    #[test_case(16383)]
+    #[test_case(16384)]
+    #[test_case(999_999)]
+    fn test_decode_skips_multi_byte_eci(eci: u32) {
+        let data = "café 🌎 1234567890".as_bytes();
+        let (encoded_data, _, _, version) =
+            encode_with_eci(data, ECLevel::L, None, Palette::Mono, None, eci).unwrap();
+        assert_eq!(decode(&encoded_data, version), data);
+    }
+
+    #[test]
+    fn test_encode_eci_too_large() {
+        let res = encode_with_eci(b"1", ECLevel::L, None, Palette::Mono, None, 1_000_000);
+        assert_eq!(res, Err(QRError::InvalidEci));
+    }
 }
 
 // Global constants
@@ -1393,3 +1924,10 @@ mod decode_tests {
 static PADDING_CODEWORDS: [u8; 2] = [0b1110_1100, 0b0001_0001];
 
 static MODES: [Mode; 3] = [Mode::Numeric, Mode::Alphanumeric, Mode::Byte];
+
+const ECI_MODE_BITS: u16 = 0b0111;
+
+// Designators run up to 6 decimal digits
+const MAX_ECI: u32 = 999_999;
+
+pub static UTF8_ECI: u32 = 26;
@@ -1,6 +1,9 @@
-use std::ops::{Deref, Not};
+use std::{
+    borrow::Cow,
+    ops::{Deref, Not},
+};
 
-use image::{GrayImage, Luma};
+use image::{imageops, GrayImage, Luma, RgbImage};
 
 use crate::{
     ec::rectify_info,
@@ -8,13 +11,78 @@ use crate::{
     iter::EncRegionIter,
     mask::MaskPattern,
     metadata::{
-        parse_format_info_qr, Color, ECLevel, Metadata, Palette, Version, FORMAT_ERROR_CAPACITY,
-        FORMAT_INFOS_QR, FORMAT_INFO_COORDS_QR_MAIN, FORMAT_INFO_COORDS_QR_SIDE, FORMAT_MASK,
+        parse_format_info_micro, parse_format_info_qr, Color, ECLevel, Metadata, Palette, Version,
+        FORMAT_ERROR_CAPACITY, FORMAT_INFOS_MICRO, FORMAT_INFOS_QR, FORMAT_INFO_COORDS_MICRO,
+        FORMAT_INFO_COORDS_QR_MAIN, FORMAT_INFO_COORDS_QR_SIDE, FORMAT_MASK, FORMAT_MASK_MICRO,
         VERSION_ERROR_BIT_LEN, VERSION_ERROR_CAPACITY, VERSION_INFOS, VERSION_INFO_COORDS_BL,
         VERSION_INFO_COORDS_TR,
     },
+    qr::QR,
 };
 
+// Module pitch in px below which planes are upscaled before sampling
+static SUPERSAMPLE_PITCH: f32 = 2.0;
+
+// Corners as (x, y) in TL, TR, BR, BL order
+pub type Quad = [(f32, f32); 4];
+
+// Axis aligned pixel region, top left corner and size
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Rect {
+    pub x: u32,
+    pub y: u32,
+    pub w: u32,
+    pub h: u32,
+}
+
+// Images the mono reader samples. Rgb is weighted down to luma with the Rec. 601 coefficients,
+// 0.299 R + 0.587 G + 0.114 B. Sources with color also give their rgb channels, which poly codes
+// are sampled from
+pub trait LumaSource {
+    fn to_luma(&self) -> Cow<'_, GrayImage>;
+
+    fn to_rgb(&self) -> Option<Cow<'_, RgbImage>> {
+        None
+    }
+}
+
+impl LumaSource for GrayImage {
+    fn to_luma(&self) -> Cow<'_, GrayImage> {
+        Cow::Borrowed(self)
+    }
+}
+
+impl LumaSource for RgbImage {
+    fn to_luma(&self) -> Cow<'_, GrayImage> {
+        let (w, h) = self.dimensions();
+        Cow::Owned(GrayImage::from_fn(w, h, |x, y| {
+            let [r, g, b] = self.get_pixel(x, y).0;
+            let luma = 0.299 * r as f32 + 0.587 * g as f32 + 0.114 * b as f32;
+            Luma([luma.round() as u8])
+        }))
+    }
+
+    fn to_rgb(&self) -> Option<Cow<'_, RgbImage>> {
+        Some(Cow::Borrowed(self))
+    }
+}
+
+// How sampled luma is split into dark and light
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub enum ThresholdMethod {
+    // Mean luma of the whole image
+    Mean,
+    // Cutoff maximising the between class variance of the image histogram, suits high and low key
+    // images where the mean leans towards the dominant class
+    Otsu,
+    // Luma below the value is dark
+    Fixed(u8),
+    // Midway between the darkest and lightest module samples, with samples near the cutoff
+    // settled by averaging around the module centre
+    #[default]
+    Adaptive,
+}
+
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum DeModule {
     Unmarked(Color),
@@ -50,44 +118,358 @@ pub struct DeQR {
     grid: Vec<DeModule>,
     version: Version,
     ec_level: Option<ECLevel>,
+    channel_ec_levels: Option<[ECLevel; 3]>,
     palette: Option<Palette>,
     mask_pattern: Option<MaskPattern>,
+    confidence: f32,
+    inverted: bool,
+    mirrored: bool,
+    bounds: Quad,
 }
 
 impl DeQR {
-    pub fn from_image(qr: &GrayImage, version: Version) -> Self {
-        let qr_width = version.width();
+    // Reads modules straight from a built grid, skipping sampling
+    pub fn from_qr(qr: &QR) -> Self {
+        let width = qr.width();
+        let w = width as i16;
+        let grid = (0..w)
+            .flat_map(|r| (0..w).map(move |c| (r, c)))
+            .map(|(r, c)| DeModule::Unmarked(*qr.get(r, c)))
+            .collect();
+
+        Self {
+            width,
+            grid,
+            version: qr.version(),
+            ec_level: None,
+            channel_ec_levels: None,
+            palette: Some(qr.palette()),
+            mask_pattern: None,
+            confidence: 1.0,
+            inverted: false,
+            mirrored: false,
+            bounds: Self::rect_bounds((0.0, 0.0), (width as f32, width as f32)),
+        }
+    }
+
+    pub fn from_image<I: LumaSource>(qr: &I, version: Version) -> Self {
+        let qr = qr.to_luma();
         let (w, h) = qr.dimensions();
-        let (w, h) = (w as i16, h as i16);
-        let mod_size = w / qr_width as i16;
-        let qz_size = if let Version::Normal(_) = version { 4 } else { 2 } * mod_size;
-
-        debug_assert!(w == h, "Image is not perfect square");
-        debug_assert!(
-            (w - 2 * qz_size) % qr_width as i16 == 0,
-            "Image width is not a multiple of qr size"
-        );
+        Self::from_planes(&[qr.as_raw()], w, h, version, None, ThresholdMethod::default())
+    }
+
+    pub fn from_image_with_threshold<I: LumaSource>(
+        qr: &I,
+        version: Version,
+        threshold: ThresholdMethod,
+    ) -> Self {
+        let qr = qr.to_luma();
+        let (w, h) = qr.dimensions();
+        Self::from_planes(&[qr.as_raw()], w, h, version, None, threshold)
+    }
+
+    // Reads a row-major 8 bit grayscale buffer, e.g. a camera frame, without wrapping it in an image.
+    // Module pitch can be fractional, each module is read by bilinear sampling at its centre. A
+    // buffer that isn't w * h bytes long errors with InvalidBufferSize
+    pub fn from_luma(data: &[u8], w: u32, h: u32, version: Version) -> QRResult<Self> {
+        if data.len() != w as usize * h as usize {
+            return Err(QRError::InvalidBufferSize);
+        }
+
+        Ok(Self::from_planes(&[data], w, h, version, None, ThresholdMethod::default()))
+    }
+
+    // Reads a poly code, each rgb channel is sampled as its own plane
+    pub fn from_rgb(qr: &RgbImage, version: Version) -> Self {
+        let (w, h) = qr.dimensions();
+        let planes: Vec<Vec<u8>> =
+            (0..3).map(|ch| qr.pixels().map(|p| p.0[ch]).collect()).collect();
+        let planes = planes.iter().map(|p| p.as_slice()).collect::<Vec<_>>();
 
-        let half_area = mod_size * mod_size / 2;
+        Self::from_planes(&planes, w, h, version, Some(Palette::Poly), ThresholdMethod::default())
+    }
+
+    fn from_planes(
+        planes: &[&[u8]],
+        w: u32,
+        h: u32,
+        version: Version,
+        palette: Option<Palette>,
+        threshold: ThresholdMethod,
+    ) -> Self {
+        // Distant codes span only a few pixels per module, where interpolating between pixel
+        // centres blurs neighbouring modules together. Upscaling with a bicubic filter first keeps
+        // module edges sharper, so the samples at module centres settle on either side. A module of
+        // a pixel or less has no edge left to sharpen
+        let qz_size = if let Version::Normal(_) = version { 4 } else { 2 };
+        let pitch = w.min(h) as f32 / (qz_size + version.width() + qz_size) as f32;
+        if pitch > 1.0 && pitch < SUPERSAMPLE_PITCH {
+            let factor = if pitch < 1.5 { 4 } else { 2 };
+            let upscaled =
+                planes.iter().map(|p| Self::upscale_plane(p, w, h, factor)).collect::<Vec<_>>();
+            let upscaled = upscaled.iter().map(|p| p.as_slice()).collect::<Vec<_>>();
+            let mut deqr = Self::from_planes_at_scale(
+                &upscaled,
+                w * factor,
+                h * factor,
+                version,
+                palette,
+                threshold,
+            );
+            deqr.bounds.iter_mut().for_each(|(x, y)| {
+                *x /= factor as f32;
+                *y /= factor as f32;
+            });
+            return deqr;
+        }
 
-        let mut black_count = vec![0; qr_width * qr_width];
-        for (c, r, pixel) in qr.enumerate_pixels() {
-            let (r, c) = (r as i16, c as i16);
-            if r < qz_size || r >= w - qz_size || c < qz_size || c >= w - qz_size {
+        Self::from_planes_at_scale(planes, w, h, version, palette, threshold)
+    }
+
+    // Bicubic upscale of a single plane by an integer factor. Plane lengths are checked against
+    // the dimensions where they come in, in from_luma
+    fn upscale_plane(plane: &[u8], w: u32, h: u32, factor: u32) -> Vec<u8> {
+        let img = GrayImage::from_fn(w, h, |x, y| Luma([plane[(y * w + x) as usize]]));
+        imageops::resize(&img, w * factor, h * factor, imageops::FilterType::CatmullRom).into_raw()
+    }
+
+    fn from_planes_at_scale(
+        planes: &[&[u8]],
+        w: u32,
+        h: u32,
+        version: Version,
+        palette: Option<Palette>,
+        threshold: ThresholdMethod,
+    ) -> Self {
+        let qz_size = if let Version::Normal(_) = version { 4 } else { 2 };
+        let deqr = Self::sample_planes(planes, w, h, version, palette, threshold, qz_size);
+        if deqr.count_finder_mismatches() == 0 {
+            return deqr;
+        }
+
+        // Codes cropped flush to their modules have no quiet zone, so the finders only line up
+        // once the image edge is taken as the symbol edge
+        let flush = Self::sample_planes(planes, w, h, version, palette, threshold, 0);
+        if flush.count_finder_mismatches() < deqr.count_finder_mismatches() {
+            flush
+        } else {
+            deqr
+        }
+    }
+
+    // Samples module centres assuming qz_size light modules between the image and symbol edges
+    fn sample_planes(
+        planes: &[&[u8]],
+        w: u32,
+        h: u32,
+        version: Version,
+        palette: Option<Palette>,
+        threshold: ThresholdMethod,
+        qz_size: usize,
+    ) -> Self {
+        // Pitch is measured per axis, so stretched photos of the code still land on module centres
+        let qr_width = version.width();
+        let full_width = (qz_size + qr_width + qz_size) as f32;
+        let (pitch_x, pitch_y) = (w as f32 / full_width, h as f32 / full_width);
+
+        let centre = |i: usize, pitch: f32| (i + qz_size) as f32 * pitch + pitch / 2.0;
+        let centre_xy = |i: usize| (centre(i % qr_width, pitch_x), centre(i / qr_width, pitch_y));
+        let centre_lumas = planes
+            .iter()
+            .map(|p| {
+                (0..qr_width * qr_width)
+                    .map(|i| {
+                        let (x, y) = centre_xy(i);
+                        Self::sample_luma(p, w, h, x, y)
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        // Adaptive thresholds each plane half way between its darkest and lightest samples. Samples
+        // between the low and high cutoffs are ambiguous, e.g. blurred edges or compression
+        // artifacts, and are settled by averaging samples spread over the module. Other methods
+        // have a single cutoff with no ambiguous band
+        let cutoffs = planes
+            .iter()
+            .zip(&centre_lumas)
+            .map(|(plane, lumas)| {
+                let lo = lumas.iter().copied().fold(f32::MAX, f32::min);
+                let hi = lumas.iter().copied().fold(f32::MIN, f32::max);
+                let half_range = ((hi - lo) / 2.0).max(1.0);
+                let mid = match threshold {
+                    ThresholdMethod::Mean => {
+                        plane.iter().map(|&p| p as f32).sum::<f32>() / plane.len() as f32
+                    }
+                    ThresholdMethod::Otsu => Self::otsu_threshold(plane) as f32 + 0.5,
+                    ThresholdMethod::Fixed(t) => t as f32,
+                    ThresholdMethod::Adaptive => {
+                        let (mid, band) = ((lo + hi) / 2.0, (hi - lo) / 8.0);
+                        return (mid - band, mid, mid + band, half_range);
+                    }
+                };
+                (mid, mid, mid, half_range)
+            })
+            .collect::<Vec<_>>();
+
+        let (spread_x, spread_y) = (pitch_x / 4.0, pitch_y / 4.0);
+        let mut grid = Vec::with_capacity(qr_width * qr_width);
+        let mut margin_sum = 0.0;
+        for i in 0..qr_width * qr_width {
+            let (x, y) = centre_xy(i);
+            let mut bits = 0;
+            for ((plane, lumas), &(low, mid, high, half_range)) in
+                planes.iter().zip(&centre_lumas).zip(&cutoffs)
+            {
+                let luma = lumas[i];
+                margin_sum += ((luma - mid).abs() / half_range).min(1.0);
+                let is_dark = if luma < low || luma > high {
+                    luma < mid
+                } else {
+                    let spread_sum: f32 = [(-1.0, -1.0), (-1.0, 1.0), (1.0, -1.0), (1.0, 1.0)]
+                        .iter()
+                        .map(|(dx, dy)| {
+                            Self::sample_luma(plane, w, h, x + dx * spread_x, y + dy * spread_y)
+                        })
+                        .sum();
+                    (luma + spread_sum) / 5.0 < mid
+                };
+                bits = (bits << 1) | is_dark as u32;
+            }
+            let color = match planes.len() {
+                1 => Color::from_channels(bits * 0b111),
+                _ => Color::from_channels(bits),
+            };
+            grid.push(DeModule::Unmarked(color));
+        }
+
+        // Average distance of sampled luma from the threshold, 1.0 for a clean code
+        let confidence = margin_sum / (grid.len() * planes.len()) as f32;
+
+        let mut deqr = Self {
+            width: qr_width,
+            grid,
+            version,
+            ec_level: None,
+            channel_ec_levels: None,
+            palette,
+            mask_pattern: None,
+            confidence,
+            inverted: false,
+            mirrored: false,
+            bounds: Self::rect_bounds(
+                (qz_size as f32 * pitch_x, qz_size as f32 * pitch_y),
+                (qr_width as f32 * pitch_x, qr_width as f32 * pitch_y),
+            ),
+        };
+
+        // Light on dark codes read as inverted finders, flip the grid back
+        if deqr.count_finder_mismatches() * 2 > deqr.finder_module_count() {
+            deqr.grid.iter_mut().for_each(|m| *m = !*m);
+            deqr.inverted = true;
+        }
+
+        // Codes seen through glass or from the back of film are mirrored, which moves the bottom
+        // left finder to the bottom right. Flip the grid back when its finders line up better
+        let mut mirrored = deqr.clone();
+        mirrored.mirror();
+        if mirrored.count_finder_mismatches() < deqr.count_finder_mismatches() {
+            return mirrored;
+        }
+
+        deqr
+    }
+
+    // Reverses each row. Bounds keep following the symbol's own corners, so its top left corner
+    // is now the image's top right
+    fn mirror(&mut self) {
+        self.grid.chunks_mut(self.width).for_each(|row| row.reverse());
+        let [tl, tr, br, bl] = self.bounds;
+        self.bounds = [tr, tl, bl, br];
+        self.mirrored = !self.mirrored;
+    }
+
+    // Otsu's method over a 256 bin histogram. Returns the highest luma of the dark class
+    fn otsu_threshold(data: &[u8]) -> u8 {
+        let mut hist = [0_usize; 256];
+        data.iter().for_each(|&p| hist[p as usize] += 1);
+
+        let total = data.len() as f64;
+        let total_sum: f64 = hist.iter().enumerate().map(|(i, &n)| (i * n) as f64).sum();
+        let (mut dark_count, mut dark_sum) = (0.0, 0.0);
+        let (mut best, mut best_var) = (0, 0.0);
+        for (t, &n) in hist.iter().enumerate() {
+            dark_count += n as f64;
+            dark_sum += (t * n) as f64;
+            let light_count = total - dark_count;
+            if dark_count == 0.0 || light_count == 0.0 {
                 continue;
             }
-            let index =
-                Self::coord_to_index((r - qz_size) / mod_size, (c - qz_size) / mod_size, qr_width);
-            let Luma([luma]) = *pixel;
-            black_count[index] += if luma < 128 { 1 } else { 0 };
+
+            let mean_diff = dark_sum / dark_count - (total_sum - dark_sum) / light_count;
+            let var = dark_count * light_count * mean_diff * mean_diff;
+            if var > best_var {
+                (best, best_var) = (t, var);
+            }
         }
+        best as u8
+    }
 
-        let grid = black_count
-            .iter()
-            .map(|&bc| DeModule::Unmarked(if bc > half_area { Color::Dark } else { Color::Light }))
-            .collect();
+    fn finder_origins(&self) -> Vec<(i16, i16)> {
+        let w = self.width as i16;
+        match self.version {
+            Version::Micro(_) => vec![(0, 0)],
+            Version::Normal(_) => vec![(0, 0), (0, w - 7), (w - 7, 0)],
+        }
+    }
+
+    fn finder_module_count(&self) -> usize {
+        self.finder_origins().len() * 49
+    }
+
+    pub fn count_finder_mismatches(&self) -> usize {
+        self.finder_origins().into_iter().map(|(r0, c0)| self.finder_mismatches_at(r0, c0)).sum()
+    }
+
+    fn finder_mismatches_at(&self, r0: i16, c0: i16) -> usize {
+        let mut count = 0;
+        for i in 0..7_i16 {
+            for j in 0..7_i16 {
+                let exp =
+                    if (i - 3).abs().max((j - 3).abs()) == 2 { Color::Light } else { Color::Dark };
+                if *self.get(r0 + i, c0 + j) != exp {
+                    count += 1;
+                }
+            }
+        }
+        count
+    }
 
-        Self { width: qr_width, grid, version, ec_level: None, palette: None, mask_pattern: None }
+    // Finders whose sampled pattern is mostly intact, with at most a quarter of its modules off
+    pub fn count_intact_finders(&self) -> usize {
+        self.finder_origins()
+            .into_iter()
+            .filter(|&(r0, c0)| self.finder_mismatches_at(r0, c0) * 4 <= 49)
+            .count()
+    }
+
+    // Bilinear interpolation of luma at a continuous point, where pixel (i, j) is centred at
+    // (i + 0.5, j + 0.5). Points near the border are clamped to the edge pixels. An empty image
+    // reads as light everywhere, so it fails on the finders rather than panicking
+    fn sample_luma(data: &[u8], w: u32, h: u32, x: f32, y: f32) -> f32 {
+        if w == 0 || h == 0 {
+            return 255.0;
+        }
+        let fx = (x - 0.5).clamp(0.0, (w - 1) as f32);
+        let fy = (y - 0.5).clamp(0.0, (h - 1) as f32);
+        let (x0, y0) = (fx.floor() as usize, fy.floor() as usize);
+        let (x1, y1) = ((x0 + 1).min(w as usize - 1), (y0 + 1).min(h as usize - 1));
+        let (tx, ty) = (fx - x0 as f32, fy - y0 as f32);
+
+        let px = |x: usize, y: usize| data[y * w as usize + x] as f32;
+        let top = px(x0, y0) * (1.0 - tx) + px(x1, y0) * tx;
+        let bottom = px(x0, y1) * (1.0 - tx) + px(x1, y1) * tx;
+        top * (1.0 - ty) + bottom * ty
     }
 
     pub fn from_str(qr: &str, version: Version) -> Self {
@@ -106,11 +488,59 @@ impl DeQR {
             .map(|(i, clr)| DeModule::Unmarked(if clr == ' ' { Color::Dark } else { Color::Light }))
             .collect();
 
-        Self { width: qr_width, grid, version, ec_level: None, palette: None, mask_pattern: None }
+        Self {
+            width: qr_width,
+            grid,
+            version,
+            ec_level: None,
+            channel_ec_levels: None,
+            palette: None,
+            mask_pattern: None,
+            confidence: 1.0,
+            inverted: false,
+            mirrored: false,
+            bounds: Self::rect_bounds(
+                (qz_size as f32, qz_size as f32),
+                (qr_width as f32, qr_width as f32),
+            ),
+        }
+    }
+
+    fn rect_bounds((x0, y0): (f32, f32), (w, h): (f32, f32)) -> Quad {
+        let (x1, y1) = (x0 + w, y0 + h);
+        [(x0, y0), (x1, y0), (x1, y1), (x0, y1)]
     }
 
     pub fn metadata(&self) -> Metadata {
         Metadata::new(Some(self.version), self.ec_level, self.palette, self.mask_pattern)
+            .with_mirrored(self.mirrored)
+            .with_inverted(self.inverted)
+    }
+
+    // Ec level of each channel read from a poly code's format info
+    pub fn channel_ec_levels(&self) -> Option<[ECLevel; 3]> {
+        self.channel_ec_levels
+    }
+
+    // Sampling confidence in 0.0..=1.0
+    pub fn confidence(&self) -> f32 {
+        self.confidence
+    }
+
+    // Outer corners of the symbol, excluding quiet zone. Image space for sampled codes, character
+    // cells for strings and modules for grids
+    pub fn bounds(&self) -> Quad {
+        self.bounds
+    }
+
+    // Whether the code was sampled light on dark and flipped back
+    pub fn is_inverted(&self) -> bool {
+        self.inverted
+    }
+
+    // Whether the code was sampled left to right mirrored and flipped back
+    pub fn is_mirrored(&self) -> bool {
+        self.mirrored
     }
 
     pub fn count_dark_modules(&self) -> usize {
@@ -162,7 +592,14 @@ impl DeQR {
 
 #[cfg(test)]
 mod deqr_util_tests {
-    use super::DeQR;
+    use image::{
+        codecs::jpeg::JpegEncoder,
+        imageops::{flip_horizontal, invert, resize, FilterType},
+        Rgb, RgbImage,
+    };
+    use test_case::test_case;
+
+    use super::{DeQR, LumaSource, ThresholdMethod};
     use crate::{
         builder::QRBuilder,
         metadata::{ECLevel, Version},
@@ -207,6 +644,219 @@ mod deqr_util_tests {
             }
         }
     }
+
+    #[test]
+    fn test_from_luma() {
+        let data = "Hello, world! 🌎";
+        let version = Version::Normal(2);
+        let ec_level = ECLevel::L;
+
+        let qr =
+            QRBuilder::new(data.as_bytes()).version(version).ec_level(ec_level).build().unwrap();
+        let img = qr.render(3);
+        let (w, h) = img.dimensions();
+
+        let from_image = DeQR::from_image(&img, version);
+        let from_luma = DeQR::from_luma(img.as_raw(), w, h, version).unwrap();
+
+        assert_eq!(from_image.grid, from_luma.grid);
+    }
+
+    #[test]
+    fn test_from_image_fractional_module_size() {
+        let data = "Hello, world! 🌎";
+        let version = Version::Normal(2);
+        let size = version.width() as i16;
+        let ec_level = ECLevel::L;
+
+        let qr =
+            QRBuilder::new(data.as_bytes()).version(version).ec_level(ec_level).build().unwrap();
+        let img = qr.render(4);
+
+        // 33 modules squeezed into 91 px gives a pitch of ~2.76 px
+        for new_size in [91, 75, 140] {
+            let img = resize(&img, new_size, new_size, FilterType::Triangle);
+            let deqr = DeQR::from_image(&img, version);
+            for r in 0..size {
+                for c in 0..size {
+                    assert_eq!(*qr.get(r, c), *deqr.get(r, c), "{new_size}: {r} {c}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_from_image_jpeg() {
+        let data = "Hello, world! 🌎";
+        let version = Version::Normal(2);
+        let size = version.width() as i16;
+
+        let qr = QRBuilder::new(data.as_bytes()).version(version).build().unwrap();
+        let img = qr.render(3);
+
+        let mut jpeg = Vec::new();
+        JpegEncoder::new_with_quality(&mut jpeg, 50).encode_image(&img).unwrap();
+        let img = image::load_from_memory(&jpeg).unwrap().to_luma8();
+
+        let deqr = DeQR::from_image(&img, version);
+        for r in 0..size {
+            for c in 0..size {
+                assert_eq!(*qr.get(r, c), *deqr.get(r, c), "{r} {c}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_from_image_inverted() {
+        let data = "Hello, world! 🌎";
+        let version = Version::Normal(2);
+        let size = version.width() as i16;
+
+        let qr = QRBuilder::new(data.as_bytes()).version(version).build().unwrap();
+        let mut img = qr.render(3);
+
+        assert!(!DeQR::from_image(&img, version).is_inverted());
+
+        invert(&mut img);
+        let deqr = DeQR::from_image(&img, version);
+        assert!(deqr.is_inverted());
+        for r in 0..size {
+            for c in 0..size {
+                assert_eq!(*qr.get(r, c), *deqr.get(r, c), "{r} {c}");
+            }
+        }
+    }
+
+    #[test_case(Version::Normal(2), false)]
+    #[test_case(Version::Normal(7), false)]
+    #[test_case(Version::Normal(2), true)]
+    fn test_from_image_mirrored(version: Version, inverted: bool) {
+        let size = version.width() as i16;
+        let qr = QRBuilder::new(b"Hello, world!").version(version).build().unwrap();
+        let img = qr.render(3);
+        assert!(!DeQR::from_image(&img, version).is_mirrored());
+
+        let mut img = flip_horizontal(&img);
+        if inverted {
+            invert(&mut img);
+        }
+        let deqr = DeQR::from_image(&img, version);
+        assert!(deqr.is_mirrored());
+        assert_eq!(deqr.is_inverted(), inverted);
+        for r in 0..size {
+            for c in 0..size {
+                assert_eq!(*qr.get(r, c), *deqr.get(r, c), "{r} {c}");
+            }
+        }
+
+        // The symbol's top left corner is on the image's right
+        let qz_size = 4.0 * 3.0;
+        assert_eq!(deqr.bounds()[0], (img.width() as f32 - qz_size, qz_size));
+        assert_eq!(deqr.count_intact_finders(), 3);
+    }
+
+    #[test]
+    fn test_from_image_large_module_size() {
+        let data = "Hello, world!";
+        let version = Version::Normal(1);
+        let size = version.width() as i16;
+
+        let qr =
+            QRBuilder::new(data.as_bytes()).version(version).ec_level(ECLevel::L).build().unwrap();
+        let deqr = DeQR::from_image(&qr.render(10), version);
+
+        for r in 0..size {
+            for c in 0..size {
+                assert_eq!(*qr.get(r, c), *deqr.get(r, c), "{r} {c}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_rgb_luma_weights() {
+        let img = RgbImage::from_fn(4, 1, |x, _| match x {
+            0 => Rgb([255, 0, 0]),
+            1 => Rgb([0, 255, 0]),
+            2 => Rgb([0, 0, 255]),
+            _ => Rgb([255, 255, 255]),
+        });
+        assert_eq!(img.to_luma().as_raw(), &vec![76, 150, 29, 255]);
+    }
+
+    #[test]
+    fn test_otsu_threshold() {
+        let mut data = vec![30; 100];
+        data.extend([200; 300]);
+        data.extend([90, 110, 180]);
+        let t = DeQR::otsu_threshold(&data);
+        assert!((90..180).contains(&t), "{t}");
+
+        // Skewed towards light, otsu stays between the classes
+        let mut data = vec![60; 10];
+        data.extend([70; 10]);
+        data.extend([180; 900]);
+        data.extend([190; 900]);
+        let t = DeQR::otsu_threshold(&data);
+        assert!((70..180).contains(&t), "{t}");
+    }
+
+    #[test]
+    fn test_from_image_with_threshold() {
+        let version = Version::Normal(2);
+        let qr = QRBuilder::new(b"Hello, world!").version(version).build().unwrap();
+        let img = qr.render(2);
+        let size = version.width() as i16;
+
+        for threshold in [
+            ThresholdMethod::Mean,
+            ThresholdMethod::Otsu,
+            ThresholdMethod::Fixed(128),
+            ThresholdMethod::Adaptive,
+        ] {
+            let deqr = DeQR::from_image_with_threshold(&img, version, threshold);
+            for r in 0..size {
+                for c in 0..size {
+                    assert_eq!(*qr.get(r, c), *deqr.get(r, c), "{threshold:?}: {r} {c}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_bounds_supersampled() {
+        let version = Version::Normal(2);
+        let qr = QRBuilder::new(b"Hello, world!").version(version).build().unwrap();
+        let img = resize(&qr.render(4), 54, 54, FilterType::Triangle);
+
+        // 33 modules in 54 px is below the supersampling pitch, bounds stay in the original image
+        let bounds = DeQR::from_image(&img, version).bounds();
+        let exp = [(6.5, 6.5), (47.5, 6.5), (47.5, 47.5), (6.5, 47.5)];
+        for ((x, y), (ex, ey)) in bounds.into_iter().zip(exp) {
+            assert!((x - ex).abs() < 0.5 && (y - ey).abs() < 0.5, "{bounds:?}");
+        }
+    }
+
+    #[test]
+    fn test_bounds() {
+        let version = Version::Normal(2);
+        let qr = QRBuilder::new(b"Hello, world!").version(version).build().unwrap();
+        let img = resize(&qr.render(4), 99, 99, FilterType::Triangle);
+
+        // 33 modules in 99 px, so the symbol spans 12 px to 87 px
+        let bounds = DeQR::from_image(&img, version).bounds();
+        let exp = [(12.0, 12.0), (87.0, 12.0), (87.0, 87.0), (12.0, 87.0)];
+        for ((x, y), (ex, ey)) in bounds.into_iter().zip(exp) {
+            assert!((x - ex).abs() < 0.5 && (y - ey).abs() < 0.5, "{bounds:?}");
+        }
+
+        // Finder anchors sit just inside the TL, TR and BL corners
+        let centre = (bounds[0].0 + bounds[2].0) / 2.0;
+        let inset = |v: f32| if v < centre { v + 1.5 } else { v - 1.5 };
+        for (x, y) in [bounds[0], bounds[1], bounds[3]] {
+            let (px, py) = (inset(x) as u32, inset(y) as u32);
+            assert!(img.get_pixel(px, py).0[0] < 128, "{px} {py}");
+        }
+    }
 }
 
 // Format & version info
@@ -214,30 +864,62 @@ mod deqr_util_tests {
 
 impl DeQR {
     pub fn read_format_info(&mut self) -> QRResult<(ECLevel, MaskPattern)> {
-        let main = self.get_number(&FORMAT_INFO_COORDS_QR_MAIN);
-        let mut f = rectify_info(main, &FORMAT_INFOS_QR, FORMAT_ERROR_CAPACITY)
-            .or_else(|_| {
-                let side = self.get_number(&FORMAT_INFO_COORDS_QR_SIDE);
-                rectify_info(side, &FORMAT_INFOS_QR, FORMAT_ERROR_CAPACITY)
-            })
-            .or(Err(QRError::InvalidFormatInfo))?;
+        if let Version::Micro(_) = self.version {
+            return self.read_format_info_micro();
+        }
+
+        // Poly codes carry one format info per channel, which only differ in ec level
+        let channel_count = if let Some(Palette::Poly) = self.palette { 3 } else { 1 };
+        let mut infos = Vec::with_capacity(channel_count);
+        for ch in 0..channel_count {
+            let main = self.get_channel_number(&FORMAT_INFO_COORDS_QR_MAIN, ch);
+            let f = rectify_info(main, &FORMAT_INFOS_QR, FORMAT_ERROR_CAPACITY)
+                .or_else(|_| {
+                    let side = self.get_channel_number(&FORMAT_INFO_COORDS_QR_SIDE, ch);
+                    rectify_info(side, &FORMAT_INFOS_QR, FORMAT_ERROR_CAPACITY)
+                })
+                .or(Err(QRError::InvalidFormatInfo))?;
+            infos.push(parse_format_info_qr(f ^ FORMAT_MASK));
+        }
+        let (ec_level, mask_pattern) = infos[0];
+        if infos.iter().any(|&(_, m)| m != mask_pattern) {
+            return Err(QRError::InvalidFormatInfo);
+        }
 
         self.mark_coords(&FORMAT_INFO_COORDS_QR_MAIN);
         self.mark_coords(&FORMAT_INFO_COORDS_QR_SIDE);
         self.set(-8, 8, DeModule::Marked);
 
-        f ^= FORMAT_MASK;
-        let (ec_level, mask_pattern) = parse_format_info_qr(f);
+        if let [r, g, b] = infos[..] {
+            self.channel_ec_levels = Some([r.0, g.0, b.0]);
+        }
         self.ec_level = Some(ec_level);
         self.mask_pattern = Some(mask_pattern);
         Ok((ec_level, mask_pattern))
     }
 
+    // Micro has a single copy of format info, and its symbol number has to agree with the version
+    fn read_format_info_micro(&mut self) -> QRResult<(ECLevel, MaskPattern)> {
+        let info = self.get_number(&FORMAT_INFO_COORDS_MICRO);
+        let f = rectify_info(info, &FORMAT_INFOS_MICRO, FORMAT_ERROR_CAPACITY)
+            .or(Err(QRError::InvalidFormatInfo))?;
+
+        let (version, ec_level, mask_pattern) = parse_format_info_micro(f ^ FORMAT_MASK_MICRO);
+        if version != self.version {
+            return Err(QRError::InvalidFormatInfo);
+        }
+
+        self.mark_coords(&FORMAT_INFO_COORDS_MICRO);
+        self.ec_level = Some(ec_level);
+        self.mask_pattern = Some(mask_pattern);
+        Ok((ec_level, mask_pattern))
+    }
+
+    // Only normal versions 7 and above carry version info, smaller ones have nothing to read
     pub fn read_version_info(&mut self) -> QRResult<Version> {
-        debug_assert!(
-            !matches!(self.version, Version::Micro(_) | Version::Normal(1..=6)),
-            "Version is too small to read version info"
-        );
+        if !matches!(self.version, Version::Normal(7..=40)) {
+            return Err(QRError::NoVersionInfo);
+        }
         let bl = self.get_number(&VERSION_INFO_COORDS_BL);
         let v = rectify_info(bl, &VERSION_INFOS, VERSION_ERROR_CAPACITY)
             .or_else(|_| {
@@ -259,6 +941,10 @@ impl DeQR {
         number
     }
 
+    pub fn get_channel_number(&self, coords: &[(i16, i16)], ch: usize) -> u32 {
+        coords.iter().fold(0, |acc, &(r, c)| (acc << 1) | self.get(r, c).channel(ch) as u32)
+    }
+
     pub fn mark_coords(&mut self, coords: &[(i16, i16)]) {
         for (r, c) in coords {
             self.set(*r, *c, DeModule::Marked);
@@ -268,10 +954,14 @@ impl DeQR {
 
 #[cfg(test)]
 mod deqr_infos_test {
+    use test_case::test_case;
+
     use crate::{
         builder::QRBuilder,
+        error::QRError,
         mask::MaskPattern,
-        metadata::{Color, ECLevel, Version},
+        metadata::{Color, ECLevel, Palette, Version},
+        qr::QR,
     };
 
     use super::DeQR;
@@ -298,6 +988,37 @@ mod deqr_infos_test {
         assert_eq!(format_info, (ec_level, mask_pattern));
     }
 
+    #[test_case(Version::Micro(1), ECLevel::L, MaskPattern::new(0))]
+    #[test_case(Version::Micro(2), ECLevel::M, MaskPattern::new(1))]
+    #[test_case(Version::Micro(3), ECLevel::L, MaskPattern::new(2))]
+    #[test_case(Version::Micro(4), ECLevel::Q, MaskPattern::new(3))]
+    fn test_read_format_info_micro(version: Version, ec_level: ECLevel, mask_pattern: MaskPattern) {
+        let qr = QRBuilder::new(b"123")
+            .version(version)
+            .ec_level(ec_level)
+            .mask(mask_pattern)
+            .build()
+            .unwrap();
+        let qr_str = qr.to_str(1);
+
+        let mut deqr = DeQR::from_str(&qr_str, version);
+
+        let format_info = deqr.read_format_info().unwrap();
+        assert_eq!(format_info, (ec_level, mask_pattern));
+    }
+
+    #[test]
+    fn test_read_format_info_micro_version_mismatch() {
+        let mut qr = QR::new(Version::Micro(3), ECLevel::M, Palette::Mono);
+        qr.draw_all_function_patterns();
+        qr.mask(MaskPattern::new(1));
+
+        let mut deqr = DeQR::from_qr(&qr);
+        deqr.version = Version::Micro(4);
+
+        assert_eq!(deqr.read_format_info().unwrap_err(), QRError::InvalidFormatInfo);
+    }
+
     #[test]
     fn test_read_format_info_one_corrupted() {
         let data = "Hello, world! 🌎";
@@ -440,6 +1161,17 @@ mod deqr_infos_test {
         assert_eq!(version_info, version);
     }
 
+    #[test_case(Version::Micro(3))]
+    #[test_case(Version::Normal(6))]
+    fn test_read_version_info_without_region(version: Version) {
+        let full_width = version.width() + if let Version::Normal(_) = version { 8 } else { 4 };
+        let qr_str = (" ".repeat(full_width) + "\n").repeat(full_width);
+
+        let mut deqr = DeQR::from_str(&qr_str, version);
+
+        assert_eq!(deqr.read_version_info(), Err(QRError::NoVersionInfo));
+    }
+
     #[test]
     fn test_read_version_info_one_corrupted() {
         let data = "Hello, world! 🌎";
@@ -900,7 +1632,7 @@ mod deqr_alignement_tests {
 
 impl DeQR {
     pub fn unmask(&mut self, pattern: MaskPattern) {
-        let mask_function = pattern.mask_functions();
+        let mask_function = pattern.mask_functions_for(self.version);
         let w = self.width as i16;
         for r in 0..w {
             for c in 0..w {
@@ -916,21 +1648,27 @@ impl DeQR {
 //------------------------------------------------------------------------------
 
 impl DeQR {
+    // Poly codes yield the three channel payloads chained, mirroring the builder
     pub fn extract_payload(&mut self, version: Version) -> Vec<u8> {
-        let total_codewords = version.total_codewords();
-        let mut codewords = Vec::with_capacity(total_codewords);
-        let mut coords = EncRegionIter::new(version);
-        for _ in 0..total_codewords {
-            let mut codeword = 0;
-            for _ in 0..8 {
-                for (r, c) in coords.by_ref() {
-                    if matches!(self.get(r, c), DeModule::Unmarked(_)) {
-                        codeword = (codeword << 1) | u8::from(*self.get(r, c));
-                        break;
+        let total_codewords = version.channel_codewords();
+        let channel_count = if let Some(Palette::Poly) = self.palette { 3 } else { 1 };
+        let half_codeword = self.ec_level.and_then(|l| version.half_codeword(l));
+        let mut codewords = Vec::with_capacity(total_codewords * channel_count);
+        for ch in 0..channel_count {
+            let mut coords = EncRegionIter::new(version);
+            for k in 0..total_codewords {
+                let bit_len = if half_codeword == Some(k) { 4 } else { 8 };
+                let mut codeword = 0;
+                for _ in 0..bit_len {
+                    for (r, c) in coords.by_ref() {
+                        if matches!(self.get(r, c), DeModule::Unmarked(_)) {
+                            codeword = (codeword << 1) | self.get(r, c).channel(ch);
+                            break;
+                        }
                     }
                 }
+                codewords.push(codeword << (8 - bit_len));
             }
-            codewords.push(codeword);
         }
         codewords
     }
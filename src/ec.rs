@@ -1,7 +1,13 @@
+mod galois;
+
+use alloc::{vec, vec::Vec};
+use core::ops::Deref;
+
 use crate::{
     error::{QRError, QRResult},
     metadata::{ECLevel, Version},
 };
+use galois::{exp, log};
 
 // ECC: Error Correction Codeword generator
 pub fn ecc(data: &[u8], version: Version, ec_level: ECLevel) -> (Vec<&[u8]>, Vec<Vec<u8>>) {
@@ -15,8 +21,11 @@ pub fn ecc(data: &[u8], version: Version, ec_level: ECLevel) -> (Vec<&[u8]>, Vec
 }
 
 pub fn blockify(data: &[u8], version: Version, ec_level: ECLevel) -> Vec<&[u8]> {
-    let (block1_size, block1_count, block2_size, block2_count) =
-        version.data_codewords_per_block(ec_level);
+    split_blocks(data, version.data_codewords_per_block(ec_level))
+}
+
+fn split_blocks(data: &[u8], block_layout: (usize, usize, usize, usize)) -> Vec<&[u8]> {
+    let (block1_size, block1_count, block2_size, block2_count) = block_layout;
 
     let total_blocks = block1_count + block2_count;
     let total_block1_size = block1_size * block1_count;
@@ -37,12 +46,52 @@ pub fn blockify(data: &[u8], version: Version, ec_level: ECLevel) -> Vec<&[u8]>
     data_blocks
 }
 
+// Blocks come in at most two lengths, so the first min length codewords of block j land at
+// i * n + j and the tail of the k-th longer block at tail_start + i * long_count + k
+pub fn interleave<T: Copy, V: Deref<Target = [T]>>(blocks: &[V]) -> Vec<T> {
+    let n = blocks.len();
+    let min_block_size = blocks.iter().map(|b| b.len()).min().expect("Blocks is empty");
+    let max_block_size = blocks.iter().map(|b| b.len()).max().expect("Blocks is empty");
+    debug_assert!(
+        blocks.iter().all(|b| b.len() == min_block_size || b.len() == max_block_size),
+        "Blocks should have at most two lengths"
+    );
+
+    let total_size = blocks.iter().map(|b| b.len()).sum::<usize>();
+    let Some(&filler) = blocks.iter().find_map(|b| b.first()) else {
+        return Vec::new();
+    };
+    let mut res = vec![filler; total_size];
+
+    for (j, b) in blocks.iter().enumerate() {
+        for (dst, &cw) in res[j..].iter_mut().step_by(n).zip(&b[..min_block_size]) {
+            *dst = cw;
+        }
+    }
+
+    let tail_start = min_block_size * n;
+    let long_count = (total_size - tail_start) / (max_block_size - min_block_size).max(1);
+    let long_blocks = blocks.iter().filter(|b| b.len() > min_block_size);
+    for (k, b) in long_blocks.enumerate() {
+        let tail = res[tail_start + k..].iter_mut().step_by(long_count);
+        for (dst, &cw) in tail.zip(&b[min_block_size..]) {
+            *dst = cw;
+        }
+    }
+    res
+}
+
 // Performs polynomial long division with data polynomial(num)
 // and generator polynomial(den) to compute remainder polynomial,
 // the coefficients of which are the ecc
 fn ecc_per_block(block: &[u8], ecc_count: usize) -> Vec<u8> {
+    ecc_with_generator(block, GENERATOR_POLYNOMIALS[ecc_count])
+}
+
+// Generator entries are logs of the coefficients below the implied leading one, one per ecc codeword
+fn ecc_with_generator(block: &[u8], gen_poly: &[u8]) -> Vec<u8> {
     let len = block.len();
-    let gen_poly = GENERATOR_POLYNOMIALS[ecc_count];
+    let ecc_count = gen_poly.len();
 
     let mut res = block.to_vec();
     res.resize(len + ecc_count, 0);
@@ -53,28 +102,55 @@ fn ecc_per_block(block: &[u8], ecc_count: usize) -> Vec<u8> {
             continue;
         }
 
-        let log_lead_coeff = LOG_TABLE[lead_coeff] as usize;
+        let log_lead_coeff = log(lead_coeff as u8) as usize;
         for (u, v) in res[i + 1..].iter_mut().zip(gen_poly.iter()) {
-            let mut log_sum = *v as usize + log_lead_coeff;
+            let log_sum = *v as usize + log_lead_coeff;
             debug_assert!(log_sum < 510, "Log sum has crossed 510: {log_sum}");
-            if log_sum >= 255 {
-                log_sum -= 255;
-            }
-            *u ^= EXP_TABLE[log_sum];
+            *u ^= exp(log_sum);
         }
     }
 
     res.split_off(len)
 }
 
+// Block layout and generator polynomial of a version and ec level, looked up once and reused
+// across payloads, e.g. when encoding many codes of the same size
+#[derive(Debug, Clone)]
+pub struct Encoder {
+    block_layout: (usize, usize, usize, usize),
+    gen_poly: &'static [u8],
+}
+
+impl Encoder {
+    pub fn new(version: Version, ec_level: ECLevel) -> QRResult<Self> {
+        if !matches!(version, Version::Micro(1..=4) | Version::Normal(1..=40)) {
+            return Err(QRError::InvalidVersion);
+        }
+        if !version.supports_ec_level(ec_level) {
+            return Err(QRError::UnsupportedECLevel(version, ec_level));
+        }
+
+        Ok(Self {
+            block_layout: version.data_codewords_per_block(ec_level),
+            gen_poly: GENERATOR_POLYNOMIALS[version.ecc_per_block(ec_level)],
+        })
+    }
+
+    // Interleaved data codewords followed by interleaved ecc of one channel, as drawn into the
+    // encoding region
+    pub fn encode_payload(&self, data: &[u8]) -> Vec<u8> {
+        let data_blocks = split_blocks(data, self.block_layout);
+        let ecc_blocks =
+            data_blocks.iter().map(|b| ecc_with_generator(b, self.gen_poly)).collect::<Vec<_>>();
+
+        let mut payload = interleave(&data_blocks);
+        payload.extend(interleave(&ecc_blocks));
+        payload
+    }
+}
+
 pub fn error_correction_capacity(version: Version, ec_level: ECLevel) -> usize {
-    let p = match (version, ec_level) {
-        (Version::Micro(2) | Version::Normal(1), ECLevel::L) => 3,
-        (Version::Micro(_) | Version::Normal(2), ECLevel::L)
-        | (Version::Micro(2) | Version::Normal(1), ECLevel::M) => 2,
-        (Version::Normal(1), _) | (Version::Normal(3), ECLevel::L) => 1,
-        _ => 0,
-    };
+    let p = misdecode_protection(version, ec_level);
 
     let ec_bytes_per_block = version.ecc_per_block(ec_level);
     let (_, count1, _, count2) = version.data_codewords_per_block(ec_level);
@@ -83,14 +159,192 @@ pub fn error_correction_capacity(version: Version, ec_level: ECLevel) -> usize {
     (ec_bytes - p) / 2
 }
 
+// Reed-Solomon corrects each block on its own, so this is what any one block can lose. Symbols
+// that hold back codewords for misdecode protection all have a single block
+pub fn block_error_correction_capacity(version: Version, ec_level: ECLevel) -> usize {
+    (version.ecc_per_block(ec_level) - misdecode_protection(version, ec_level)) / 2
+}
+
+fn misdecode_protection(version: Version, ec_level: ECLevel) -> usize {
+    match (version, ec_level) {
+        (Version::Micro(2) | Version::Normal(1), ECLevel::L) => 3,
+        (Version::Micro(_) | Version::Normal(2), ECLevel::L)
+        | (Version::Micro(2) | Version::Normal(1), ECLevel::M) => 2,
+        (Version::Normal(1), _) | (Version::Normal(3), ECLevel::L) => 1,
+        _ => 0,
+    }
+}
+
+// Block of the codeword at index i in one channel's payload, data then ecc, as laid out by
+// interleave
+pub fn codeword_block(i: usize, version: Version, ec_level: ECLevel) -> usize {
+    let (size1, count1, size2, count2) = version.data_codewords_per_block(ec_level);
+    let total_blocks = count1 + count2;
+    let data_len = size1 * count1 + size2 * count2;
+    match i {
+        i if i < size1 * total_blocks => i % total_blocks,
+        i if i < data_len => count1 + (i - size1 * total_blocks) % count2,
+        i => (i - data_len) % total_blocks,
+    }
+}
+
 #[cfg(test)]
 mod ec_tests {
+    use test_case::test_case;
 
     use crate::{
-        ec::{ecc, ecc_per_block},
-        metadata::{ECLevel, Version},
+        ec::{codeword_block, ecc, ecc_per_block, interleave, Encoder},
+        error::QRError,
+        metadata::{ECLevel, Palette, Version},
     };
 
+    fn free_payload(data: &[u8], version: Version, ec_level: ECLevel) -> Vec<u8> {
+        let (data_blocks, ecc_blocks) = ecc(data, version, ec_level);
+        let mut payload = interleave(&data_blocks);
+        payload.extend(interleave(&ecc_blocks));
+        payload
+    }
+
+    #[test]
+    fn test_interleave() {
+        let blocks = vec![vec![1, 2, 3], vec![4, 5, 6], vec![7, 8, 9, 0]];
+        let interleaved = interleave(&blocks);
+        let exp_interleaved = vec![1, 4, 7, 2, 5, 8, 3, 6, 9, 0];
+        assert_eq!(interleaved, exp_interleaved);
+    }
+
+    #[test_case(Version::Micro(3), ECLevel::M)]
+    #[test_case(Version::Normal(1), ECLevel::L)]
+    #[test_case(Version::Normal(5), ECLevel::Q)]
+    #[test_case(Version::Normal(40), ECLevel::H)]
+    fn test_codeword_block(version: Version, ec_level: ECLevel) {
+        let (size1, count1, size2, count2) = version.data_codewords_per_block(ec_level);
+        let sizes = [size1].repeat(count1).into_iter().chain([size2].repeat(count2));
+        let data_blocks = sizes.map(|s| vec![0; s]).collect::<Vec<_>>();
+        let tag = |blocks: &[Vec<usize>]| {
+            blocks.iter().enumerate().map(|(i, b)| vec![i; b.len()]).collect::<Vec<_>>()
+        };
+        let ecc_blocks = vec![vec![0; version.ecc_per_block(ec_level)]; count1 + count2];
+
+        let mut exp_blocks = interleave(&tag(&data_blocks));
+        exp_blocks.extend(interleave(&tag(&ecc_blocks)));
+        assert_eq!(exp_blocks.len(), version.channel_codewords());
+        for (i, exp) in exp_blocks.into_iter().enumerate() {
+            assert_eq!(codeword_block(i, version, ec_level), exp, "{i}");
+        }
+    }
+
+    // cargo test --release --features bench bench_interleave -- --nocapture
+    #[cfg(feature = "bench")]
+    #[test_case(ECLevel::L)]
+    #[test_case(ECLevel::M)]
+    #[test_case(ECLevel::Q)]
+    #[test_case(ECLevel::H)]
+    fn bench_interleave(ec_level: ECLevel) {
+        use std::{hint::black_box, time::Instant};
+
+        // Previous implementation, checking every block against every column
+        fn naive_interleave(blocks: &[Vec<u8>]) -> Vec<u8> {
+            let max_block_size = blocks.iter().map(|b| b.len()).max().unwrap();
+            let mut res = Vec::new();
+            for i in 0..max_block_size {
+                for b in blocks {
+                    if i < b.len() {
+                        res.push(b[i]);
+                    }
+                }
+            }
+            res
+        }
+
+        let (size1, count1, size2, count2) = Version::Normal(40).data_codewords_per_block(ec_level);
+        let blocks = (0..count1)
+            .map(|j| vec![j as u8; size1])
+            .chain((0..count2).map(|j| vec![!j as u8; size2]))
+            .collect::<Vec<_>>();
+        assert_eq!(interleave(&blocks), naive_interleave(&blocks));
+
+        let runs = 10_000;
+        let start = Instant::now();
+        for _ in 0..runs {
+            black_box(interleave(black_box(&blocks)));
+        }
+        let fast = start.elapsed();
+        let start = Instant::now();
+        for _ in 0..runs {
+            black_box(naive_interleave(black_box(&blocks)));
+        }
+        let naive = start.elapsed();
+
+        let bytes = (runs * (size1 * count1 + size2 * count2)) as f64;
+        println!(
+            "{ec_level:?}: interleave {:.0} MB/s, naive {:.0} MB/s",
+            bytes / fast.as_secs_f64() / 1e6,
+            bytes / naive.as_secs_f64() / 1e6
+        );
+    }
+
+    #[test_case(Version::Normal(1), ECLevel::M)]
+    #[test_case(Version::Normal(5), ECLevel::Q)]
+    #[test_case(Version::Normal(21), ECLevel::H)]
+    #[test_case(Version::Normal(40), ECLevel::L)]
+    #[test_case(Version::Micro(3), ECLevel::L)]
+    fn test_encoder_matches_ecc(version: Version, ec_level: ECLevel) {
+        let data_len = version.bit_capacity(ec_level, Palette::Mono).div_ceil(8);
+        let data = (0..data_len).map(|i| (i * 37 + 11) as u8).collect::<Vec<_>>();
+
+        let encoder = Encoder::new(version, ec_level).unwrap();
+        assert_eq!(encoder.encode_payload(&data), free_payload(&data, version, ec_level));
+    }
+
+    #[test_case(Version::Normal(0), ECLevel::L, QRError::InvalidVersion)]
+    #[test_case(Version::Normal(41), ECLevel::L, QRError::InvalidVersion)]
+    #[test_case(Version::Micro(5), ECLevel::L, QRError::InvalidVersion)]
+    #[test_case(
+        Version::Micro(1),
+        ECLevel::M,
+        QRError::UnsupportedECLevel(Version::Micro(1), ECLevel::M)
+    )]
+    #[test_case(
+        Version::Micro(4),
+        ECLevel::H,
+        QRError::UnsupportedECLevel(Version::Micro(4), ECLevel::H)
+    )]
+    fn test_encoder_invalid(version: Version, ec_level: ECLevel, exp_err: QRError) {
+        assert_eq!(Encoder::new(version, ec_level).unwrap_err(), exp_err);
+    }
+
+    // cargo test --release --features bench bench_encoder -- --nocapture
+    #[cfg(feature = "bench")]
+    #[test_case(Version::Normal(2), ECLevel::L)]
+    #[test_case(Version::Normal(10), ECLevel::M)]
+    #[test_case(Version::Normal(40), ECLevel::H)]
+    fn bench_encoder(version: Version, ec_level: ECLevel) {
+        use std::{hint::black_box, time::Instant};
+
+        let data_len = version.bit_capacity(ec_level, Palette::Mono).div_ceil(8);
+        let data = (0..data_len).map(|i| (i * 37 + 11) as u8).collect::<Vec<_>>();
+
+        let runs = 10_000;
+        let encoder = Encoder::new(version, ec_level).unwrap();
+        let start = Instant::now();
+        for _ in 0..runs {
+            black_box(encoder.encode_payload(black_box(&data)));
+        }
+        let cached = start.elapsed();
+        let start = Instant::now();
+        for _ in 0..runs {
+            black_box(free_payload(black_box(&data), version, ec_level));
+        }
+        let free = start.elapsed();
+
+        println!(
+            "{version:?} {ec_level:?}: encoder {:.2} us/call, ecc {:.2} us/call",
+            cached.as_secs_f64() * 1e6 / runs as f64,
+            free.as_secs_f64() * 1e6 / runs as f64
+        );
+    }
+
     #[test]
     fn test_poly_mod_1() {
         let res = ecc_per_block(b" [\x0bx\xd1r\xdcMC@\xec\x11\xec\x11\xec\x11", 10);
@@ -137,18 +391,18 @@ mod ec_tests {
 // Rectifier
 //------------------------------------------------------------------------------
 
-pub fn rectify(data_blocks: &[Vec<u8>], ecc_blocks: &[Vec<u8>]) -> Vec<u8> {
+pub fn rectify(data_blocks: &[Vec<u8>], ecc_blocks: &[Vec<u8>]) -> QRResult<Vec<u8>> {
     let total_size = data_blocks.iter().map(|b| b.len()).sum::<usize>();
     let mut res = Vec::with_capacity(total_size);
     for (db, eb) in data_blocks.iter().zip(ecc_blocks) {
-        res.extend(rectify_block(db.to_vec(), eb.to_vec()));
+        res.extend(rectify_block(db.to_vec(), eb.to_vec())?);
     }
-    res
+    Ok(res)
 }
 
-pub fn rectify_block(data: Vec<u8>, ecc: Vec<u8>) -> Vec<u8> {
+pub fn rectify_block(data: Vec<u8>, ecc: Vec<u8>) -> QRResult<Vec<u8>> {
     let combined = ecc.iter().rev().chain(data.iter().rev());
-    syndromes(combined, ecc.len()).map(|_| data).unwrap()
+    syndromes(combined, ecc.len()).map(|_| data)
 }
 
 // Computes syndromes for a block
@@ -162,9 +416,7 @@ where
             if *c == 0 {
                 continue;
             }
-            let log_c = LOG_TABLE[*c as usize];
-            let log_sum = (i * j + log_c as usize) % 255;
-            *e ^= EXP_TABLE[log_sum];
+            *e ^= exp(i * j + log(*c) as usize);
         }
     }
 
@@ -175,56 +427,100 @@ where
     }
 }
 
-// Rectifier for format and version infos
+// Rectifier for format and version infos. The nearest valid number is only trusted when it is
+// within capacity and unique, a tie means the damage can't be attributed to either
 pub fn rectify_info(info: u32, valid_numbers: &[u32], err_capacity: u32) -> QRResult<u32> {
-    let res = *valid_numbers.iter().min_by_key(|&n| (info ^ n).count_ones()).unwrap();
+    if valid_numbers.contains(&info) {
+        return Ok(info);
+    }
 
-    if (info ^ res).count_ones() <= err_capacity {
-        Ok(res)
-    } else {
-        Err(QRError::InvalidInfo)
+    let mut nearest = None;
+    let mut tied = false;
+    for &n in valid_numbers {
+        let dist = (info ^ n).count_ones();
+        match nearest {
+            _ if dist > err_capacity => {}
+            Some((_, d)) if dist > d => {}
+            Some((_, d)) if dist == d => tied = true,
+            _ => {
+                nearest = Some((n, dist));
+                tied = false;
+            }
+        }
+    }
+
+    match nearest {
+        Some((n, _)) if !tied => Ok(n),
+        _ => Err(QRError::InvalidInfo),
+    }
+}
+
+#[cfg(test)]
+mod rectify_info_tests {
+    use super::rectify_info;
+    use crate::{
+        error::QRError,
+        metadata::{FORMAT_ERROR_CAPACITY, FORMAT_INFOS_QR},
+    };
+
+    #[test]
+    fn test_rectify_info_exact() {
+        for &f in FORMAT_INFOS_QR.iter() {
+            assert_eq!(rectify_info(f, &FORMAT_INFOS_QR, FORMAT_ERROR_CAPACITY), Ok(f));
+        }
+    }
+
+    #[test]
+    fn test_rectify_info_within_capacity() {
+        let info = FORMAT_INFOS_QR[5] ^ 0b100_0000_0100_0001;
+        assert_eq!(
+            rectify_info(info, &FORMAT_INFOS_QR, FORMAT_ERROR_CAPACITY),
+            Ok(FORMAT_INFOS_QR[5])
+        );
+    }
+
+    #[test]
+    fn test_rectify_info_beyond_capacity() {
+        let info = FORMAT_INFOS_QR[5] ^ 0b100_0010_0100_0001;
+        assert_eq!(
+            rectify_info(info, &FORMAT_INFOS_QR, FORMAT_ERROR_CAPACITY),
+            Err(QRError::InvalidInfo)
+        );
+    }
+
+    #[test]
+    fn test_rectify_info_tie() {
+        let valid_numbers = [0b0000_0000, 0b0011_0000, 0b1111_1111];
+        assert_eq!(rectify_info(0b0001_0000, &valid_numbers, 1), Err(QRError::InvalidInfo));
+        assert_eq!(rectify_info(0b0111_0000, &valid_numbers, 1), Ok(0b0011_0000));
+    }
+
+    // Format numbers are at least 7 apart, so a value halfway between two at an even distance is
+    // beyond capacity from both
+    #[test]
+    fn test_rectify_info_format_equidistant() {
+        let (a, b) = FORMAT_INFOS_QR
+            .iter()
+            .flat_map(|&a| FORMAT_INFOS_QR.iter().map(move |&b| (a, b)))
+            .find(|(a, b)| a != b && (a ^ b).count_ones() % 2 == 0)
+            .unwrap();
+
+        let diff = a ^ b;
+        let half_dist = diff.count_ones() / 2;
+        let half = (0..15).filter(|i| diff >> i & 1 == 1).take(half_dist as usize);
+        let info = half.fold(a, |acc, i| acc ^ (1 << i));
+
+        assert_eq!((info ^ a).count_ones(), (info ^ b).count_ones());
+        assert_eq!(
+            rectify_info(info, &FORMAT_INFOS_QR, FORMAT_ERROR_CAPACITY),
+            Err(QRError::InvalidInfo)
+        );
     }
 }
 
 // Global constants
 //------------------------------------------------------------------------------
 
-static EXP_TABLE: &[u8] = b"\
-\x01\x02\x04\x08\x10\x20\x40\x80\x1d\x3a\x74\xe8\xcd\x87\x13\x26\
-\x4c\x98\x2d\x5a\xb4\x75\xea\xc9\x8f\x03\x06\x0c\x18\x30\x60\xc0\
-\x9d\x27\x4e\x9c\x25\x4a\x94\x35\x6a\xd4\xb5\x77\xee\xc1\x9f\x23\
-\x46\x8c\x05\x0a\x14\x28\x50\xa0\x5d\xba\x69\xd2\xb9\x6f\xde\xa1\
-\x5f\xbe\x61\xc2\x99\x2f\x5e\xbc\x65\xca\x89\x0f\x1e\x3c\x78\xf0\
-\xfd\xe7\xd3\xbb\x6b\xd6\xb1\x7f\xfe\xe1\xdf\xa3\x5b\xb6\x71\xe2\
-\xd9\xaf\x43\x86\x11\x22\x44\x88\x0d\x1a\x34\x68\xd0\xbd\x67\xce\
-\x81\x1f\x3e\x7c\xf8\xed\xc7\x93\x3b\x76\xec\xc5\x97\x33\x66\xcc\
-\x85\x17\x2e\x5c\xb8\x6d\xda\xa9\x4f\x9e\x21\x42\x84\x15\x2a\x54\
-\xa8\x4d\x9a\x29\x52\xa4\x55\xaa\x49\x92\x39\x72\xe4\xd5\xb7\x73\
-\xe6\xd1\xbf\x63\xc6\x91\x3f\x7e\xfc\xe5\xd7\xb3\x7b\xf6\xf1\xff\
-\xe3\xdb\xab\x4b\x96\x31\x62\xc4\x95\x37\x6e\xdc\xa5\x57\xae\x41\
-\x82\x19\x32\x64\xc8\x8d\x07\x0e\x1c\x38\x70\xe0\xdd\xa7\x53\xa6\
-\x51\xa2\x59\xb2\x79\xf2\xf9\xef\xc3\x9b\x2b\x56\xac\x45\x8a\x09\
-\x12\x24\x48\x90\x3d\x7a\xf4\xf5\xf7\xf3\xfb\xeb\xcb\x8b\x0b\x16\
-\x2c\x58\xb0\x7d\xfa\xe9\xcf\x83\x1b\x36\x6c\xd8\xad\x47\x8e\x01";
-
-static LOG_TABLE: &[u8] = b"\
-\xff\x00\x01\x19\x02\x32\x1a\xc6\x03\xdf\x33\xee\x1b\x68\xc7\x4b\
-\x04\x64\xe0\x0e\x34\x8d\xef\x81\x1c\xc1\x69\xf8\xc8\x08\x4c\x71\
-\x05\x8a\x65\x2f\xe1\x24\x0f\x21\x35\x93\x8e\xda\xf0\x12\x82\x45\
-\x1d\xb5\xc2\x7d\x6a\x27\xf9\xb9\xc9\x9a\x09\x78\x4d\xe4\x72\xa6\
-\x06\xbf\x8b\x62\x66\xdd\x30\xfd\xe2\x98\x25\xb3\x10\x91\x22\x88\
-\x36\xd0\x94\xce\x8f\x96\xdb\xbd\xf1\xd2\x13\x5c\x83\x38\x46\x40\
-\x1e\x42\xb6\xa3\xc3\x48\x7e\x6e\x6b\x3a\x28\x54\xfa\x85\xba\x3d\
-\xca\x5e\x9b\x9f\x0a\x15\x79\x2b\x4e\xd4\xe5\xac\x73\xf3\xa7\x57\
-\x07\x70\xc0\xf7\x8c\x80\x63\x0d\x67\x4a\xde\xed\x31\xc5\xfe\x18\
-\xe3\xa5\x99\x77\x26\xb8\xb4\x7c\x11\x44\x92\xd9\x23\x20\x89\x2e\
-\x37\x3f\xd1\x5b\x95\xbc\xcf\xcd\x90\x87\x97\xb2\xdc\xfc\xbe\x61\
-\xf2\x56\xd3\xab\x14\x2a\x5d\x9e\x84\x3c\x39\x53\x47\x6d\x41\xa2\
-\x1f\x2d\x43\xd8\xb7\x7b\xa4\x76\xc4\x17\x49\xec\x7f\x0c\x6f\xf6\
-\x6c\xa1\x3b\x52\x29\x9d\x55\xaa\xfb\x60\x86\xb1\xbb\xcc\x3e\x5a\
-\xcb\x59\x5f\xb0\x9c\xa9\xa0\x51\x0b\xf5\x16\xeb\x7a\x75\x2c\xd7\
-\x4f\xae\xd5\xe9\xe6\xe7\xad\xe8\x74\xd6\xf4\xea\xa8\x50\x58\xaf";
-
 static GENERATOR_POLYNOMIALS: [&[u8]; 70] = [
     b"",
     b"\x00",
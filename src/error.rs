@@ -1,9 +1,12 @@
-use std::fmt::{Debug, Display, Error, Formatter};
+use alloc::string::String;
+use core::fmt::{Debug, Display, Error, Formatter};
+
+use crate::metadata::{ECLevel, Version};
 
 // Error
 //------------------------------------------------------------------------------
 
-#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+#[derive(Debug, PartialEq, Eq, Clone)]
 pub enum QRError {
     // QR builder
     EmptyData,
@@ -13,23 +16,45 @@ pub enum QRError {
     InvalidECLevel,
     InvalidPalette,
     InvalidColor,
-    InvalidChar,
+    InvalidChar(usize),
     InvalidMaskingPattern,
+    InvalidEci,
+    UnsupportedECLevel(Version, ECLevel),
+    RoundTripMismatch,
 
     // QR reader
+    NoSymbolFound,
+    DecodeFailed,
     ErrorDetected([u8; 64]),
     InvalidInfo,
     InvalidFormatInfo,
     InvalidVersionInfo,
+    NoVersionInfo,
     FinderMismatch,
     TimingMismatch,
     AlignmentMismatch,
     InvalidUTF8Sequence,
+    InvalidBufferSize,
+    Image(String),
+
+    // QR serialization
+    InvalidBytes,
+    InvalidMatrix,
+
+    // QR render
+    LogoTooLarge,
+    SmoothingTooStrong,
+    BufferTooSmall,
 }
 
 impl Display for QRError {
     fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
-        let msg = match *self {
+        let msg = match self {
+            Self::InvalidChar(offset) => return write!(f, "Invalid character at byte {offset}"),
+            Self::Image(err) => return write!(f, "Image error: {err}"),
+            Self::UnsupportedECLevel(version, ec_level) => {
+                return write!(f, "Ec level {ec_level:?} is not supported by version {version:?}")
+            }
             Self::EmptyData => "Empty data",
             Self::DataTooLong => "Data too long",
             Self::CapacityOverflow => "Capacity overflow",
@@ -37,21 +62,52 @@ impl Display for QRError {
             Self::InvalidECLevel => "Invalid error correction level",
             Self::InvalidPalette => "Invalid color palette",
             Self::InvalidColor => "Invalid color",
-            Self::InvalidChar => "Invalid character",
             Self::InvalidMaskingPattern => "Invalid masking pattern",
+            Self::InvalidEci => "Eci designator exceeds 999999",
+            Self::RoundTripMismatch => "Built code doesn't decode to its input",
+            Self::NoSymbolFound => "No QR code found in image",
+            Self::DecodeFailed => "QR code found but could not be decoded",
             Self::ErrorDetected(_) => "Error detected in data",
             Self::InvalidInfo => "Invalid info",
             Self::InvalidFormatInfo => "Invalid format info detected",
             Self::InvalidVersionInfo => "Invalid version info detected",
+            Self::NoVersionInfo => "Version has no version info region",
             Self::FinderMismatch => "Finder color mismatch",
             Self::TimingMismatch => "Timing color mismatch",
             Self::AlignmentMismatch => "Alignment color mismatch",
             Self::InvalidUTF8Sequence => "Invalid UTF8 sequence",
+            Self::InvalidBufferSize => "Buffer size doesn't match image dimensions",
+            Self::InvalidBytes => "Invalid serialized qr bytes",
+            Self::InvalidMatrix => "Module matrix size does not match version",
+            Self::LogoTooLarge => "Logo covers more than error correction can recover",
+            Self::SmoothingTooStrong => "Smoothing blurs finder patterns beyond recognition",
+            Self::BufferTooSmall => "Framebuffer too small for rendered code",
         };
         f.write_str(msg)
     }
 }
 
-impl std::error::Error for QRError {}
+impl core::error::Error for QRError {}
+
+#[cfg(feature = "std")]
+impl From<image::ImageError> for QRError {
+    fn from(err: image::ImageError) -> Self {
+        Self::Image(err.to_string())
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<std::io::Error> for QRError {
+    fn from(err: std::io::Error) -> Self {
+        Self::Image(err.to_string())
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<png::EncodingError> for QRError {
+    fn from(err: png::EncodingError) -> Self {
+        Self::Image(err.to_string())
+    }
+}
 
 pub type QRResult<T> = Result<T, QRError>;
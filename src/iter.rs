@@ -52,11 +52,15 @@ impl Iterator for EncRegionIter {
 
 #[cfg(test)]
 mod iter_tests {
+    use test_case::test_case;
+
+    #[cfg(feature = "std")]
+    use crate::deqr::DeQR;
     use crate::{
         builder::QRBuilder,
         iter::EncRegionIter,
-        metadata::{ECLevel, Version},
-        qr::Module,
+        metadata::{ECLevel, Palette, Version},
+        qr::{Module, QR},
     };
 
     #[test]
@@ -72,8 +76,50 @@ mod iter_tests {
                 .filter(|(r, c)| matches!(qr.get(*r, *c), Module::Data(_)))
                 .count()
                 / 8;
-            let exp_codewords = version.total_codewords();
+            let exp_codewords = version.channel_codewords();
             assert_eq!(total_codewords, exp_codewords);
         }
     }
+
+    // The drawer and the reader both walk EncRegionIter, so a payload drawn by one is extracted
+    // unchanged by the other
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_enc_region_iter_draw_extract_agree() {
+        for v in 1..=10 {
+            let version = Version::Normal(v);
+            let qr = QRBuilder::new(b"Hello, world!").version(version).build().unwrap();
+
+            let mut deqr = DeQR::from_qr(&qr);
+            let (_, mask_pattern) = deqr.read_format_info().unwrap();
+            if v >= 7 {
+                deqr.read_version_info().unwrap();
+            }
+            deqr.mark_all_function_patterns();
+            deqr.unmask(mask_pattern);
+
+            assert_eq!(deqr.extract_payload(version), qr.codewords(), "Version {v}");
+        }
+    }
+
+    // M1 and M3 end with a 4 bit data codeword
+    #[test_case(1, 36)]
+    #[test_case(2, 80)]
+    #[test_case(3, 132)]
+    #[test_case(4, 192)]
+    fn test_enc_region_iter_micro(v: usize, exp_data_modules: usize) {
+        let version = Version::Micro(v);
+        let mut qr = QR::new(version, ECLevel::L, Palette::Mono);
+        qr.draw_all_function_patterns();
+        qr.draw_encoding_region(&vec![0; version.channel_codewords()]);
+
+        let coords = EncRegionIter::new(version).collect::<Vec<_>>();
+        let data_modules =
+            coords.iter().filter(|(r, c)| matches!(qr.get(*r, *c), Module::Data(_))).count();
+        assert_eq!(data_modules, exp_data_modules);
+        assert_eq!(data_modules.div_ceil(8), version.channel_codewords());
+
+        // Timing column is never visited
+        assert!(coords.iter().all(|(_, c)| *c > 0));
+    }
 }
@@ -0,0 +1,33 @@
+// TODO: Remember to remove unused_variables & dead_code
+#![allow(clippy::items_after_test_module, unused_variables, dead_code, mixed_script_confusables)]
+// Without std only the encoding core is built: builder, ecc, masking and the module grid
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
+// Build progress goes to stdout, and is dropped without std
+macro_rules! progress {
+    ($($arg:tt)*) => {{
+        #[cfg(feature = "std")]
+        println!($($arg)*);
+    }};
+}
+
+pub mod bits;
+pub mod builder;
+mod codec;
+#[cfg(feature = "std")]
+mod deqr;
+mod ec;
+pub mod error;
+mod iter;
+pub mod mask;
+pub mod metadata;
+pub mod qr;
+#[cfg(feature = "std")]
+pub mod reader;
+#[cfg(all(feature = "std", any(test, feature = "testkit")))]
+pub mod testkit;
+
+pub use codec::{Mode, SegmentStat};
+pub use ec::Encoder;
@@ -1,22 +1,14 @@
-// TODO: Remember to remove unused_variables & dead_code
-#![allow(clippy::items_after_test_module, unused_variables, dead_code, mixed_script_confusables)]
+// TODO: Remember to remove unused_variables
+#![allow(unused_variables)]
 
 use std::error::Error;
 
-use builder::QRBuilder;
-use metadata::{ECLevel, Version};
-use reader::QRReader;
-
-mod builder;
-mod codec;
-mod deqr;
-mod ec;
-mod error;
-mod iter;
-pub mod mask;
-pub mod metadata;
-pub mod qr;
-mod reader;
+#[cfg(feature = "std")]
+use qr_pro_max::reader::QRReader;
+use qr_pro_max::{
+    builder::QRBuilder,
+    metadata::{ECLevel, Version},
+};
 
 fn main() -> Result<(), Box<dyn Error>> {
     let data = "Hello, world! 🌎";
@@ -31,8 +23,11 @@ fn main() -> Result<(), Box<dyn Error>> {
         .to_str(1);
     println!("{qr}");
 
-    let extracted_data = QRReader::read_from_str(&qr, version).unwrap();
-    println!("Extracted Data: {extracted_data}");
+    #[cfg(feature = "std")]
+    {
+        let extracted_data = QRReader::read_from_str(&qr, version).unwrap();
+        println!("Extracted Data: {extracted_data}");
+    }
 
     // FIXME: Remove
     // let path = "assets/test_image_1.png";
@@ -1,8 +1,9 @@
-use std::ops::Deref;
+use alloc::{boxed::Box, vec, vec::Vec};
+use core::ops::Deref;
 
 use crate::{
-    metadata::{Color, Version},
-    qr::QR,
+    metadata::{Color, ECLevel, Palette, Version},
+    qr::{Module, QR},
 };
 
 #[derive(Debug, PartialEq, Eq, Copy, Clone, PartialOrd, Ord)]
@@ -72,41 +73,141 @@ impl MaskPattern {
             _ => unreachable!(),
         }
     }
+
+    // Micro symbols number their 4 masks separately, as normal patterns 1, 4, 6 and 7
+    pub fn mask_functions_for(self, version: Version) -> fn(i16, i16) -> bool {
+        match version {
+            Version::Micro(_) => {
+                debug_assert!(*self < 4, "Invalid micro pattern");
+                MaskPattern([1, 4, 6, 7][*self as usize]).mask_functions()
+            }
+            Version::Normal(_) => self.mask_functions(),
+        }
+    }
+
+    // Row major width * width grid of the cells this mask flips in a symbol of the given version.
+    // Function patterns, format and version info are never masked, so they stay false
+    pub fn render_preview(self, version: Version) -> Vec<bool> {
+        let w = version.width();
+        let mut qr = QR::from_matrix(version, ECLevel::L, Palette::Mono, &vec![false; w * w])
+            .expect("Blank matrix should match the symbol width");
+        qr.mask(self);
+
+        let w = w as i16;
+        (0..w)
+            .flat_map(|r| (0..w).map(move |c| (r, c)))
+            .map(|(r, c)| matches!(qr.get(r, c), Module::Data(Color::Dark)))
+            .collect()
+    }
+}
+
+// Scales each penalty rule before summing, e.g. a high block weight steers selection away from
+// large dark areas. The default weighs every rule by 1 as the spec does
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub struct MaskSelectionConfig {
+    pub adjacent_weight: u32,
+    pub block_weight: u32,
+    pub finder_weight: u32,
+    pub balance_weight: u32,
+}
+
+impl Default for MaskSelectionConfig {
+    fn default() -> Self {
+        Self { adjacent_weight: 1, block_weight: 1, finder_weight: 1, balance_weight: 1 }
+    }
 }
 
 pub fn apply_best_mask(qr: &mut QR) -> MaskPattern {
-    let best_mask = (0..8)
-        .min_by_key(|m| {
-            let mut qr = qr.clone();
-            qr.mask(MaskPattern(*m));
-            compute_total_penalty(&qr)
-        })
-        .expect("Should return atleast 1 mask");
-    let best_mask = MaskPattern(best_mask);
+    apply_best_mask_with_config(qr, &MaskSelectionConfig::default())
+}
+
+pub fn apply_best_mask_with_config(qr: &mut QR, config: &MaskSelectionConfig) -> MaskPattern {
+    let best_mask = compute_mask_penalties_with_config(qr, config)[0].0;
     qr.mask(best_mask);
     best_mask
 }
 
+// Penalty of every mask pattern applied to an unmasked qr, sorted from best to worst. Ties keep
+// the pattern order, so the first entry is always the mask apply_best_mask picks. Micro has 4
+// patterns, normal has 8
+pub fn compute_mask_penalties(qr: &QR) -> Vec<(MaskPattern, u32)> {
+    compute_mask_penalties_with_config(qr, &MaskSelectionConfig::default())
+}
+
+// Like compute_mask_penalties, with each rule scaled by its weight in the config
+pub fn compute_mask_penalties_with_config(
+    qr: &QR,
+    config: &MaskSelectionConfig,
+) -> Vec<(MaskPattern, u32)> {
+    let mask_count = if let Version::Micro(_) = qr.version() { 4 } else { 8 };
+    let mut penalties = (0..mask_count)
+        .map(|m| {
+            let mask = MaskPattern(m);
+            let mut qr = qr.clone();
+            qr.mask(mask);
+            (mask, compute_penalty_breakdown(&qr).weighted_sum(config))
+        })
+        .collect::<Vec<_>>();
+    penalties.sort_by_key(|(_, p)| *p);
+    penalties
+}
+
 pub fn apply_mask(qr: &mut QR, pattern: MaskPattern) -> MaskPattern {
     qr.mask(pattern);
     pattern
 }
 
+// Normal symbols are scored by the first five rules, micro symbols only by edge, so the other
+// side is always 0
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub struct PenaltyBreakdown {
+    pub adjacent: u32,
+    pub block: u32,
+    pub finder_h: u32,
+    pub finder_v: u32,
+    pub balance: u32,
+    pub edge: u32,
+}
+
+impl PenaltyBreakdown {
+    pub fn sum(&self) -> u32 {
+        self.adjacent + self.block + self.finder_h + self.finder_v + self.balance + self.edge
+    }
+
+    // Weights only apply to the normal rules, micro has a single rule to rank by. Saturates at
+    // u32::MAX rather than overflowing on large weights
+    pub fn weighted_sum(&self, config: &MaskSelectionConfig) -> u32 {
+        self.adjacent
+            .saturating_mul(config.adjacent_weight)
+            .saturating_add(self.block.saturating_mul(config.block_weight))
+            .saturating_add((self.finder_h + self.finder_v).saturating_mul(config.finder_weight))
+            .saturating_add(self.balance.saturating_mul(config.balance_weight))
+            .saturating_add(self.edge)
+    }
+}
+
 pub fn compute_total_penalty(qr: &QR) -> u32 {
+    compute_penalty_breakdown(qr).sum()
+}
+
+pub fn compute_penalty_breakdown(qr: &QR) -> PenaltyBreakdown {
     match qr.version() {
-        Version::Micro(_) => todo!(),
-        Version::Normal(_) => {
-            let adjacent_penalty = compute_adjacent_penalty(qr);
-            let block_penalty = compute_block_penalty(qr);
-            let finder_penalty_hor = compute_finder_pattern_penalty(qr, true);
-            let finder_penalty_ver = compute_finder_pattern_penalty(qr, false);
-            let balance_penalty = compute_balance_penalty(qr);
-            adjacent_penalty
-                + block_penalty
-                + finder_penalty_hor
-                + finder_penalty_ver
-                + balance_penalty
-        }
+        Version::Micro(_) => PenaltyBreakdown {
+            adjacent: 0,
+            block: 0,
+            finder_h: 0,
+            finder_v: 0,
+            balance: 0,
+            edge: compute_edge_penalty(qr),
+        },
+        Version::Normal(_) => PenaltyBreakdown {
+            adjacent: compute_adjacent_penalty(qr),
+            block: compute_block_penalty(qr),
+            finder_h: compute_finder_pattern_penalty(qr, true),
+            finder_v: compute_finder_pattern_penalty(qr, false),
+            balance: compute_balance_penalty(qr),
+            edge: 0,
+        },
     }
 }
 
@@ -187,7 +288,19 @@ fn compute_finder_pattern_penalty(qr: &QR, is_horizontal: bool) -> u32 {
     penalty
 }
 
-fn compute_balance_penalty(qr: &QR) -> u32 {
+// ISO 18004 scores micro masks by the dark modules on the right and bottom edges, leaving out the
+// timing pattern ends. The smaller count is weighted by 16 and the highest score wins, so it's
+// taken from the best possible score to rank lowest first like the normal rules
+fn compute_edge_penalty(qr: &QR) -> u32 {
+    let w = qr.width() as i16;
+    let is_dark = |r: i16, c: i16| (*qr.get(r, c) == Color::Dark) as u32;
+    let right = (1..w).map(|r| is_dark(r, w - 1)).sum::<u32>();
+    let bottom = (1..w).map(|c| is_dark(w - 1, c)).sum::<u32>();
+    let score = right.min(bottom) * 16 + right.max(bottom);
+    (w as u32 - 1) * 17 - score
+}
+
+pub fn compute_balance_penalty(qr: &QR) -> u32 {
     let dark_count = qr.count_dark_modules();
     let w = qr.width();
     let total_count = w * w;
@@ -199,4 +312,186 @@ fn compute_balance_penalty(qr: &QR) -> u32 {
     }
 }
 
+#[cfg(test)]
+mod preview_tests {
+    use test_case::test_case;
+
+    use super::MaskPattern;
+    use crate::{
+        metadata::{ECLevel, Palette, Version},
+        qr::{Module, QR},
+    };
+
+    #[test_case(Version::Normal(1))]
+    #[test_case(Version::Normal(7))]
+    #[test_case(Version::Micro(2))]
+    fn test_render_preview_checkerboard(version: Version) {
+        let preview = MaskPattern::new(0).render_preview(version);
+        let w = version.width();
+        assert_eq!(preview.len(), w * w);
+
+        let mut qr = QR::new(version, ECLevel::L, Palette::Mono);
+        qr.draw_all_function_patterns();
+        qr.draw_encoding_region(&vec![0; version.total_codewords(Palette::Mono)]);
+        for r in 0..w as i16 {
+            for c in 0..w as i16 {
+                let is_data = matches!(qr.get(r, c), Module::Data(_));
+                let flipped = preview[r as usize * w + c as usize];
+                // Micro pattern 0 is normal pattern 1
+                let exp = match version {
+                    Version::Micro(_) => r & 1 == 0,
+                    Version::Normal(_) => (r + c) & 1 == 0,
+                };
+                assert_eq!(flipped, is_data && exp, "Row {r}, Col {c}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_render_preview_distinct_patterns() {
+        let previews = (0..8)
+            .map(|m| MaskPattern::new(m).render_preview(Version::Normal(2)))
+            .collect::<Vec<_>>();
+        for i in 0..8 {
+            assert!(previews[i].iter().any(|&f| f), "Pattern {i} flips nothing");
+            for j in i + 1..8 {
+                assert_ne!(previews[i], previews[j], "Patterns {i} and {j}");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod penalty_tests {
+    use test_case::test_case;
+
+    use super::{
+        apply_best_mask, apply_best_mask_with_config, compute_block_penalty,
+        compute_mask_penalties, compute_mask_penalties_with_config, compute_penalty_breakdown,
+        compute_total_penalty, MaskSelectionConfig,
+    };
+    use crate::{
+        builder::QRBuilder,
+        metadata::{Color, ECLevel, Palette, Version},
+        qr::{Module, QR},
+    };
+
+    #[test_case("Hello, world!", Version::Normal(1))]
+    #[test_case("A11111111111111", Version::Normal(7))]
+    #[test_case("1234567890", Version::Normal(10))]
+    fn test_penalty_breakdown_sums_to_total(data: &str, version: Version) {
+        let qr = QRBuilder::new(data.as_bytes()).version(version).build().unwrap();
+        let breakdown = compute_penalty_breakdown(&qr);
+        assert_eq!(breakdown.sum(), compute_total_penalty(&qr));
+        assert_eq!(
+            breakdown.sum(),
+            breakdown.adjacent
+                + breakdown.block
+                + breakdown.finder_h
+                + breakdown.finder_v
+                + breakdown.balance
+                + breakdown.edge
+        );
+    }
+
+    // Right edge 10 dark and bottom edge 4 dark, so the score is 4 * 16 + 10 out of 16 * 17
+    #[test]
+    fn test_edge_penalty() {
+        let mut qr = QR::new(Version::Micro(4), ECLevel::L, Palette::Mono);
+        let w = qr.width() as i16;
+        for r in 0..w {
+            for c in 0..w {
+                qr.set(r, c, Module::Data(Color::Light));
+            }
+        }
+        (1..11).for_each(|r| qr.set(r, w - 1, Module::Data(Color::Dark)));
+        (1..5).for_each(|c| qr.set(w - 1, c, Module::Data(Color::Dark)));
+        // Timing pattern ends aren't counted
+        qr.set(0, w - 1, Module::Func(Color::Dark));
+        qr.set(w - 1, 0, Module::Func(Color::Dark));
+
+        let breakdown = compute_penalty_breakdown(&qr);
+        assert_eq!(breakdown.edge, 16 * 17 - (4 * 16 + 10));
+        assert_eq!(breakdown.sum(), breakdown.edge);
+    }
+
+    #[test_case("1", Version::Micro(1))]
+    #[test_case("HELLO", Version::Micro(2))]
+    #[test_case("Hello", Version::Micro(4))]
+    fn test_micro_mask_selection(data: &str, version: Version) {
+        let qr = QRBuilder::new(data.as_bytes()).version(version).ec_level(ECLevel::L).build();
+        let qr = qr.unwrap();
+        assert!(*qr.mask_pattern().unwrap() < 4);
+
+        let mut unmasked = QRBuilder::new(data.as_bytes());
+        let unmasked = unmasked.version(version).ec_level(ECLevel::L).no_mask().build().unwrap();
+        let penalties = compute_mask_penalties(&unmasked);
+        assert_eq!(penalties.len(), 4);
+        assert_eq!(qr.mask_pattern(), Some(penalties[0].0));
+    }
+
+    #[test]
+    fn test_block_weight_changes_mask() {
+        let data = "Hello, world!".as_bytes();
+        let version = Version::Normal(1);
+        let heavy = MaskSelectionConfig { block_weight: 10, ..Default::default() };
+        let spec = QRBuilder::new(data).version(version).build().unwrap();
+        let weighted = QRBuilder::new(data).version(version).mask_selection(heavy).build().unwrap();
+        assert_ne!(spec.mask_pattern(), weighted.mask_pattern());
+
+        let breakdown = compute_penalty_breakdown(&spec);
+        assert_eq!(breakdown.weighted_sum(&MaskSelectionConfig::default()), breakdown.sum());
+
+        let spec_block = breakdown.block;
+        let weighted_block = compute_penalty_breakdown(&weighted).block;
+        assert!(weighted_block < spec_block, "{weighted_block} >= {spec_block}");
+    }
+
+    #[test]
+    fn test_weighted_sum_saturates() {
+        let data = "Hello, world!".as_bytes();
+        let huge = MaskSelectionConfig { block_weight: u32::MAX / 2, ..Default::default() };
+        let qr = QRBuilder::new(data).version(Version::Normal(1)).mask_selection(huge).build();
+        assert!(qr.is_ok());
+
+        let breakdown = compute_penalty_breakdown(&qr.unwrap());
+        assert_eq!(breakdown.weighted_sum(&huge), u32::MAX);
+    }
+
+    #[test]
+    fn test_default_config_matches_unweighted() {
+        let mut unmasked = QRBuilder::new(b"Hello, world!");
+        let unmasked = unmasked.version(Version::Normal(1)).no_mask().build().unwrap();
+        let config = MaskSelectionConfig::default();
+        assert_eq!(
+            compute_mask_penalties(&unmasked),
+            compute_mask_penalties_with_config(&unmasked, &config)
+        );
+
+        let spec_mask = apply_best_mask(&mut unmasked.clone());
+        assert_eq!(spec_mask, compute_mask_penalties(&unmasked)[0].0);
+        let heavy = MaskSelectionConfig { block_weight: 10, ..Default::default() };
+        assert_ne!(apply_best_mask_with_config(&mut unmasked.clone(), &heavy), spec_mask);
+    }
+
+    #[test_case(2, 3)]
+    #[test_case(3, 12)]
+    fn test_block_penalty_solid_region(size: i16, exp_penalty: u32) {
+        let mut qr = QR::new(Version::Normal(1), ECLevel::L, Palette::Mono);
+        let w = qr.width() as i16;
+        for r in 0..w {
+            for c in 0..w {
+                let color = if (r + c) & 1 == 0 { Color::Dark } else { Color::Light };
+                qr.set(r, c, Module::Data(color));
+            }
+        }
+        for r in 10..10 + size {
+            for c in 10..10 + size {
+                qr.set(r, c, Module::Data(Color::Dark));
+            }
+        }
+        assert_eq!(compute_block_penalty(&qr), exp_penalty);
+    }
+}
+
 // TODO: Write test cases
@@ -1,22 +1,29 @@
+use alloc::{format, string::ToString, vec::Vec};
+use core::cmp::PartialOrd;
+use core::fmt::{Debug, Display};
+use core::ops::{Deref, Not};
 use core::panic;
-use std::cmp::PartialOrd;
-use std::fmt::{Debug, Display};
-use std::ops::{Deref, Not};
+use core::str::FromStr;
 
-use image::Rgb;
+#[cfg(feature = "std")]
+use image::{Luma, Rgb};
 
 use crate::codec::Mode;
+use crate::error::{QRError, QRResult};
 use crate::mask::MaskPattern;
 
 // Metadata
 //------------------------------------------------------------------------------
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Clone)]
 pub struct Metadata {
     version: Option<Version>,
     ec_level: Option<ECLevel>,
     palette: Option<Palette>,
     mask_pattern: Option<MaskPattern>,
+    segments: Vec<(Mode, usize)>,
+    mirrored: bool,
+    inverted: bool,
 }
 
 impl Metadata {
@@ -26,12 +33,66 @@ impl Metadata {
         palette: Option<Palette>,
         mask_pattern: Option<MaskPattern>,
     ) -> Self {
-        Self { version, ec_level, palette, mask_pattern }
+        Self {
+            version,
+            ec_level,
+            palette,
+            mask_pattern,
+            segments: Vec::new(),
+            mirrored: false,
+            inverted: false,
+        }
+    }
+
+    pub fn with_segments(mut self, segments: Vec<(Mode, usize)>) -> Self {
+        self.segments = segments;
+        self
+    }
+
+    pub fn with_mirrored(mut self, mirrored: bool) -> Self {
+        self.mirrored = mirrored;
+        self
+    }
+
+    pub fn with_inverted(mut self, inverted: bool) -> Self {
+        self.inverted = inverted;
+        self
+    }
+
+    pub fn version(&self) -> Option<Version> {
+        self.version
+    }
+
+    pub fn ec_level(&self) -> Option<ECLevel> {
+        self.ec_level
+    }
+
+    pub fn palette(&self) -> Option<Palette> {
+        self.palette
+    }
+
+    pub fn mask(&self) -> Option<MaskPattern> {
+        self.mask_pattern
+    }
+
+    // Mode and char count of each decoded segment, empty until the data has been decoded
+    pub fn segments(&self) -> &[(Mode, usize)] {
+        &self.segments
+    }
+
+    // Whether the code was read from a mirror image, always false for built codes
+    pub fn is_mirrored(&self) -> bool {
+        self.mirrored
+    }
+
+    // Whether the code was read light on dark, always false for built codes
+    pub fn is_inverted(&self) -> bool {
+        self.inverted
     }
 }
 
 impl Display for Metadata {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         let ver = match &self.version {
             Some(v) => format!("{:?}", v),
             None => "None".to_string(),
@@ -56,10 +117,46 @@ impl Display for Metadata {
     }
 }
 
+#[cfg(all(test, feature = "std"))]
+mod metadata_tests {
+    use test_case::test_case;
+
+    use super::{ECLevel, Palette, Version};
+    use crate::builder::QRBuilder;
+    use crate::deqr::DeQR;
+    use crate::mask::MaskPattern;
+
+    #[test_case(Version::Normal(1), ECLevel::L, MaskPattern::new(0))]
+    #[test_case(Version::Normal(5), ECLevel::Q, MaskPattern::new(3))]
+    #[test_case(Version::Normal(7), ECLevel::H, MaskPattern::new(6))]
+    fn test_metadata_getters(version: Version, ec_level: ECLevel, mask: MaskPattern) {
+        let qr = QRBuilder::new(b"Hello, world!")
+            .version(version)
+            .ec_level(ec_level)
+            .mask(mask)
+            .build()
+            .unwrap();
+
+        let meta = qr.metadata();
+        assert_eq!(meta.version(), Some(version));
+        assert_eq!(meta.ec_level(), Some(ec_level));
+        assert_eq!(meta.palette(), Some(Palette::Mono));
+        assert_eq!(meta.mask(), Some(mask));
+
+        let mut deqr = DeQR::from_image(&qr.render(2), version);
+        deqr.read_format_info().unwrap();
+        let meta = deqr.metadata();
+        assert_eq!(meta.version(), Some(version));
+        assert_eq!(meta.ec_level(), Some(ec_level));
+        assert_eq!(meta.mask(), Some(mask));
+    }
+}
+
 // Version
 //------------------------------------------------------------------------------
 
-#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+// Ordered by size, all micro versions come before normal ones
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Copy, Clone)]
 pub enum Version {
     Micro(usize),
     Normal(usize),
@@ -75,7 +172,39 @@ impl Deref for Version {
     }
 }
 
+// Numbers map to normal versions, micro versions go through Version::micro
+impl TryFrom<usize> for Version {
+    type Error = QRError;
+    fn try_from(value: usize) -> Result<Self, Self::Error> {
+        match value {
+            1..=40 => Ok(Self::Normal(value)),
+            _ => Err(QRError::InvalidVersion),
+        }
+    }
+}
+
 impl Version {
+    pub fn micro(version: usize) -> QRResult<Self> {
+        match version {
+            1..=4 => Ok(Self::Micro(version)),
+            _ => Err(QRError::InvalidVersion),
+        }
+    }
+
+    // Every version in ascending size, micro 1 to 4 then normal 1 to 40
+    pub fn all() -> impl Iterator<Item = Version> {
+        (1..=4).map(Self::Micro).chain((1..=40).map(Self::Normal))
+    }
+
+    // Inverse of width. Micro widths 11 to 17 and normal widths 21 to 177 don't overlap
+    pub fn from_grid_size(size: usize) -> Option<Version> {
+        match size {
+            11..=17 if size & 1 == 1 => Some(Self::Micro((size - 9) / 2)),
+            21..=177 if size & 3 == 1 => Some(Self::Normal((size - 17) / 4)),
+            _ => None,
+        }
+    }
+
     pub const fn width(self) -> usize {
         debug_assert!(matches!(self, Self::Micro(1..=4) | Self::Normal(1..=40)), "Invalid version");
         match self {
@@ -92,6 +221,14 @@ impl Version {
         }
     }
 
+    // Patterns actually drawn, the three positions overlapping finders are skipped
+    pub fn alignment_pattern_count(self) -> usize {
+        match self.alignment_pattern().len() {
+            0 => 0,
+            n => n * n - 3,
+        }
+    }
+
     pub fn mode_len(self) -> usize {
         match self {
             Version::Micro(v) => v - 1,
@@ -99,6 +236,20 @@ impl Version {
         }
     }
 
+    pub fn terminator_len(self) -> usize {
+        match self {
+            Version::Micro(v) => 2 * v + 1,
+            Version::Normal(_) => 4,
+        }
+    }
+
+    // M1 and M3 end their data on a 4 bit codeword. Index of that codeword, which keeps its bits
+    // in the high nibble and is drawn without the low one
+    pub fn half_codeword(self, ec_level: ECLevel) -> Option<usize> {
+        let bit_capacity = self.bit_capacity(ec_level, Palette::Mono);
+        (bit_capacity & 7 != 0).then_some(bit_capacity >> 3)
+    }
+
     pub fn char_count_bit_len(&self, mode: Mode) -> usize {
         debug_assert!(
             matches!(self, Version::Micro(1..=4) | Version::Normal(1..=40)),
@@ -129,6 +280,16 @@ impl Version {
         }
     }
 
+    // Micro versions drop the stronger levels, M1 only detects errors and counts as L
+    pub fn supports_ec_level(self, ec_level: ECLevel) -> bool {
+        match self {
+            Version::Micro(1) => ec_level == ECLevel::L,
+            Version::Micro(2 | 3) => ec_level <= ECLevel::M,
+            Version::Micro(_) => ec_level <= ECLevel::Q,
+            Version::Normal(_) => true,
+        }
+    }
+
     pub fn bit_capacity(self, ec_level: ECLevel, palette: Palette) -> usize {
         let mut bc = match self {
             Version::Micro(v) => VERSION_BIT_CAPACITY[39 + v][ec_level as usize],
@@ -140,13 +301,45 @@ impl Version {
         bc
     }
 
-    pub fn total_codewords(self) -> usize {
+    // Most chars of a single mode segment that fit, after the mode and char count headers. Modes
+    // a micro version can't hold have no capacity
+    pub fn capacity_in_mode(self, ec_level: ECLevel, mode: Mode, palette: Palette) -> usize {
+        match (self, mode) {
+            (Version::Micro(1), Mode::Alphanumeric | Mode::Byte) => return 0,
+            (Version::Micro(2), Mode::Byte) => return 0,
+            _ => {}
+        }
+
+        let char_count_bit_len = self.char_count_bit_len(mode);
+        let avail = self
+            .bit_capacity(ec_level, palette)
+            .saturating_sub(self.mode_len() + char_count_bit_len);
+        let cap = match mode {
+            Mode::Numeric => avail / 10 * 3 + [0, 0, 0, 0, 1, 1, 1, 2, 2, 2][avail % 10],
+            Mode::Alphanumeric => avail / 11 * 2 + (avail % 11 >= 6) as usize,
+            Mode::Byte => avail / 8,
+        };
+        cap.min((1 << char_count_bit_len) - 1)
+    }
+
+    // Data plus ecc codewords carried by a single colour channel
+    pub fn channel_codewords(self) -> usize {
         match self {
             Version::Micro(v) => VERSION_TOTAL_CODEWORDS[39 + v],
             Version::Normal(v) => VERSION_TOTAL_CODEWORDS[v - 1],
         }
     }
 
+    // Codewords across all channels; Poly carries one full set per channel
+    pub fn total_codewords(self, palette: Palette) -> usize {
+        let cw = self.channel_codewords();
+        if matches!(palette, Palette::Poly) {
+            cw * 3
+        } else {
+            cw
+        }
+    }
+
     pub fn data_codewords_per_block(self, ec_level: ECLevel) -> (usize, usize, usize, usize) {
         match self {
             Version::Micro(v) => DATA_CODEWORDS_PER_BLOCK[39 + v][ec_level as usize],
@@ -161,6 +354,7 @@ impl Version {
         }
     }
 
+    // Zero bits padding the encoding region after the last codeword (ISO 18004 table 1)
     pub fn remainder_bits(self) -> usize {
         match self {
             Version::Micro(_) | Version::Normal(1) => 0,
@@ -185,9 +379,105 @@ impl Version {
 
 #[cfg(test)]
 mod version_tests {
+    use test_case::test_case;
+
     use crate::codec::Mode;
+    use crate::error::QRError;
+
+    use super::Version::{self, *};
+    use super::{ECLevel, Palette};
+
+    #[test_case(1, Ok(Normal(1)))]
+    #[test_case(7, Ok(Normal(7)))]
+    #[test_case(40, Ok(Normal(40)))]
+    #[test_case(0, Err(QRError::InvalidVersion))]
+    #[test_case(41, Err(QRError::InvalidVersion))]
+    fn test_try_from_usize(v: usize, exp: Result<Version, QRError>) {
+        assert_eq!(Version::try_from(v), exp);
+    }
 
-    use super::Version::*;
+    #[test_case(1, Ok(Micro(1)))]
+    #[test_case(4, Ok(Micro(4)))]
+    #[test_case(0, Err(QRError::InvalidVersion))]
+    #[test_case(5, Err(QRError::InvalidVersion))]
+    fn test_micro(v: usize, exp: Result<Version, QRError>) {
+        assert_eq!(Version::micro(v), exp);
+    }
+
+    #[test]
+    fn test_all_ascending() {
+        let versions = Version::all().collect::<Vec<_>>();
+        assert_eq!(versions.len(), 44);
+        assert!(versions.windows(2).all(|w| w[0] < w[1] && w[0].width() < w[1].width()));
+        assert_eq!(versions[0], Micro(1));
+        assert_eq!(versions[43], Normal(40));
+    }
+
+    #[test]
+    fn test_from_grid_size() {
+        for version in Version::all() {
+            assert_eq!(Version::from_grid_size(version.width()), Some(version));
+        }
+        for size in [0, 9, 10, 12, 19, 23, 179, 181] {
+            assert_eq!(Version::from_grid_size(size), None, "{size}");
+        }
+    }
+
+    // Capacities from ISO 18004 table 7
+    #[test_case(Normal(1), ECLevel::L, Mode::Numeric, 41)]
+    #[test_case(Normal(1), ECLevel::L, Mode::Alphanumeric, 25)]
+    #[test_case(Normal(1), ECLevel::L, Mode::Byte, 17)]
+    #[test_case(Normal(5), ECLevel::Q, Mode::Numeric, 144)]
+    #[test_case(Normal(5), ECLevel::Q, Mode::Alphanumeric, 87)]
+    #[test_case(Normal(5), ECLevel::Q, Mode::Byte, 60)]
+    #[test_case(Normal(10), ECLevel::M, Mode::Numeric, 513)]
+    #[test_case(Normal(10), ECLevel::M, Mode::Alphanumeric, 311)]
+    #[test_case(Normal(10), ECLevel::M, Mode::Byte, 213)]
+    #[test_case(Normal(40), ECLevel::L, Mode::Numeric, 7089)]
+    #[test_case(Normal(40), ECLevel::L, Mode::Alphanumeric, 4296)]
+    #[test_case(Normal(40), ECLevel::H, Mode::Byte, 1273)]
+    #[test_case(Micro(1), ECLevel::L, Mode::Numeric, 5)]
+    #[test_case(Micro(1), ECLevel::L, Mode::Alphanumeric, 0)]
+    #[test_case(Micro(2), ECLevel::L, Mode::Alphanumeric, 6)]
+    #[test_case(Micro(2), ECLevel::M, Mode::Byte, 0)]
+    #[test_case(Micro(4), ECLevel::L, Mode::Numeric, 35)]
+    #[test_case(Micro(4), ECLevel::L, Mode::Byte, 15)]
+    fn test_capacity_in_mode(version: Version, ec_level: ECLevel, mode: Mode, exp_cap: usize) {
+        assert_eq!(version.capacity_in_mode(ec_level, mode, Palette::Mono), exp_cap);
+    }
+
+    #[test]
+    fn test_alignment_pattern_count() {
+        assert_eq!(Micro(4).alignment_pattern_count(), 0);
+        assert_eq!(Normal(1).alignment_pattern_count(), 0);
+        assert_eq!(Normal(2).alignment_pattern_count(), 1);
+        assert_eq!(Normal(7).alignment_pattern_count(), 6);
+        assert_eq!(Normal(40).alignment_pattern_count(), 46);
+    }
+
+    #[test]
+    fn test_remainder_bits() {
+        assert_eq!(Normal(1).remainder_bits(), 0);
+        assert_eq!(Normal(2).remainder_bits(), 7);
+        assert_eq!(Normal(7).remainder_bits(), 0);
+        assert_eq!(Normal(40).remainder_bits(), 0);
+    }
+
+    #[test]
+    fn test_total_codewords() {
+        assert_eq!(Normal(1).channel_codewords(), 26);
+        assert_eq!(Normal(40).channel_codewords(), 3706);
+        assert_eq!(Normal(1).total_codewords(Palette::Mono), 26);
+        assert_eq!(Normal(1).total_codewords(Palette::Poly), 78);
+    }
+
+    #[test]
+    fn test_ordering() {
+        assert!(Micro(4) < Normal(1));
+        assert!(Micro(1) < Micro(2));
+        assert!(Normal(39) < Normal(40));
+        assert_eq!(Normal(1).max(Micro(4)), Normal(1));
+    }
 
     #[test]
     #[should_panic(expected = "Invalid version")]
@@ -253,6 +543,25 @@ mod version_tests {
         assert_eq!(Normal(40).char_count_bit_len(Mode::Byte), 16);
     }
 
+    // Widths on each side of the 9/10 and 26/27 transitions, and for every micro version
+    #[test_case(Micro(1), [3, 0, 0])]
+    #[test_case(Micro(2), [4, 3, 0])]
+    #[test_case(Micro(3), [5, 4, 4])]
+    #[test_case(Micro(4), [6, 5, 5])]
+    #[test_case(Normal(9), [10, 9, 8])]
+    #[test_case(Normal(10), [12, 11, 16])]
+    #[test_case(Normal(26), [12, 11, 16])]
+    #[test_case(Normal(27), [14, 13, 16])]
+    fn test_char_count_bit_len_boundaries(version: Version, exp: [usize; 3]) {
+        let modes = [Mode::Numeric, Mode::Alphanumeric, Mode::Byte];
+        for (mode, exp) in modes.into_iter().zip(exp) {
+            // Modes a micro version can't hold have no width to check
+            if exp > 0 {
+                assert_eq!(version.char_count_bit_len(mode), exp, "{mode:?}");
+            }
+        }
+    }
+
     #[test]
     #[should_panic]
     fn test_char_count_bit_len_invalid_version_low() {
@@ -295,6 +604,41 @@ impl From<u8> for ECLevel {
     }
 }
 
+// Case insensitive level letter, e.g. "H" or "h"
+impl FromStr for ECLevel {
+    type Err = QRError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_uppercase().as_str() {
+            "L" => Ok(ECLevel::L),
+            "M" => Ok(ECLevel::M),
+            "Q" => Ok(ECLevel::Q),
+            "H" => Ok(ECLevel::H),
+            _ => Err(QRError::InvalidECLevel),
+        }
+    }
+}
+
+#[cfg(test)]
+mod ec_level_tests {
+    use test_case::test_case;
+
+    use super::ECLevel;
+    use crate::error::QRError;
+
+    #[test_case("L", Ok(ECLevel::L))]
+    #[test_case("M", Ok(ECLevel::M))]
+    #[test_case("q", Ok(ECLevel::Q))]
+    #[test_case("h", Ok(ECLevel::H))]
+    #[test_case("H", Ok(ECLevel::H))]
+    #[test_case("", Err(QRError::InvalidECLevel))]
+    #[test_case("X", Err(QRError::InvalidECLevel))]
+    #[test_case("HH", Err(QRError::InvalidECLevel))]
+    #[test_case(" H", Err(QRError::InvalidECLevel))]
+    fn test_from_str(s: &str, exp: Result<ECLevel, QRError>) {
+        assert_eq!(s.parse::<ECLevel>(), exp);
+    }
+}
+
 // Palette
 //------------------------------------------------------------------------------
 
@@ -305,11 +649,84 @@ pub enum Palette {
 }
 
 impl Palette {
+    // Bits follow Color::channel, a set bit means the channel is dark. So 0b000 is white and
+    // 0b111 is black, matching Color::from_channels
+    #[cfg(feature = "std")]
     pub fn color(self, bits: u8) -> Rgb<u8> {
         debug_assert!(matches!(self, Palette::Poly), "Palette is not poly");
         debug_assert!(bits < 8, "Bits should be between 0 and 7");
 
-        PALETTE[bits as usize]
+        Rgb(PALETTE[bits as usize])
+    }
+
+    // Colors are indexed like PALETTE, by rgb channel bits where a set bit means the channel
+    // should read dark. Mono only draws white and black, so only those two are checked
+    pub fn validate_contrast(self, colors: &[[u8; 3]; 8]) -> QRResult<()> {
+        let used: &[usize] = match self {
+            Palette::Mono => &[0b000, 0b111],
+            Palette::Poly => &[0, 1, 2, 3, 4, 5, 6, 7],
+        };
+
+        for (ch, bit) in [0b100, 0b010, 0b001].into_iter().enumerate() {
+            let darkest_light =
+                used.iter().filter(|&&i| i & bit == 0).map(|&i| colors[i][ch]).min();
+            let lightest_dark =
+                used.iter().filter(|&&i| i & bit != 0).map(|&i| colors[i][ch]).max();
+            if let (Some(l), Some(d)) = (darkest_light, lightest_dark) {
+                if (l as u16) < d as u16 + MIN_CHANNEL_CONTRAST as u16 {
+                    return Err(QRError::InvalidPalette);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod palette_tests {
+    #[cfg(feature = "std")]
+    use super::Color;
+    use super::{Palette, PALETTE};
+    use crate::error::QRError;
+
+    #[test]
+    fn test_validate_contrast() {
+        assert_eq!(Palette::Mono.validate_contrast(&PALETTE), Ok(()));
+        assert_eq!(Palette::Poly.validate_contrast(&PALETTE), Ok(()));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_color() {
+        let exp = [
+            [255, 255, 255],
+            [255, 255, 0],
+            [255, 0, 255],
+            [255, 0, 0],
+            [0, 255, 255],
+            [0, 255, 0],
+            [0, 0, 255],
+            [0, 0, 0],
+        ];
+        for (bits, exp) in exp.into_iter().enumerate() {
+            assert_eq!(Palette::Poly.color(bits as u8), image::Rgb(exp), "{bits:03b}");
+            assert_eq!(Palette::Poly.color(bits as u8), Color::from_channels(bits as u32).rgb());
+        }
+    }
+
+    #[test]
+    fn test_validate_contrast_degenerate() {
+        // Red reads too dark on the red plane, so it can't be told apart from black there
+        let mut colors = PALETTE;
+        colors[0b011] = [100, 0, 0];
+        assert_eq!(Palette::Poly.validate_contrast(&colors), Err(QRError::InvalidPalette));
+
+        // Mono only uses black and white
+        assert_eq!(Palette::Mono.validate_contrast(&colors), Ok(()));
+
+        let mut colors = PALETTE;
+        colors[0b000] = [120, 120, 120];
+        assert_eq!(Palette::Mono.validate_contrast(&colors), Err(QRError::InvalidPalette));
     }
 }
 
@@ -330,7 +747,7 @@ impl Not for Color {
         match self {
             Self::Light => Self::Dark,
             Self::Dark => Self::Light,
-            Self::Hue(h) => Self::Hue(!h),
+            Self::Hue(h) => Self::Hue(!h & 0b111),
         }
     }
 }
@@ -364,6 +781,120 @@ impl Color {
             Self::Hue(_) => todo!(),
         }
     }
+
+    // Hue holds one bit per rgb channel, a set bit means the channel is dark. All light and all
+    // dark channels collapse to Light and Dark, so mono and poly modules compare alike
+    pub fn from_channels(bits: u32) -> Self {
+        debug_assert!(bits < 8, "Bits should be between 0 and 7");
+
+        match bits {
+            0b000 => Self::Light,
+            0b111 => Self::Dark,
+            _ => Self::Hue(bits),
+        }
+    }
+
+    // Bit of channel 0 (red), 1 (green) or 2 (blue). Light and dark read the same on every channel
+    pub fn channel(self, ch: usize) -> u8 {
+        debug_assert!(ch < 3, "Invalid channel: {ch}");
+
+        match self {
+            Self::Light => 0,
+            Self::Dark => 1,
+            Self::Hue(h) => ((h >> (2 - ch)) & 1) as u8,
+        }
+    }
+
+    #[cfg(feature = "std")]
+    pub fn rgb(self) -> Rgb<u8> {
+        Rgb(PALETTE[(0..3).fold(0, |acc, ch| (acc << 1) | self.channel(ch) as usize)])
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<Color> for Rgb<u8> {
+    fn from(value: Color) -> Self {
+        value.rgb()
+    }
+}
+
+// Hues are weighted to luma with the Rec. 601 coefficients, the same the reader uses on rgb images
+#[cfg(feature = "std")]
+impl From<Color> for Luma<u8> {
+    fn from(value: Color) -> Self {
+        let [r, g, b] = value.rgb().0;
+        let luma = 0.299 * r as f32 + 0.587 * g as f32 + 0.114 * b as f32;
+        Luma([luma.round() as u8])
+    }
+}
+
+// Each channel below half intensity reads as dark
+#[cfg(feature = "std")]
+impl From<Rgb<u8>> for Color {
+    fn from(value: Rgb<u8>) -> Self {
+        let bits = value.0.iter().fold(0, |acc, &p| (acc << 1) | (p < 128) as u32);
+        Self::from_channels(bits)
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<Luma<u8>> for Color {
+    fn from(value: Luma<u8>) -> Self {
+        if value.0[0] < 128 {
+            Self::Dark
+        } else {
+            Self::Light
+        }
+    }
+}
+
+#[cfg(test)]
+mod color_tests {
+    #[cfg(feature = "std")]
+    use image::{Luma, Rgb};
+
+    use super::Color;
+
+    #[test]
+    fn test_channels_round_trip() {
+        for bits in 0..8 {
+            let color = Color::from_channels(bits);
+            let back = (0..3).fold(0, |acc, ch| (acc << 1) | color.channel(ch) as u32);
+            assert_eq!(back, bits);
+            assert_eq!(Color::from_channels(bits ^ 0b111), !color);
+        }
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_rgb() {
+        assert_eq!(Color::Light.rgb(), Rgb([255, 255, 255]));
+        assert_eq!(Color::Dark.rgb(), Rgb([0, 0, 0]));
+        assert_eq!(Color::Hue(0b100).rgb(), Rgb([0, 255, 255]));
+        assert_eq!(Color::Hue(0b010).rgb(), Rgb([255, 0, 255]));
+        assert_eq!(Color::Hue(0b001).rgb(), Rgb([255, 255, 0]));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_rgb_round_trip() {
+        for bits in 0..8 {
+            let color = Color::from_channels(bits);
+            let rgb = Rgb::from(color);
+            assert_eq!(rgb, color.rgb());
+            assert_eq!(Color::from(rgb), color);
+        }
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_luma() {
+        assert_eq!(Luma::from(Color::Light), Luma([255]));
+        assert_eq!(Luma::from(Color::Dark), Luma([0]));
+        assert_eq!(Luma::from(Color::Hue(0b011)), Luma([76]));
+        assert_eq!(Color::from(Luma::from(Color::Light)), Color::Light);
+        assert_eq!(Color::from(Luma::from(Color::Dark)), Color::Dark);
+    }
 }
 
 // Format information
@@ -380,6 +911,45 @@ pub fn parse_format_info_qr(info: u32) -> (ECLevel, MaskPattern) {
     (ec_level, mask_pattern)
 }
 
+// Micro format data is a 3 bit symbol number, standing for both version and ec level, followed
+// by a 2 bit mask
+pub fn generate_format_info_micro(
+    version: Version,
+    ec_level: ECLevel,
+    mask_pattern: MaskPattern,
+) -> u32 {
+    debug_assert!(*mask_pattern < 4, "Invalid micro masking pattern");
+
+    let symbol_number = match (version, ec_level) {
+        (Version::Micro(1), ECLevel::L) => 0,
+        (Version::Micro(2), ECLevel::L) => 1,
+        (Version::Micro(2), ECLevel::M) => 2,
+        (Version::Micro(3), ECLevel::L) => 3,
+        (Version::Micro(3), ECLevel::M) => 4,
+        (Version::Micro(4), ECLevel::L) => 5,
+        (Version::Micro(4), ECLevel::M) => 6,
+        (Version::Micro(4), ECLevel::Q) => 7,
+        _ => unreachable!("Invalid micro version and ec level: {version:?} {ec_level:?}"),
+    };
+    FORMAT_INFOS_MICRO[symbol_number << 2 | *mask_pattern as usize]
+}
+
+pub fn parse_format_info_micro(info: u32) -> (Version, ECLevel, MaskPattern) {
+    let (version, ec_level) = match (info >> 12) & 7 {
+        0 => (Version::Micro(1), ECLevel::L),
+        1 => (Version::Micro(2), ECLevel::L),
+        2 => (Version::Micro(2), ECLevel::M),
+        3 => (Version::Micro(3), ECLevel::L),
+        4 => (Version::Micro(3), ECLevel::M),
+        5 => (Version::Micro(4), ECLevel::L),
+        6 => (Version::Micro(4), ECLevel::M),
+        7 => (Version::Micro(4), ECLevel::Q),
+        _ => unreachable!(),
+    };
+    let mask_pattern = MaskPattern::new(((info >> 10) & 3) as u8);
+    (version, ec_level, mask_pattern)
+}
+
 // Global constants
 //------------------------------------------------------------------------------
 
@@ -585,6 +1155,7 @@ pub static FORMAT_ERROR_BIT_LEN: usize = 10;
 pub static FORMAT_ERROR_CAPACITY: u32 = 3;
 
 pub static FORMAT_MASK: u32 = 0b101010000010010;
+pub static FORMAT_MASK_MICRO: u32 = 0b100010001000101;
 
 pub static FORMAT_INFOS_QR: [u32; 32] = [
     0x5412, 0x5125, 0x5e7c, 0x5b4b, 0x45f9, 0x40ce, 0x4f97, 0x4aa0, 0x77c4, 0x72f3, 0x7daa, 0x789d,
@@ -592,6 +1163,12 @@ pub static FORMAT_INFOS_QR: [u32; 32] = [
     0x355f, 0x3068, 0x3f31, 0x3a06, 0x24b4, 0x2183, 0x2eda, 0x2bed,
 ];
 
+pub static FORMAT_INFOS_MICRO: [u32; 32] = [
+    0x4445, 0x4172, 0x4e2b, 0x4b1c, 0x55ae, 0x5099, 0x5fc0, 0x5af7, 0x6793, 0x62a4, 0x6dfd, 0x68ca,
+    0x7678, 0x734f, 0x7c16, 0x7921, 0x06de, 0x03e9, 0x0cb0, 0x0987, 0x1735, 0x1202, 0x1d5b, 0x186c,
+    0x2508, 0x203f, 0x2f66, 0x2a51, 0x34e3, 0x31d4, 0x3e8d, 0x3bba,
+];
+
 pub static FORMAT_INFO_COORDS_QR_MAIN: [(i16, i16); 15] = [
     (8, 0),
     (8, 1),
@@ -628,6 +1205,24 @@ pub static FORMAT_INFO_COORDS_QR_SIDE: [(i16, i16); 15] = [
     (8, -1),
 ];
 
+pub static FORMAT_INFO_COORDS_MICRO: [(i16, i16); 15] = [
+    (8, 1),
+    (8, 2),
+    (8, 3),
+    (8, 4),
+    (8, 5),
+    (8, 6),
+    (8, 7),
+    (8, 8),
+    (7, 8),
+    (6, 8),
+    (5, 8),
+    (4, 8),
+    (3, 8),
+    (2, 8),
+    (1, 8),
+];
+
 pub static VERSION_INFO_BIT_LEN: usize = 18;
 pub static VERSION_ERROR_BIT_LEN: usize = 12;
 pub static VERSION_ERROR_CAPACITY: u32 = 3;
@@ -683,17 +1278,22 @@ pub static VERSION_INFO_COORDS_TR: [(i16, i16); 18] = [
 
 pub static PALETTE_INFO_BIT_LEN: usize = 12;
 
-pub static PALETTE: [Rgb<u8>; 8] = [
-    Rgb([0, 0, 0]),
-    Rgb([255, 255, 0]),
-    Rgb([255, 0, 255]),
-    Rgb([255, 0, 0]),
-    Rgb([0, 255, 255]),
-    Rgb([0, 255, 0]),
-    Rgb([0, 0, 255]),
-    Rgb([255, 255, 255]),
+// Indexed by rgb channel bits, a set bit means the channel is dark
+pub static PALETTE: [[u8; 3]; 8] = [
+    [255, 255, 255],
+    [255, 255, 0],
+    [255, 0, 255],
+    [255, 0, 0],
+    [0, 255, 255],
+    [0, 255, 0],
+    [0, 0, 255],
+    [0, 0, 0],
 ];
 
+// Min gap on every channel between the lightest dark and the darkest light color, so each rgb
+// plane can be thresholded on its own
+pub static MIN_CHANNEL_CONTRAST: u8 = 128;
+
 pub static PALETTE_INFO_COORDS_BL: [(i16, i16); 12] = [
     (-1, 10),
     (-1, 9),
@@ -1,13 +1,28 @@
-use std::ops::Deref;
-
-use image::{GrayImage, Luma};
+use alloc::{format, string::String, vec, vec::Vec};
+use core::ops::Deref;
+#[cfg(feature = "std")]
+use std::{fs::File, io::BufWriter, path::Path};
+
+#[cfg(feature = "std")]
+use image::{
+    imageops::{blur, overlay, resize, FilterType},
+    GrayImage, Luma, Rgb, RgbImage,
+};
 
+#[cfg(feature = "std")]
+use crate::{
+    deqr::DeQR,
+    ec::{block_error_correction_capacity, codeword_block},
+};
 use crate::{
+    error::{QRError, QRResult},
     iter::EncRegionIter,
-    mask::MaskPattern,
+    mask::{compute_balance_penalty, compute_total_penalty, MaskPattern},
     metadata::{
-        generate_format_info_qr, Color, ECLevel, Metadata, Palette, Version, FORMAT_INFO_BIT_LEN,
-        FORMAT_INFO_COORDS_QR_MAIN, FORMAT_INFO_COORDS_QR_SIDE, VERSION_INFO_BIT_LEN,
+        generate_format_info_micro, generate_format_info_qr, parse_format_info_micro,
+        parse_format_info_qr, Color, ECLevel, Metadata, Palette, Version, FORMAT_INFOS_MICRO,
+        FORMAT_INFOS_QR, FORMAT_INFO_BIT_LEN, FORMAT_INFO_COORDS_MICRO, FORMAT_INFO_COORDS_QR_MAIN,
+        FORMAT_INFO_COORDS_QR_SIDE, FORMAT_MASK, FORMAT_MASK_MICRO, PALETTE, VERSION_INFO_BIT_LEN,
         VERSION_INFO_COORDS_BL, VERSION_INFO_COORDS_TR,
     },
 };
@@ -41,8 +56,11 @@ pub struct QR {
     version: Version,
     width: usize,
     ec_level: ECLevel,
+    channel_ec_levels: Option<[ECLevel; 3]>,
     palette: Palette,
     mask_pattern: Option<MaskPattern>,
+    mask_penalty: Option<u32>,
+    colors: [[u8; 3]; 8],
     grid: Vec<Module>,
 }
 
@@ -61,8 +79,11 @@ impl QR {
             version,
             width,
             ec_level,
+            channel_ec_levels: None,
             palette,
             mask_pattern: None,
+            mask_penalty: None,
+            colors: PALETTE,
             grid: vec![Module::Empty; width * width],
         }
     }
@@ -79,10 +100,49 @@ impl QR {
         self.ec_level
     }
 
+    // Poly codes can carry an ec level per channel, each written into its own channel of the
+    // format info. The red channel's level doubles as the code's ec level
+    pub fn channel_ec_levels(&self) -> Option<[ECLevel; 3]> {
+        self.channel_ec_levels
+    }
+
+    pub fn set_channel_ec_levels(&mut self, ec_levels: [ECLevel; 3]) {
+        debug_assert!(matches!(self.palette, Palette::Poly), "Palette is not poly");
+
+        self.ec_level = ec_levels[0];
+        self.channel_ec_levels = Some(ec_levels);
+    }
+
     pub fn palette(&self) -> Palette {
         self.palette
     }
 
+    // Colors drawn by the rgb renderers, indexed like PALETTE
+    pub fn colors(&self) -> [[u8; 3]; 8] {
+        self.colors
+    }
+
+    pub fn set_colors(&mut self, colors: [[u8; 3]; 8]) {
+        self.colors = colors;
+    }
+
+    fn color_rgb(&self, color: Color) -> [u8; 3] {
+        self.colors[(0..3).fold(0, |acc, ch| (acc << 1) | color.channel(ch) as usize)]
+    }
+
+    pub fn mask_pattern(&self) -> Option<MaskPattern> {
+        self.mask_pattern
+    }
+
+    // Total penalty of the final masked grid, as recorded by the builder. Cleared by remasking
+    pub fn mask_penalty(&self) -> Option<u32> {
+        self.mask_penalty
+    }
+
+    pub fn record_mask_penalty(&mut self) {
+        self.mask_penalty = Some(compute_total_penalty(self));
+    }
+
     pub fn metadata(&self) -> Metadata {
         Metadata::new(
             Some(self.version),
@@ -96,6 +156,16 @@ impl QR {
         self.grid.iter().filter(|&m| matches!(**m, Color::Dark)).count()
     }
 
+    // Share of dark modules, 0.5 prints and scans best
+    pub fn dark_ratio(&self) -> f32 {
+        self.count_dark_modules() as f32 / self.grid.len() as f32
+    }
+
+    // Balance rule of the mask penalty, 0 for an even split of dark and light
+    pub fn balance_score(&self) -> u32 {
+        compute_balance_penalty(self)
+    }
+
     #[cfg(test)]
     pub fn to_debug_str(&self) -> String {
         let w = self.width as i16;
@@ -164,6 +234,24 @@ mod qr_util_tests {
         assert_eq!(qr.get(-w, -w), Module::Func(Color::Dark));
     }
 
+    // Finders on an otherwise light grid, 33 dark modules each so 99 of 441
+    #[test]
+    fn test_dark_ratio() {
+        let mut qr =
+            QR::from_matrix(Version::Normal(1), ECLevel::L, Palette::Mono, &[false; 441]).unwrap();
+        assert_eq!(qr.dark_ratio(), 0.0);
+        assert_eq!(qr.balance_score(), 100);
+
+        qr.draw_finder_patterns();
+        assert_eq!(qr.dark_ratio(), 99.0 / 441.0);
+        assert_eq!(qr.balance_score(), 56);
+
+        let qr =
+            QR::from_matrix(Version::Normal(1), ECLevel::L, Palette::Mono, &[true; 441]).unwrap();
+        assert_eq!(qr.dark_ratio(), 1.0);
+        assert_eq!(qr.balance_score(), 100);
+    }
+
     #[test]
     #[should_panic]
     fn test_row_out_of_bound() {
@@ -357,8 +445,9 @@ impl QR {
     }
 
     fn draw_alignment_pattern_at(&mut self, r: i16, c: i16) {
+        // Positions run from 6 to w - 7, the corners other than bottom right are under finders
         let w = self.width as i16;
-        if (r == 6 && (c == 6 || c - w == -7)) || (r - w == -7 && c == 6) {
+        if (r == 6 && (c == 6 || c == w - 7)) || (r == w - 7 && c == 6) {
             return;
         }
         for i in -2..=2 {
@@ -378,11 +467,46 @@ impl QR {
 
 #[cfg(test)]
 mod alignment_pattern_tests {
+    use test_case::test_case;
+
     use crate::{
         metadata::{ECLevel, Palette, Version},
-        qr::QR,
+        qr::{Module, QR},
     };
 
+    #[test_case(Version::Normal(1))]
+    #[test_case(Version::Normal(2))]
+    #[test_case(Version::Normal(7))]
+    #[test_case(Version::Normal(40))]
+    fn test_alignment_pattern_count(version: Version) {
+        let mut qr = QR::new(version, ECLevel::L, Palette::Mono);
+        qr.draw_alignment_patterns();
+        let drawn = qr.grid.iter().filter(|m| matches!(m, Module::Func(_))).count() / 25;
+        assert_eq!(drawn, version.alignment_pattern_count());
+    }
+
+    // Skipped positions are exactly those overlapping finders, the rest are drawn clear of them
+    #[test]
+    fn test_alignment_pattern_count_all_versions() {
+        for v in 1..=40 {
+            let version = Version::Normal(v);
+            let mut qr = QR::new(version, ECLevel::L, Palette::Mono);
+            qr.draw_finder_patterns();
+            let finders = qr.grid.clone();
+            qr.draw_alignment_patterns();
+
+            let overwritten = finders
+                .iter()
+                .zip(&qr.grid)
+                .filter(|(f, m)| !matches!(f, Module::Empty) && f != m)
+                .count();
+            assert_eq!(overwritten, 0, "Alignment overlaps finder: Version {v}");
+
+            let drawn = finders.iter().zip(&qr.grid).filter(|(f, m)| f != m).count();
+            assert_eq!(drawn, version.alignment_pattern_count() * 25, "Version {v}");
+        }
+    }
+
     #[test]
     fn test_alignment_pattern_1() {
         let mut qr = QR::new(Version::Normal(1), ECLevel::L, Palette::Mono);
@@ -579,26 +703,26 @@ impl QR {
     }
 
     fn draw_format_info(&mut self, format_info: u32) {
-        match self.version {
-            Version::Micro(_) => todo!(),
-            Version::Normal(_) => {
-                self.draw_number(
-                    format_info,
-                    FORMAT_INFO_BIT_LEN,
-                    Module::Format(Color::Light),
-                    Module::Format(Color::Dark),
-                    &FORMAT_INFO_COORDS_QR_MAIN,
-                );
-                self.draw_number(
-                    format_info,
-                    FORMAT_INFO_BIT_LEN,
-                    Module::Format(Color::Light),
-                    Module::Format(Color::Dark),
-                    &FORMAT_INFO_COORDS_QR_SIDE,
-                );
-                self.set(-8, 8, Module::Format(Color::Dark));
+        self.draw_channel_format_info([format_info; 3]);
+    }
+
+    // Each module carries one bit of each channel's format info, so identical infos draw plain
+    // light and dark modules
+    fn draw_channel_format_info(&mut self, format_infos: [u32; 3]) {
+        let coords: &[&[(i16, i16)]] = match self.version {
+            Version::Micro(_) => &[&FORMAT_INFO_COORDS_MICRO],
+            Version::Normal(_) => &[&FORMAT_INFO_COORDS_QR_MAIN, &FORMAT_INFO_COORDS_QR_SIDE],
+        };
+        for coords in coords {
+            for (i, &(r, c)) in coords.iter().enumerate() {
+                let shift = FORMAT_INFO_BIT_LEN - 1 - i;
+                let bits = format_infos.iter().fold(0, |acc, f| (acc << 1) | ((f >> shift) & 1));
+                self.set(r, c, Module::Format(Color::from_channels(bits)));
             }
         }
+        if let Version::Normal(_) = self.version {
+            self.set(-8, 8, Module::Format(Color::Dark));
+        }
     }
 
     fn draw_version_info(&mut self) {
@@ -647,11 +771,36 @@ impl QR {
 
 #[cfg(test)]
 mod qr_information_tests {
+    use test_case::test_case;
+
     use crate::{
-        metadata::{ECLevel, Palette, Version},
-        qr::QR,
+        builder::QRBuilder,
+        metadata::{
+            Color, ECLevel, Palette, Version, FORMAT_INFO_COORDS_QR_MAIN,
+            FORMAT_INFO_COORDS_QR_SIDE, PALETTE_INFO_COORDS_BL, PALETTE_INFO_COORDS_TR,
+        },
+        qr::{Module, QR},
     };
 
+    // The always dark module sits at (-8, 8), beside the bottom left finder. (8, -8) is a format
+    // bit of the side copy, and neither is claimed by the palette info
+    #[test_case(Version::Normal(1), Palette::Mono)]
+    #[test_case(Version::Normal(7), Palette::Mono)]
+    #[test_case(Version::Normal(7), Palette::Poly)]
+    fn test_dark_module(version: Version, palette: Palette) {
+        let qr = QRBuilder::new(b"Hello").version(version).palette(palette).build().unwrap();
+        assert_eq!(qr.get(-8, 8), Module::Format(Color::Dark));
+        assert!(matches!(qr.get(8, -8), Module::Format(_)));
+
+        assert!(FORMAT_INFO_COORDS_QR_SIDE.contains(&(8, -8)));
+        let palette_coords = PALETTE_INFO_COORDS_BL.iter().chain(&PALETTE_INFO_COORDS_TR);
+        for coord in palette_coords {
+            assert_ne!(*coord, (-8, 8));
+            assert!(!FORMAT_INFO_COORDS_QR_MAIN.contains(coord));
+            assert!(!FORMAT_INFO_COORDS_QR_SIDE.contains(coord));
+        }
+    }
+
     #[test]
     fn test_version_info_1() {
         let mut qr = QR::new(Version::Normal(1), ECLevel::L, Palette::Mono);
@@ -839,15 +988,43 @@ impl QR {
         debug_assert!(!self.grid.contains(&Module::Empty), "Empty module found in debug");
     }
 
+    // Poly payload is the three channel payloads chained, each module carries one bit of each
     fn draw_payload(&mut self, payload: &[u8]) {
         let mut coords = EncRegionIter::new(self.version);
-        self.draw_codewords(payload, &mut coords);
+        match self.palette {
+            Palette::Mono => self.draw_codewords(payload, &mut coords),
+            Palette::Poly => {
+                let channels = payload.chunks(self.version.channel_codewords()).collect::<Vec<_>>();
+                debug_assert!(channels.len() == 3, "Poly payload should have 3 channels");
+                self.draw_poly_codewords(&channels, &mut coords);
+            }
+        }
         self.fill_remainder_bits(&mut coords);
     }
 
+    fn draw_poly_codewords(&mut self, channels: &[&[u8]], coords: &mut EncRegionIter) {
+        let half_codeword = self.version.half_codeword(self.ec_level);
+        for i in 0..channels[0].len() {
+            let low_bit = if half_codeword == Some(i) { 4 } else { 0 };
+            for b in (low_bit..8).rev() {
+                let bits =
+                    channels.iter().fold(0, |acc, ch| (acc << 1) | ((ch[i] >> b) & 1) as u32);
+                let module = Module::Data(Color::from_channels(bits));
+                for (r, c) in coords.by_ref() {
+                    if matches!(self.get(r, c), Module::Empty) {
+                        self.set(r, c, module);
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
     fn draw_codewords(&mut self, codewords: &[u8], coords: &mut EncRegionIter) {
-        for &codeword in codewords.iter() {
-            for i in (0..8).rev() {
+        let half_codeword = self.version.half_codeword(self.ec_level);
+        for (k, &codeword) in codewords.iter().enumerate() {
+            let low_bit = if half_codeword == Some(k) { 4 } else { 0 };
+            for i in (low_bit..8).rev() {
                 let bit = (codeword >> i) & 1;
                 let module = Module::Data(if bit & 1 == 0 { Color::Light } else { Color::Dark });
                 for (r, c) in coords.by_ref() {
@@ -873,7 +1050,7 @@ impl QR {
     }
 
     pub fn mask(&mut self, pattern: MaskPattern) {
-        let mask_function = pattern.mask_functions();
+        let mask_function = pattern.mask_functions_for(self.version);
         let w = self.width as i16;
         for r in 0..w {
             for c in 0..w {
@@ -884,82 +1061,1746 @@ impl QR {
                 }
             }
         }
-        let format_info = generate_format_info_qr(self.ec_level, pattern);
-        self.draw_format_info(format_info);
+        let format_info = |ec_level| match self.version {
+            Version::Micro(_) => generate_format_info_micro(self.version, ec_level, pattern),
+            Version::Normal(_) => generate_format_info_qr(ec_level, pattern),
+        };
+        let ec_levels = self.channel_ec_levels.unwrap_or([self.ec_level; 3]);
+        self.draw_channel_format_info(ec_levels.map(format_info));
+        self.mask_pattern = Some(pattern);
+        self.mask_penalty = None;
+    }
+
+    // Inverse of draw_encoding_region, the interleaved data and ecc codewords with the mask undone.
+    // Poly yields the three channel payloads chained
+    pub fn codewords(&self) -> Vec<u8> {
+        let mask_function = self.mask_pattern.map(|m| m.mask_functions_for(self.version));
+        let channel_count = if let Palette::Poly = self.palette { 3 } else { 1 };
+        let half_codeword = self.version.half_codeword(self.ec_level);
+        let mut codewords = Vec::with_capacity(self.version.total_codewords(self.palette));
+        for ch in 0..channel_count {
+            let mut coords = EncRegionIter::new(self.version)
+                .filter(|&(r, c)| matches!(self.get(r, c), Module::Data(_)));
+            for k in 0..self.version.channel_codewords() {
+                let bit_len = if half_codeword == Some(k) { 4 } else { 8 };
+                let mut codeword = 0;
+                for (r, c) in coords.by_ref().take(bit_len) {
+                    let flip = mask_function.is_some_and(|f| f(r, c)) as u8;
+                    codeword = (codeword << 1) | (self.get(r, c).channel(ch) ^ flip);
+                }
+                codewords.push(codeword << (8 - bit_len));
+            }
+        }
+        codewords
     }
 }
 
-// Render
+#[cfg(test)]
+mod encoding_region_tests {
+    use test_case::test_case;
+
+    use crate::{
+        builder::QRBuilder,
+        codec::encode_with_version,
+        ec::{ecc, interleave},
+        metadata::{ECLevel, Palette, Version},
+        qr::QR,
+    };
+
+    #[test_case("Hello, world!🌎", Version::Normal(1), ECLevel::L, Palette::Mono)]
+    #[test_case("Hello, world!🌎", Version::Normal(5), ECLevel::Q, Palette::Mono)]
+    #[test_case("Hello, world!🌎", Version::Normal(7), ECLevel::M, Palette::Poly)]
+    #[test_case("12345", Version::Micro(1), ECLevel::L, Palette::Mono)]
+    #[test_case("HELLO", Version::Micro(2), ECLevel::M, Palette::Mono)]
+    #[test_case("Hello", Version::Micro(3), ECLevel::M, Palette::Mono)]
+    #[test_case("Hello, wo", Version::Micro(4), ECLevel::Q, Palette::Mono)]
+    fn test_codewords(data: &str, version: Version, ec_level: ECLevel, palette: Palette) {
+        let data = data.as_bytes();
+        let qr = QRBuilder::new(data)
+            .version(version)
+            .ec_level(ec_level)
+            .palette(palette)
+            .build()
+            .unwrap();
+
+        let (encoded, _, _, _) = encode_with_version(data, ec_level, version, palette).unwrap();
+        let channel_count = if let Palette::Poly = palette { 3 } else { 1 };
+        let mut exp_codewords = Vec::new();
+        for channel_data in encoded.chunks(encoded.len() / channel_count) {
+            let (data_blocks, ecc_blocks) = ecc(channel_data, version, ec_level);
+            exp_codewords.extend(interleave(&data_blocks));
+            exp_codewords.extend(interleave(&ecc_blocks));
+        }
+
+        assert_eq!(qr.codewords(), exp_codewords);
+    }
+
+    // Placement starts upwards in the bottom right column pair, so the set bits of the first
+    // codeword land on rows 20 and 17
+    #[test]
+    fn test_draw_encoding_region() {
+        let mut qr = QR::new(Version::Normal(1), ECLevel::L, Palette::Mono);
+        qr.draw_all_function_patterns();
+        let mut payload = vec![0; Version::Normal(1).channel_codewords()];
+        payload[0] = 0b1100_0011;
+        qr.draw_encoding_region(&payload);
+        assert_eq!(
+            qr.to_debug_str(),
+            "\n\
+             fffffffFmDDDDFfffffff\n\
+             fFFFFFfFmDDDDFfFFFFFf\n\
+             fFfffFfFmDDDDFfFfffFf\n\
+             fFfffFfFmDDDDFfFfffFf\n\
+             fFfffFfFmDDDDFfFfffFf\n\
+             fFFFFFfFmDDDDFfFFFFFf\n\
+             fffffffFfFfFfFfffffff\n\
+             FFFFFFFFmDDDDFFFFFFFF\n\
+             mmmmmmfmmDDDDmmmmmmmm\n\
+             DDDDDDFDDDDDDDDDDDDDD\n\
+             DDDDDDfDDDDDDDDDDDDDD\n\
+             DDDDDDFDDDDDDDDDDDDDD\n\
+             DDDDDDfDDDDDDDDDDDDDD\n\
+             FFFFFFFFmDDDDDDDDDDDD\n\
+             fffffffFmDDDDDDDDDDDD\n\
+             fFFFFFfFmDDDDDDDDDDDD\n\
+             fFfffFfFmDDDDDDDDDDDD\n\
+             fFfffFfFmDDDDDDDDDDdd\n\
+             fFfffFfFmDDDDDDDDDDDD\n\
+             fFFFFFfFmDDDDDDDDDDDD\n\
+             fffffffFmDDDDDDDDDDdd\n"
+        );
+    }
+
+    #[test]
+    fn test_codewords_unmasked() {
+        let data = "Hello, world!🌎".as_bytes();
+        let qr = QRBuilder::new(data).version(Version::Normal(3)).build().unwrap();
+        let unmasked = QRBuilder::new(data).version(Version::Normal(3)).no_mask().build().unwrap();
+        assert_eq!(unmasked.codewords(), qr.codewords());
+    }
+}
+
+// Serialization
 //------------------------------------------------------------------------------
 
-// TODO: Write testcases
+static BYTES_MAGIC: [u8; 4] = *b"QRPM";
+static BYTES_FORMAT_VERSION: u8 = 3;
+static BYTES_HEADER_LEN: usize = 35;
+
+// Header is the magic, format version, version kind & number, ec level, palette, mask (0xFF when
+// unmasked), channel ec levels (2 bits each from red in the high bits, 0xFF when unset) and the 8
+// rgb colors. The grid follows row-major, each module packed as a 3 bit kind and its color: 1 dark
+// bit for mono, 3 channel bits for poly. Formats 1 and 2 are still read, format 1 has no channel
+// ec levels byte and neither has colors, so they come back with PALETTE
 impl QR {
-    pub fn render(&self, module_size: u32) -> GrayImage {
-        let qz_size = if let Version::Normal(_) = self.version { 4 } else { 2 } * module_size;
-        let qr_size = self.width as u32 * module_size;
-        let total_size = qz_size + qr_size + qz_size;
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let (ver_kind, ver_num) = match self.version {
+            Version::Normal(v) => (0, v as u8),
+            Version::Micro(v) => (1, v as u8),
+        };
+        let palette = match self.palette {
+            Palette::Mono => 0,
+            Palette::Poly => 1,
+        };
+        let mask = self.mask_pattern.map_or(0xFF, |m| *m);
+        let channel_ec_levels = self
+            .channel_ec_levels
+            .map_or(0xFF, |l| l.iter().fold(0, |acc, &e| (acc << 2) | e as u8));
+
+        let color_len = Self::color_bit_len(self.palette);
+        let module_len = 3 + color_len;
+        let mut res = Vec::with_capacity(
+            BYTES_HEADER_LEN + (self.grid.len() * module_len as usize).div_ceil(8),
+        );
+        res.extend(BYTES_MAGIC);
+        res.extend([BYTES_FORMAT_VERSION, ver_kind, ver_num, self.ec_level as u8, palette, mask]);
+        res.push(channel_ec_levels);
+        res.extend(self.colors.concat());
+
+        let mut acc = 0_u32;
+        let mut acc_len = 0;
+        for m in self.grid.iter() {
+            let kind = match m {
+                Module::Empty => 0,
+                Module::Func(_) => 1,
+                Module::Version(_) => 2,
+                Module::Format(_) => 3,
+                Module::Palette(_) => 4,
+                Module::Data(_) => 5,
+            };
+            let color = match m {
+                Module::Empty => 0,
+                _ => (0..color_len).fold(0, |acc, ch| (acc << 1) | m.channel(ch as usize) as u32),
+            };
+            acc = (acc << module_len) | (kind << color_len) | color;
+            acc_len += module_len;
+            while acc_len >= 8 {
+                acc_len -= 8;
+                res.push((acc >> acc_len) as u8);
+            }
+            acc &= (1 << acc_len) - 1;
+        }
+        if acc_len > 0 {
+            res.push((acc << (8 - acc_len)) as u8);
+        }
 
-        let mut canvas = GrayImage::new(total_size, total_size);
-        for i in 0..total_size {
-            for j in 0..total_size {
-                if i < qz_size || i >= qz_size + qr_size || j < qz_size || j >= qz_size + qr_size {
-                    canvas.put_pixel(j, i, Luma([255]));
-                    continue;
-                }
-                let r = (i - qz_size) / module_size;
-                let c = (j - qz_size) / module_size;
+        res
+    }
 
-                let color = match self.get(r as i16, c as i16) {
-                    Module::Func(c)
-                    | Module::Format(c)
-                    | Module::Version(c)
-                    | Module::Palette(c)
-                    | Module::Data(c) => c,
-                    Module::Empty => panic!("Empty module found at: {r} {c}"),
-                };
+    pub fn from_bytes(bytes: &[u8]) -> QRResult<Self> {
+        if bytes.len() < 5 || bytes[..4] != BYTES_MAGIC {
+            return Err(QRError::InvalidBytes);
+        }
+        let format = bytes[4];
+        let header_len = match format {
+            1 => 10,
+            2 => 11,
+            f if f == BYTES_FORMAT_VERSION => BYTES_HEADER_LEN,
+            _ => return Err(QRError::InvalidBytes),
+        };
+        if bytes.len() < header_len {
+            return Err(QRError::InvalidBytes);
+        }
 
-                let pixel = match color {
-                    Color::Dark => Luma([0]),
-                    Color::Light => Luma([255]),
-                    Color::Hue(_) => todo!(),
-                };
+        let version = match (bytes[5], bytes[6] as usize) {
+            (0, v @ 1..=40) => Version::Normal(v),
+            (1, v @ 1..=4) => Version::Micro(v),
+            _ => return Err(QRError::InvalidVersion),
+        };
+        let ec_level = match bytes[7] {
+            e @ 0..=3 => ECLevel::from(e),
+            _ => return Err(QRError::InvalidECLevel),
+        };
+        let palette = match bytes[8] {
+            0 => Palette::Mono,
+            1 => Palette::Poly,
+            _ => return Err(QRError::InvalidPalette),
+        };
+        if !version.supports_ec_level(ec_level) {
+            return Err(QRError::UnsupportedECLevel(version, ec_level));
+        }
+        let mask_pattern = match (version, bytes[9]) {
+            (_, 0xFF) => None,
+            (Version::Micro(_), m @ 0..=3) | (Version::Normal(_), m @ 0..=7) => {
+                Some(MaskPattern::new(m))
+            }
+            _ => return Err(QRError::InvalidMaskingPattern),
+        };
+
+        let channel_bits = if format >= 2 { bytes[10] } else { 0xFF };
+        let channel_ec_levels = match (version, palette, channel_bits) {
+            (_, _, 0xFF) => None,
+            (Version::Normal(_), Palette::Poly, l @ 0..0x40) => {
+                Some([4, 2, 0].map(|shift| ECLevel::from((l >> shift) & 0b11)))
+            }
+            _ => return Err(QRError::InvalidECLevel),
+        };
 
-                canvas.put_pixel(j, i, pixel);
+        let mut qr = Self::new(version, ec_level, palette);
+        qr.mask_pattern = mask_pattern;
+        if let Some(ec_levels) = channel_ec_levels {
+            qr.set_channel_ec_levels(ec_levels);
+        }
+        if format >= 3 {
+            for (rgb, chunk) in qr.colors.iter_mut().zip(bytes[11..header_len].chunks(3)) {
+                rgb.copy_from_slice(chunk);
             }
         }
 
-        canvas
+        let color_len = Self::color_bit_len(palette);
+        let module_len = 3 + color_len;
+        let grid_bytes = &bytes[header_len..];
+        if grid_bytes.len() != (qr.grid.len() * module_len as usize).div_ceil(8) {
+            return Err(QRError::InvalidBytes);
+        }
+
+        for (i, m) in qr.grid.iter_mut().enumerate() {
+            let offset = i * module_len as usize;
+            let bits = (offset..offset + module_len as usize)
+                .fold(0, |acc, j| (acc << 1) | ((grid_bytes[j >> 3] >> (7 - (j & 7))) & 1) as u32);
+            let color = match palette {
+                Palette::Mono => Color::from_channels((bits & 1) * 0b111),
+                Palette::Poly => Color::from_channels(bits & 0b111),
+            };
+            *m = match bits >> color_len {
+                0 => Module::Empty,
+                1 => Module::Func(color),
+                2 => Module::Version(color),
+                3 => Module::Format(color),
+                4 => Module::Palette(color),
+                5 => Module::Data(color),
+                _ => return Err(QRError::InvalidBytes),
+            };
+        }
+
+        Ok(qr)
     }
 
-    pub fn to_str(&self, module_size: usize) -> String {
-        let qz_size = if let Version::Normal(_) = self.version { 4 } else { 2 } * module_size;
-        let qr_size = self.width * module_size;
-        let total_size = qz_size + qr_size + qz_size;
+    fn color_bit_len(palette: Palette) -> u32 {
+        match palette {
+            Palette::Mono => 1,
+            Palette::Poly => 3,
+        }
+    }
+}
 
-        let mut canvas = String::new();
-        for i in 0..total_size {
-            for j in 0..total_size {
-                if i < qz_size || i >= qz_size + qr_size || j < qz_size || j >= qz_size + qr_size {
-                    canvas.push('█');
-                    continue;
-                }
-                let r = ((i - qz_size) / module_size) as i16;
-                let c = ((j - qz_size) / module_size) as i16;
+#[cfg(test)]
+mod serialization_tests {
+    use test_case::test_case;
 
-                let color = match self.get(r, c) {
-                    Module::Func(c)
-                    | Module::Format(c)
-                    | Module::Version(c)
-                    | Module::Palette(c)
-                    | Module::Data(c) => c,
-                    Module::Empty => panic!("Empty module found at: {r} {c}"),
-                };
-                canvas.push(color.select('█', ' '));
+    use super::QR;
+    use crate::{
+        builder::QRBuilder,
+        error::QRError,
+        metadata::{ECLevel, Palette, Version, PALETTE},
+        qr::Module,
+    };
+
+    #[test_case(Version::Normal(1), ECLevel::L, Palette::Mono)]
+    #[test_case(Version::Normal(7), ECLevel::M, Palette::Mono)]
+    #[test_case(Version::Normal(10), ECLevel::Q, Palette::Poly)]
+    #[test_case(Version::Normal(27), ECLevel::H, Palette::Mono)]
+    #[test_case(Version::Normal(40), ECLevel::L, Palette::Poly)]
+    fn test_bytes_round_trip(version: Version, ec_level: ECLevel, palette: Palette) {
+        let qr = QRBuilder::new("Hello, world!🌎".as_bytes())
+            .version(version)
+            .ec_level(ec_level)
+            .palette(palette)
+            .build()
+            .unwrap();
+
+        let restored = QR::from_bytes(&qr.to_bytes()).unwrap();
+
+        assert_eq!(restored.to_debug_str(), qr.to_debug_str());
+        assert_eq!(restored.metadata().to_string(), qr.metadata().to_string());
+        let w = qr.width() as i16;
+        for r in 0..w {
+            for c in 0..w {
+                assert_eq!(restored.get(r, c), qr.get(r, c), "{r} {c}");
             }
-            canvas.push('\n');
         }
+    }
 
-        canvas
+    #[test]
+    fn test_bytes_round_trip_unfinished_grid() {
+        let mut qr = QR::new(Version::Normal(3), ECLevel::L, Palette::Mono);
+        qr.draw_all_function_patterns();
+
+        let restored = QR::from_bytes(&qr.to_bytes()).unwrap();
+
+        assert_eq!(restored.to_debug_str(), qr.to_debug_str());
+        assert_eq!(restored.mask_pattern(), None);
+        assert_eq!(restored.get(10, 10), Module::Empty);
+    }
+
+    #[test]
+    fn test_from_bytes_invalid() {
+        let qr = QRBuilder::new(b"Hello, world!").version(Version::Normal(1)).build().unwrap();
+        let bytes = qr.to_bytes();
+
+        assert_eq!(QR::from_bytes(&bytes[..8]).unwrap_err(), QRError::InvalidBytes);
+        assert_eq!(QR::from_bytes(&bytes[..bytes.len() - 1]).unwrap_err(), QRError::InvalidBytes);
+
+        let mut bad_magic = bytes.clone();
+        bad_magic[0] = b'X';
+        assert_eq!(QR::from_bytes(&bad_magic).unwrap_err(), QRError::InvalidBytes);
+
+        let mut bad_format = bytes.clone();
+        bad_format[4] = 4;
+        assert_eq!(QR::from_bytes(&bad_format).unwrap_err(), QRError::InvalidBytes);
+
+        let mut bad_version = bytes.clone();
+        bad_version[6] = 41;
+        assert_eq!(QR::from_bytes(&bad_version).unwrap_err(), QRError::InvalidVersion);
+
+        let mut bad_mask = bytes.clone();
+        bad_mask[9] = 8;
+        assert_eq!(QR::from_bytes(&bad_mask).unwrap_err(), QRError::InvalidMaskingPattern);
+
+        // Channel ec levels on a mono code
+        let mut bad_channels = bytes.clone();
+        bad_channels[10] = 0;
+        assert_eq!(QR::from_bytes(&bad_channels).unwrap_err(), QRError::InvalidECLevel);
+    }
+
+    #[test]
+    fn test_from_bytes_invalid_micro() {
+        let qr = QRBuilder::new(b"12345").version(Version::Micro(2)).build().unwrap();
+        let bytes = qr.to_bytes();
+        assert!(QR::from_bytes(&bytes).is_ok());
+
+        // Micro codes have only 4 masks
+        let mut bad_mask = bytes.clone();
+        bad_mask[9] = 4;
+        assert_eq!(QR::from_bytes(&bad_mask).unwrap_err(), QRError::InvalidMaskingPattern);
+
+        let mut bad_ec_level = bytes.clone();
+        bad_ec_level[7] = ECLevel::Q as u8;
+        let exp_err = QRError::UnsupportedECLevel(Version::Micro(2), ECLevel::Q);
+        assert_eq!(QR::from_bytes(&bad_ec_level).unwrap_err(), exp_err);
+
+        // Channel ec levels are only for normal poly codes
+        let mut bad_channels = bytes.clone();
+        bad_channels[8] = 1;
+        bad_channels[10] = 0;
+        assert_eq!(QR::from_bytes(&bad_channels).unwrap_err(), QRError::InvalidECLevel);
+    }
+
+    #[test]
+    fn test_bytes_round_trip_channel_ec_levels() {
+        let ec_levels = [ECLevel::H, ECLevel::L, ECLevel::Q];
+        let qr = QRBuilder::new(b"Hello, world!")
+            .version(Version::Normal(5))
+            .palette(Palette::Poly)
+            .channel_ec_levels(ec_levels)
+            .build()
+            .unwrap();
+
+        let restored = QR::from_bytes(&qr.to_bytes()).unwrap();
+
+        assert_eq!(restored.channel_ec_levels(), Some(ec_levels));
+        assert_eq!(restored.ec_level(), ECLevel::H);
+        assert_eq!(restored.to_debug_str(), qr.to_debug_str());
+    }
+
+    #[test]
+    fn test_bytes_round_trip_colors() {
+        let colors = PALETTE.map(|rgb| rgb.map(|v| if v == 0 { 0x20 } else { 0xD0 }));
+        let qr = QRBuilder::new(b"Hello, world!")
+            .version(Version::Normal(2))
+            .palette(Palette::Poly)
+            .colors(colors)
+            .build()
+            .unwrap();
+
+        let restored = QR::from_bytes(&qr.to_bytes()).unwrap();
+
+        assert_eq!(restored.colors(), colors);
+        assert_eq!(restored.to_debug_str(), qr.to_debug_str());
+    }
+
+    // Format 1 has no channel ec levels byte, and neither format 1 nor 2 has colors
+    #[test_case(1, 10)]
+    #[test_case(2, 11)]
+    fn test_from_bytes_older_format(format: u8, header_len: usize) {
+        let ec_levels = [ECLevel::H, ECLevel::L, ECLevel::Q];
+        let colors = PALETTE.map(|rgb| rgb.map(|v| if v == 0 { 0x20 } else { 0xD0 }));
+        let qr = QRBuilder::new(b"Hello, world!")
+            .version(Version::Normal(5))
+            .palette(Palette::Poly)
+            .channel_ec_levels(ec_levels)
+            .colors(colors)
+            .build()
+            .unwrap();
+        let mut bytes = qr.to_bytes();
+        bytes[4] = format;
+        bytes.drain(header_len..35);
+
+        let restored = QR::from_bytes(&bytes).unwrap();
+
+        let exp_ec_levels = if format >= 2 { Some(ec_levels) } else { None };
+        assert_eq!(restored.channel_ec_levels(), exp_ec_levels);
+        assert_eq!(restored.colors(), PALETTE);
+        assert_eq!(restored.to_debug_str(), qr.to_debug_str());
+    }
+}
+
+// Module matrix
+//------------------------------------------------------------------------------
+
+impl QR {
+    // Wraps a row-major, already masked matrix (true is dark) from another tool. Cells are
+    // classified by position, and the mask is recovered from the format info when it is intact.
+    // A bool per module can't hold poly colors, so Poly errors with InvalidPalette
+    pub fn from_matrix(
+        version: Version,
+        ec_level: ECLevel,
+        palette: Palette,
+        modules: &[bool],
+    ) -> QRResult<Self> {
+        if let Palette::Poly = palette {
+            return Err(QRError::InvalidPalette);
+        }
+
+        let colors = modules
+            .iter()
+            .map(|&dark| if dark { Color::Dark } else { Color::Light })
+            .collect::<Vec<_>>();
+        Self::from_colors(version, ec_level, palette, &colors)
+    }
+
+    // Like from_matrix, but with the color of every module so poly grids keep their hues. The mask
+    // is recovered from the red channel's format info
+    pub(crate) fn from_colors(
+        version: Version,
+        ec_level: ECLevel,
+        palette: Palette,
+        colors: &[Color],
+    ) -> QRResult<Self> {
+        if !matches!(version, Version::Micro(1..=4) | Version::Normal(1..=40)) {
+            return Err(QRError::InvalidVersion);
+        }
+        if !version.supports_ec_level(ec_level) {
+            return Err(QRError::UnsupportedECLevel(version, ec_level));
+        }
+
+        let mut qr = Self::new(version, ec_level, palette);
+        if colors.len() != qr.grid.len() {
+            return Err(QRError::InvalidMatrix);
+        }
+
+        qr.draw_all_function_patterns();
+        qr.reserve_format_area();
+        qr.draw_version_info();
+        for (m, &color) in qr.grid.iter_mut().zip(colors) {
+            *m = match m {
+                Module::Empty | Module::Data(_) => Module::Data(color),
+                Module::Func(_) => Module::Func(color),
+                Module::Version(_) => Module::Version(color),
+                Module::Format(_) => Module::Format(color),
+                Module::Palette(_) => Module::Palette(color),
+            };
+        }
+
+        // Micro format info also names the version, which has to agree
+        let format_coords = match version {
+            Version::Micro(_) => &FORMAT_INFO_COORDS_MICRO[..],
+            Version::Normal(_) => &FORMAT_INFO_COORDS_QR_MAIN[..],
+        };
+        let format_info = format_coords
+            .iter()
+            .fold(0, |acc, &(r, c)| (acc << 1) | qr.get(r, c).channel(0) as u32);
+        qr.mask_pattern = match version {
+            Version::Micro(_) if FORMAT_INFOS_MICRO.contains(&format_info) => {
+                let (v, _, mask_pattern) = parse_format_info_micro(format_info ^ FORMAT_MASK_MICRO);
+                (v == version).then_some(mask_pattern)
+            }
+            Version::Normal(_) if FORMAT_INFOS_QR.contains(&format_info) => {
+                Some(parse_format_info_qr(format_info ^ FORMAT_MASK).1)
+            }
+            _ => None,
+        };
+
+        Ok(qr)
+    }
+
+    // Rows of the masked grid with true for dark, the inverse of from_matrix once flattened.
+    // Hue modules of poly codes count as light
+    pub fn to_bool_matrix(&self) -> Vec<Vec<bool>> {
+        let w = self.width as i16;
+        (0..w).map(|r| (0..w).map(|c| matches!(*self.get(r, c), Color::Dark)).collect()).collect()
+    }
+
+    // Row and column of every module whose color differs, e.g. between the ideal code and one
+    // rebuilt from a scan of its print. Codes of different versions error with InvalidVersion
+    pub fn diff(&self, other: &QR) -> QRResult<Vec<(usize, usize)>> {
+        if self.version != other.version {
+            return Err(QRError::InvalidVersion);
+        }
+
+        let w = self.width as i16;
+        Ok((0..w)
+            .flat_map(|r| (0..w).map(move |c| (r, c)))
+            .filter(|&(r, c)| *self.get(r, c) != *other.get(r, c))
+            .map(|(r, c)| (r as usize, c as usize))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod matrix_tests {
+    use test_case::test_case;
+
+    use super::{Module, QR};
+    use crate::{
+        builder::QRBuilder,
+        error::QRError,
+        metadata::{ECLevel, Palette, Version},
+    };
+
+    fn to_matrix(qr: &QR) -> Vec<bool> {
+        qr.to_bool_matrix().concat()
+    }
+
+    #[test]
+    fn test_to_bool_matrix_finder() {
+        let qr = QRBuilder::new(b"Hello, world!").version(Version::Normal(1)).build().unwrap();
+        let matrix = qr.to_bool_matrix();
+        assert_eq!(matrix.len(), 21);
+        assert!(matrix.iter().all(|row| row.len() == 21));
+
+        let finder = "\
+            #######.\n\
+            #.....#.\n\
+            #.###.#.\n\
+            #.###.#.\n\
+            #.###.#.\n\
+            #.....#.\n\
+            #######.\n\
+            ........";
+        for (r, row) in finder.lines().enumerate() {
+            for (c, m) in row.chars().enumerate() {
+                assert_eq!(matrix[r][c], m == '#', "Row {r}, Col {c}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_from_matrix_round_trip() {
+        let qr = QRBuilder::new(b"Hello, world!")
+            .version(Version::Normal(1))
+            .ec_level(ECLevel::M)
+            .build()
+            .unwrap();
+
+        let restored =
+            QR::from_matrix(Version::Normal(1), ECLevel::M, Palette::Mono, &to_matrix(&qr))
+                .unwrap();
+
+        assert_eq!(restored.to_debug_str(), qr.to_debug_str());
+        assert_eq!(restored.mask_pattern(), qr.mask_pattern());
+        assert_eq!(restored.metadata().to_string(), qr.metadata().to_string());
+    }
+
+    #[test]
+    fn test_from_matrix_version_info() {
+        let qr = QRBuilder::new(b"Hello, world!").version(Version::Normal(7)).build().unwrap();
+
+        let restored =
+            QR::from_matrix(Version::Normal(7), qr.ec_level(), Palette::Mono, &to_matrix(&qr))
+                .unwrap();
+
+        assert_eq!(restored.to_debug_str(), qr.to_debug_str());
+    }
+
+    #[test]
+    fn test_diff() {
+        let qr = QRBuilder::new(b"Hello, world!").version(Version::Normal(2)).build().unwrap();
+        assert!(qr.diff(&qr).unwrap().is_empty());
+
+        let mut corrupted = qr.clone();
+        let Module::Data(clr) = qr.get(12, 15) else { panic!("Not a data module") };
+        corrupted.set(12, 15, Module::Data(!clr));
+        assert_eq!(qr.diff(&corrupted), Ok(vec![(12, 15)]));
+        assert_eq!(corrupted.diff(&qr), Ok(vec![(12, 15)]));
+
+        let smaller = QRBuilder::new(b"Hello").version(Version::Normal(1)).build().unwrap();
+        assert_eq!(qr.diff(&smaller), Err(QRError::InvalidVersion));
+    }
+
+    #[test]
+    fn test_from_matrix_invalid_size() {
+        let modules = vec![false; 21 * 21 - 1];
+        let res = QR::from_matrix(Version::Normal(1), ECLevel::L, Palette::Mono, &modules);
+        assert_eq!(res.unwrap_err(), QRError::InvalidMatrix);
+    }
+
+    #[test_case(Version::Micro(1), ECLevel::L)]
+    #[test_case(Version::Micro(2), ECLevel::M)]
+    #[test_case(Version::Micro(3), ECLevel::L)]
+    #[test_case(Version::Micro(4), ECLevel::Q)]
+    fn test_from_matrix_micro(version: Version, ec_level: ECLevel) {
+        let qr = QRBuilder::new(b"123").version(version).ec_level(ec_level).build().unwrap();
+
+        let restored = QR::from_matrix(version, ec_level, Palette::Mono, &to_matrix(&qr)).unwrap();
+
+        assert_eq!(restored.to_debug_str(), qr.to_debug_str());
+        assert!(restored.mask_pattern().is_some());
+        assert_eq!(restored.mask_pattern(), qr.mask_pattern());
+    }
+
+    #[test]
+    fn test_from_matrix_invalid_args() {
+        let modules = vec![false; 21 * 21];
+        let res = QR::from_matrix(Version::Normal(41), ECLevel::L, Palette::Mono, &modules);
+        assert_eq!(res.unwrap_err(), QRError::InvalidVersion);
+        let res = QR::from_matrix(Version::Micro(0), ECLevel::L, Palette::Mono, &modules);
+        assert_eq!(res.unwrap_err(), QRError::InvalidVersion);
+
+        let modules = vec![false; 11 * 11];
+        let res = QR::from_matrix(Version::Micro(1), ECLevel::M, Palette::Mono, &modules);
+        assert_eq!(res.unwrap_err(), QRError::UnsupportedECLevel(Version::Micro(1), ECLevel::M));
+
+        let modules = vec![false; 21 * 21];
+        let res = QR::from_matrix(Version::Normal(1), ECLevel::L, Palette::Poly, &modules);
+        assert_eq!(res.unwrap_err(), QRError::InvalidPalette);
+    }
+}
+
+// Render
+//------------------------------------------------------------------------------
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum ModuleShape {
+    Square,
+    Circle,
+    // Corner radius as a fraction of module size, up to 0.5
+    RoundedSquare(f32),
+}
+
+impl ModuleShape {
+    // Whether the point at (u, v), as fractions of the module from its top left, is inside
+    fn contains(self, u: f32, v: f32) -> bool {
+        match self {
+            Self::Square => true,
+            Self::Circle => {
+                let (du, dv) = (u - 0.5, v - 0.5);
+                du * du + dv * dv <= 0.25
+            }
+            Self::RoundedSquare(radius) => {
+                let radius = radius.clamp(0.0, 0.5);
+                let du = (radius - u).max(u - 1.0 + radius).max(0.0);
+                let dv = (radius - v).max(v - 1.0 + radius).max(0.0);
+                du * du + dv * dv <= radius * radius
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+static MM_PER_INCH: f64 = 25.4;
+
+// Smallest module size in pixels printing at least mm_per_module at the given dpi. Sizes that are
+// a whole number of pixels up to float error aren't rounded up a further pixel
+#[cfg(feature = "std")]
+pub fn modules_px_for(mm_per_module: f32, dpi: f32) -> u32 {
+    let px = mm_per_module as f64 * dpi as f64 / MM_PER_INCH;
+    ((px - 1e-6).ceil() as u32).max(1)
+}
+
+// TODO: Write testcases
+impl QR {
+    #[cfg(feature = "std")]
+    pub fn render(&self, module_size: u32) -> GrayImage {
+        let qz_size = if let Version::Normal(_) = self.version { 4 } else { 2 } * module_size;
+        let qr_size = self.width as u32 * module_size;
+        let total_size = qz_size + qr_size + qz_size;
+
+        let mut canvas = GrayImage::new(total_size, total_size);
+        for i in 0..total_size {
+            for j in 0..total_size {
+                if i < qz_size || i >= qz_size + qr_size || j < qz_size || j >= qz_size + qr_size {
+                    canvas.put_pixel(j, i, Luma::from(Color::Light));
+                    continue;
+                }
+                let r = (i - qz_size) / module_size;
+                let c = (j - qz_size) / module_size;
+
+                let color = match self.get(r as i16, c as i16) {
+                    Module::Func(c)
+                    | Module::Format(c)
+                    | Module::Version(c)
+                    | Module::Palette(c)
+                    | Module::Data(c) => c,
+                    Module::Empty => panic!("Empty module found at: {r} {c}"),
+                };
+
+                canvas.put_pixel(j, i, Luma::from(color));
+            }
+        }
+
+        canvas
+    }
+
+    // Renders for a printer at dpi, with modules at least mm_per_module across
+    #[cfg(feature = "std")]
+    pub fn render_for_print(&self, mm_per_module: f32, dpi: f32) -> GrayImage {
+        self.render(modules_px_for(mm_per_module, dpi))
+    }
+
+    #[cfg(feature = "std")]
+    pub fn render_color(&self, module_size: u32) -> RgbImage {
+        let qz_size = if let Version::Normal(_) = self.version { 4 } else { 2 } * module_size;
+        let qr_size = self.width as u32 * module_size;
+        let total_size = qz_size + qr_size + qz_size;
+
+        let mut canvas =
+            RgbImage::from_pixel(total_size, total_size, Rgb(self.color_rgb(Color::Light)));
+        for i in qz_size..qz_size + qr_size {
+            for j in qz_size..qz_size + qr_size {
+                let r = (i - qz_size) / module_size;
+                let c = (j - qz_size) / module_size;
+                let module = self.get(r as i16, c as i16);
+                debug_assert!(module != Module::Empty, "Empty module found at: {r} {c}");
+
+                canvas.put_pixel(j, i, Rgb(self.color_rgb(*module)));
+            }
+        }
+
+        canvas
+    }
+
+    // Composites the logo over a centred square covering the given fraction of the symbol area.
+    // Fails if the square hides more codewords of any block than ecc can recover in that block,
+    // since that's what the occluded modules cost a reader
+    #[cfg(feature = "std")]
+    pub fn render_with_logo(
+        &self,
+        module_size: u32,
+        logo: &RgbImage,
+        coverage: f32,
+    ) -> QRResult<RgbImage> {
+        let w = self.width;
+        let mut side = ((coverage.clamp(0.0, 1.0).sqrt() * w as f32).ceil() as usize).min(w);
+        if (w - side) & 1 == 1 {
+            side += 1;
+        }
+        let start = ((w - side) / 2) as i16;
+        let end = start + side as i16;
+
+        // Data modules in placement order, 8 to a codeword except the 4 bit one of M1 and M3.
+        // Remainder bits belong to no codeword
+        let half_codeword = self.version.half_codeword(self.ec_level);
+        let codewords = (0..self.version.channel_codewords())
+            .flat_map(|i| core::iter::repeat_n(i, if half_codeword == Some(i) { 4 } else { 8 }));
+        let mut hidden = EncRegionIter::new(self.version)
+            .filter(|&(r, c)| matches!(self.get(r, c), Module::Data(_)))
+            .zip(codewords)
+            .filter(|((r, c), _)| (start..end).contains(r) && (start..end).contains(c))
+            .map(|(_, i)| i)
+            .collect::<Vec<_>>();
+        hidden.dedup();
+
+        // Poly modules hide the same codewords in every channel, each at its own ec level
+        let channel_count = if let Palette::Poly = self.palette { 3 } else { 1 };
+        let ec_levels = self.channel_ec_levels.unwrap_or([self.ec_level; 3]);
+        for &ec_level in &ec_levels[..channel_count] {
+            let (_, count1, _, count2) = self.version.data_codewords_per_block(ec_level);
+            let mut block_hidden = vec![0; count1 + count2];
+            hidden
+                .iter()
+                .for_each(|&i| block_hidden[codeword_block(i, self.version, ec_level)] += 1);
+            let capacity = block_error_correction_capacity(self.version, ec_level);
+            if block_hidden.into_iter().any(|n| n > capacity) {
+                return Err(QRError::LogoTooLarge);
+            }
+        }
+
+        let qz_size = if let Version::Normal(_) = self.version { 4 } else { 2 };
+        let offset = ((qz_size + start as u32) * module_size) as i64;
+        let logo_size = side as u32 * module_size;
+        let mut canvas = self.render_color(module_size);
+        overlay(
+            &mut canvas,
+            &resize(logo, logo_size, logo_size, FilterType::Triangle),
+            offset,
+            offset,
+        );
+
+        Ok(canvas)
+    }
+
+    // Gaussian blur over render_color for display. The result is sampled back and rejected if any
+    // finder module reads wrong, which keeps the radius small relative to module size
+    #[cfg(feature = "std")]
+    pub fn render_smoothed(&self, module_size: u32, radius: f32) -> QRResult<RgbImage> {
+        let canvas = blur(&self.render_color(module_size), radius);
+
+        let deqr = DeQR::from_rgb(&canvas, self.version);
+        if deqr.is_inverted() || deqr.count_finder_mismatches() > 0 {
+            return Err(QRError::SmoothingTooStrong);
+        }
+
+        Ok(canvas)
+    }
+
+    // Draws each non light module as the given shape over a light background. Finder modules are
+    // drawn with finder_shape, keep it square for the most reliable scans
+    #[cfg(feature = "std")]
+    pub fn render_styled(
+        &self,
+        module_size: u32,
+        shape: ModuleShape,
+        finder_shape: ModuleShape,
+    ) -> RgbImage {
+        let qz_size = if let Version::Normal(_) = self.version { 4 } else { 2 } * module_size;
+        let qr_size = self.width as u32 * module_size;
+        let total_size = qz_size + qr_size + qz_size;
+        let w = self.width as i16;
+        let is_finder = |r: i16, c: i16| match self.version {
+            Version::Micro(_) => r < 7 && c < 7,
+            Version::Normal(_) => (r < 7 && (c < 7 || c >= w - 7)) || (r >= w - 7 && c < 7),
+        };
+
+        let mut canvas =
+            RgbImage::from_pixel(total_size, total_size, Rgb(self.color_rgb(Color::Light)));
+        for i in qz_size..qz_size + qr_size {
+            for j in qz_size..qz_size + qr_size {
+                let (r, c) = ((i - qz_size) / module_size, (j - qz_size) / module_size);
+                let (r, c) = (r as i16, c as i16);
+                let module = self.get(r, c);
+                debug_assert!(module != Module::Empty, "Empty module found at: {r} {c}");
+
+                if *module == Color::Light {
+                    continue;
+                }
+                let u = ((j - qz_size) % module_size) as f32 + 0.5;
+                let v = ((i - qz_size) % module_size) as f32 + 0.5;
+                let shape = if is_finder(r, c) { finder_shape } else { shape };
+                if shape.contains(u / module_size as f32, v / module_size as f32) {
+                    canvas.put_pixel(j, i, Rgb(self.color_rgb(*module)));
+                }
+            }
+        }
+
+        canvas
+    }
+
+    // Tiles render_color of each code row by row into cells sized for the largest one, with gap
+    // light pixels between cells. Each code keeps its quiet zone and sits at its cell's top left.
+    // Labels aren't drawn, there's no text rendering to build them on. Zero cols lays out one column
+    #[cfg(feature = "std")]
+    pub fn contact_sheet(codes: &[QR], cols: usize, module_size: u32, gap: u32) -> RgbImage {
+        let cols = cols.max(1);
+        if codes.is_empty() {
+            return RgbImage::new(0, 0);
+        }
+
+        let renders = codes.iter().map(|qr| qr.render_color(module_size)).collect::<Vec<_>>();
+        let cell_size = renders.iter().map(|r| r.width()).max().unwrap_or(0);
+        let rows = renders.len().div_ceil(cols) as u32;
+        let sheet_size = |n: u32| (n * (cell_size + gap)).saturating_sub(gap);
+
+        let mut canvas =
+            RgbImage::from_pixel(sheet_size(cols as u32), sheet_size(rows), Color::Light.rgb());
+        for (i, render) in renders.iter().enumerate() {
+            let x = (i % cols) as u32 * (cell_size + gap);
+            let y = (i / cols) as u32 * (cell_size + gap);
+            overlay(&mut canvas, render, x as i64, y as i64);
+        }
+
+        canvas
+    }
+
+    // Writes an indexed png straight from the grid: 1 bit per pixel for mono, and 4 bits
+    // indexing the code's colors for poly. File and encoding failures surface as QRError::Image
+    #[cfg(feature = "std")]
+    pub fn write_png<P: AsRef<Path>>(&self, path: P, module_size: u32) -> QRResult<()> {
+        let qz_size = if let Version::Normal(_) = self.version { 4 } else { 2 } * module_size;
+        let qr_size = self.width as u32 * module_size;
+        let total_size = qz_size + qr_size + qz_size;
+
+        let (depth, bit_len, palette) = match self.palette {
+            Palette::Mono => {
+                (png::BitDepth::One, 1, [self.colors[0b000], self.colors[0b111]].concat())
+            }
+            Palette::Poly => (png::BitDepth::Four, 4, self.colors.concat()),
+        };
+
+        let row_len = (total_size * bit_len).div_ceil(8) as usize;
+        let mut data = vec![0_u8; row_len * total_size as usize];
+        for i in qz_size..qz_size + qr_size {
+            let row = &mut data[i as usize * row_len..(i as usize + 1) * row_len];
+            for j in qz_size..qz_size + qr_size {
+                let r = (i - qz_size) / module_size;
+                let c = (j - qz_size) / module_size;
+                let module = self.get(r as i16, c as i16);
+                let index = match self.palette {
+                    Palette::Mono => module.channel(0),
+                    Palette::Poly => (0..3).fold(0, |acc, ch| (acc << 1) | module.channel(ch)),
+                };
+                let offset = (j * bit_len) as usize;
+                row[offset >> 3] |= index << (8 - bit_len as usize - (offset & 7));
+            }
+        }
+
+        let file = BufWriter::new(File::create(path)?);
+        let mut encoder = png::Encoder::new(file, total_size, total_size);
+        encoder.set_color(png::ColorType::Indexed);
+        encoder.set_depth(depth);
+        encoder.set_palette(palette);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&data)?;
+        writer.finish()?;
+        Ok(())
+    }
+
+    // Light background with one rect per area of same colored modules. Runs along a row are
+    // merged, then stacked with identical runs directly below, which keeps version 40 small
+    pub fn to_svg(&self, module_size: u32) -> String {
+        self.svg(module_size, true)
+    }
+
+    fn svg(&self, module_size: u32, merge: bool) -> String {
+        let qz_size = if let Version::Normal(_) = self.version { 4 } else { 2 };
+        let total_size = (qz_size + self.width as u32 + qz_size) * module_size;
+        let w = self.width as i16;
+        let fill = |color: Color| {
+            let [r, g, b] = self.color_rgb(color);
+            format!("#{r:02x}{g:02x}{b:02x}")
+        };
+
+        // (row, col, width, height, color) in modules. Rects still open in the previous row are
+        // indexed by their starting column so a matching run can extend them downwards
+        let mut rects: Vec<(i16, i16, i16, i16, Color)> = Vec::new();
+        let mut open = vec![None; self.width];
+        for r in 0..w {
+            let mut c = 0;
+            while c < w {
+                let module = self.get(r, c);
+                debug_assert!(module != Module::Empty, "Empty module found at: {r} {c}");
+
+                let color = *module;
+                let mut run = 1;
+                while merge && c + run < w && *self.get(r, c + run) == color {
+                    run += 1;
+                }
+
+                let prev = open[c as usize].take();
+                if color != Color::Light {
+                    match prev.filter(|&i: &usize| {
+                        let (pr, _, pw, ph, pc) = rects[i];
+                        merge && pr + ph == r && pw == run && pc == color
+                    }) {
+                        Some(i) => {
+                            rects[i].3 += 1;
+                            open[c as usize] = Some(i);
+                        }
+                        None => {
+                            open[c as usize] = Some(rects.len());
+                            rects.push((r, c, run, 1, color));
+                        }
+                    }
+                }
+                open[c as usize + 1..(c + run) as usize].fill(None);
+                c += run;
+            }
+        }
+
+        let mut svg = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"0 0 {total_size} {total_size}\" \
+             shape-rendering=\"crispEdges\">\n"
+        );
+        svg += &format!(
+            "<rect width=\"{total_size}\" height=\"{total_size}\" fill=\"{}\"/>\n",
+            fill(Color::Light)
+        );
+        for (r, c, rw, rh, color) in rects {
+            let x = (qz_size + c as u32) * module_size;
+            let y = (qz_size + r as u32) * module_size;
+            let (rw, rh) = (rw as u32 * module_size, rh as u32 * module_size);
+            svg += &format!(
+                "<rect x=\"{x}\" y=\"{y}\" width=\"{rw}\" height=\"{rh}\" fill=\"{}\"/>\n",
+                fill(color)
+            );
+        }
+        svg.push_str("</svg>\n");
+
+        svg
+    }
+
+    pub fn to_str(&self, module_size: usize) -> String {
+        let qz_size = if let Version::Normal(_) = self.version { 4 } else { 2 } * module_size;
+        let qr_size = self.width * module_size;
+        let total_size = qz_size + qr_size + qz_size;
+
+        let mut canvas = String::new();
+        for i in 0..total_size {
+            for j in 0..total_size {
+                if i < qz_size || i >= qz_size + qr_size || j < qz_size || j >= qz_size + qr_size {
+                    canvas.push('█');
+                    continue;
+                }
+                let r = ((i - qz_size) / module_size) as i16;
+                let c = ((j - qz_size) / module_size) as i16;
+
+                let color = match self.get(r, c) {
+                    Module::Func(c)
+                    | Module::Format(c)
+                    | Module::Version(c)
+                    | Module::Palette(c)
+                    | Module::Data(c) => c,
+                    Module::Empty => panic!("Empty module found at: {r} {c}"),
+                };
+                canvas.push(color.select('█', ' '));
+            }
+            canvas.push('\n');
+        }
+
+        canvas
+    }
+
+    // Packs two module rows into each text row using half blocks, so the code looks square in a
+    // terminal. Follows to_str in drawing light modules filled. The row past the bottom edge is light
+    pub fn render_half_blocks(&self) -> String {
+        let qz_size = if let Version::Normal(_) = self.version { 4 } else { 2 };
+        let total_size = (qz_size + self.width + qz_size) as i16;
+        let qz_size = qz_size as i16;
+        let w = self.width as i16;
+
+        let is_light = |i: i16, j: i16| {
+            let (r, c) = (i - qz_size, j - qz_size);
+            if r < 0 || r >= w || c < 0 || c >= w {
+                return true;
+            }
+            match self.get(r, c) {
+                Module::Empty => panic!("Empty module found at: {r} {c}"),
+                m => *m != Color::Dark,
+            }
+        };
+
+        let mut canvas = String::new();
+        for i in (0..total_size).step_by(2) {
+            for j in 0..total_size {
+                let ch = match (is_light(i, j), is_light(i + 1, j)) {
+                    (true, true) => '█',
+                    (true, false) => '▀',
+                    (false, true) => '▄',
+                    (false, false) => ' ',
+                };
+                canvas.push(ch);
+            }
+            canvas.push('\n');
+        }
+
+        canvas
+    }
+
+    // Packs the code into a caller owned 1 bit per pixel framebuffer, e.g. a monochrome display
+    // on a microcontroller. Rows start every stride bytes, pixels are msb first and dark is set.
+    // Light pixels are cleared, bits past the rendered width are left untouched
+    pub fn render_into_1bpp(
+        &self,
+        buf: &mut [u8],
+        stride: usize,
+        module_size: usize,
+        quiet_zone: usize,
+    ) -> QRResult<()> {
+        let total_size = (quiet_zone + self.width + quiet_zone) * module_size;
+        let row_bytes = total_size.div_ceil(8);
+        if total_size == 0 {
+            return Ok(());
+        }
+        if stride < row_bytes || buf.len() < (total_size - 1) * stride + row_bytes {
+            return Err(QRError::BufferTooSmall);
+        }
+
+        let qz_size = quiet_zone * module_size;
+        let qr_size = self.width * module_size;
+        for i in 0..total_size {
+            let row = &mut buf[i * stride..i * stride + row_bytes];
+            for j in 0..total_size {
+                let dark = i >= qz_size
+                    && i < qz_size + qr_size
+                    && j >= qz_size
+                    && j < qz_size + qr_size
+                    && {
+                        let r = ((i - qz_size) / module_size) as i16;
+                        let c = ((j - qz_size) / module_size) as i16;
+                        debug_assert!(self.get(r, c) != Module::Empty, "Empty module at: {r} {c}");
+                        *self.get(r, c) == Color::Dark
+                    };
+
+                let bit = 0x80 >> (j % 8);
+                if dark {
+                    row[j / 8] |= bit;
+                } else {
+                    row[j / 8] &= !bit;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod render_tests {
+    use image::{DynamicImage, Rgb, RgbImage};
+    use test_case::test_case;
+
+    use crate::{
+        builder::QRBuilder,
+        error::QRError,
+        metadata::{ECLevel, Palette, Version},
+        qr::{modules_px_for, ModuleShape},
+        reader::QRReader,
+    };
+
+    // Paints each rect in order onto a grid of fills, enough to rasterize our own svg output
+    fn rasterize_svg(svg: &str) -> Vec<Vec<String>> {
+        let attr = |rect: &str, name: &str| {
+            rect.split(&format!(" {name}=\""))
+                .nth(1)
+                .map(|v| v.split('"').next().unwrap().to_string())
+        };
+        let num = |rect: &str, name: &str| attr(rect, name).map_or(0, |v| v.parse().unwrap());
+
+        let mut canvas: Vec<Vec<String>> = Vec::new();
+        for rect in svg.split("<rect").skip(1) {
+            let (x, y, w, h): (usize, usize, usize, usize) =
+                (num(rect, "x"), num(rect, "y"), num(rect, "width"), num(rect, "height"));
+            let fill = attr(rect, "fill").unwrap();
+            if canvas.is_empty() {
+                canvas = vec![vec![String::new(); w]; h];
+            }
+            for row in &mut canvas[y..y + h] {
+                row[x..x + w].fill(fill.clone());
+            }
+        }
+        canvas
+    }
+
+    #[test_case("Hello, world!".to_string(), Version::Normal(1), Palette::Mono)]
+    #[test_case("Hello, world!🌎".to_string(), Version::Normal(3), Palette::Poly)]
+    fn test_svg_merged_matches_per_module(data: String, version: Version, palette: Palette) {
+        let qr = QRBuilder::new(data.as_bytes())
+            .version(version)
+            .ec_level(ECLevel::L)
+            .palette(palette)
+            .build()
+            .unwrap();
+
+        let merged = qr.to_svg(2);
+        let per_module = qr.svg(2, false);
+        assert!(merged.len() < per_module.len());
+        assert_eq!(rasterize_svg(&merged), rasterize_svg(&per_module));
+    }
+
+    #[test]
+    fn test_svg_merged_size() {
+        let data = "A111111111111111".repeat(97);
+        let qr = QRBuilder::new(data.as_bytes())
+            .version(Version::Normal(40))
+            .ec_level(ECLevel::M)
+            .build()
+            .unwrap();
+
+        let merged_len = qr.to_svg(1).len();
+        let per_module_len = qr.svg(1, false).len();
+        assert!(
+            merged_len * 2 < per_module_len,
+            "Merged {merged_len}, Per module {per_module_len}"
+        );
+    }
+
+    // Version 1 is at most 29 modules of 3 pixels, 87 bits per row, padded to a 12 byte stride
+    #[test_case(2)]
+    #[test_case(4)]
+    fn test_render_into_1bpp(quiet_zone: usize) {
+        let qr = QRBuilder::new(b"Hello").version(Version::Normal(1)).build().unwrap();
+        let (module_size, stride) = (3, 12);
+        let total_size = (quiet_zone + qr.width() + quiet_zone) * module_size;
+        let mut buf = vec![0xAA; stride * total_size];
+        qr.render_into_1bpp(&mut buf, stride, module_size, quiet_zone).unwrap();
+
+        let matrix = qr.to_bool_matrix();
+        let bit = |x: usize, y: usize| buf[y * stride + x / 8] & (0x80 >> (x % 8)) != 0;
+        for y in 0..total_size {
+            for x in 0..total_size {
+                let (r, c) = (
+                    (y / module_size).wrapping_sub(quiet_zone),
+                    (x / module_size).wrapping_sub(quiet_zone),
+                );
+                let dark = r < qr.width() && c < qr.width() && matrix[r][c];
+                assert_eq!(bit(x, y), dark, "Pixel mismatch at: {x} {y}");
+            }
+            // Padding past the rendered row is the caller's
+            assert!(buf[y * stride + total_size.div_ceil(8)..(y + 1) * stride]
+                .iter()
+                .all(|&b| b == 0xAA));
+        }
+    }
+
+    #[test]
+    fn test_render_into_1bpp_too_small() {
+        let qr = QRBuilder::new(b"Hello").version(Version::Normal(1)).build().unwrap();
+        let total_size = (4 + 21 + 4) * 2;
+        let row_bytes = total_size / 8 + 1;
+
+        let mut buf = vec![0; row_bytes * total_size];
+        assert_eq!(
+            qr.render_into_1bpp(&mut buf, row_bytes - 1, 2, 4),
+            Err(QRError::BufferTooSmall)
+        );
+        assert_eq!(
+            qr.render_into_1bpp(&mut buf[1..], row_bytes, 2, 4),
+            Err(QRError::BufferTooSmall)
+        );
+        assert_eq!(qr.render_into_1bpp(&mut buf, row_bytes, 2, 4), Ok(()));
+    }
+
+    // 0.5mm at 254 dpi is exactly 5px, anything finer needs the next whole pixel
+    #[test_case(0.5, 254.0, 5)]
+    #[test_case(0.51, 254.0, 6)]
+    #[test_case(0.33, 300.0, 4)]
+    #[test_case(0.254, 600.0, 6)]
+    #[test_case(0.01, 72.0, 1)]
+    fn test_modules_px_for(mm_per_module: f32, dpi: f32, exp_px: u32) {
+        let px = modules_px_for(mm_per_module, dpi);
+        assert_eq!(px, exp_px);
+        assert!(px as f32 * 25.4 / dpi >= mm_per_module - 1e-4);
+    }
+
+    #[test]
+    fn test_render_for_print() {
+        let qr = QRBuilder::new(b"Hello").version(Version::Normal(1)).build().unwrap();
+        let img = qr.render_for_print(0.5, 300.0);
+        assert_eq!(img.dimensions(), qr.render(6).dimensions());
+        assert_eq!(img.width(), (4 + 21 + 4) * 6);
+    }
+
+    #[test]
+    fn test_render_half_blocks() {
+        for v in [1, 2, 7] {
+            let version = Version::Normal(v);
+            let qr = QRBuilder::new("Hello, world!".as_bytes())
+                .version(version)
+                .ec_level(ECLevel::L)
+                .build()
+                .unwrap();
+            let full_width = version.width() + 8;
+            let half_blocks = qr.render_half_blocks();
+            let lines = half_blocks.lines().collect::<Vec<_>>();
+            assert_eq!(lines.len(), full_width.div_ceil(2));
+            assert!(lines.iter().all(|l| l.chars().count() == full_width));
+        }
+    }
+
+    #[test]
+    fn test_render_half_blocks_matches_to_str() {
+        let version = Version::Normal(1);
+        let qr = QRBuilder::new("Hello, world!".as_bytes())
+            .version(version)
+            .ec_level(ECLevel::L)
+            .build()
+            .unwrap();
+        let full_rows = qr.to_str(1).lines().map(|l| l.chars().collect()).collect::<Vec<Vec<_>>>();
+        let half_rows = qr.render_half_blocks();
+        for (i, line) in half_rows.lines().enumerate() {
+            for (j, ch) in line.chars().enumerate() {
+                let top = full_rows[i * 2][j] == '█';
+                let bottom = full_rows.get(i * 2 + 1).is_none_or(|r| r[j] == '█');
+                let exp = match (top, bottom) {
+                    (true, true) => '█',
+                    (true, false) => '▀',
+                    (false, true) => '▄',
+                    (false, false) => ' ',
+                };
+                assert_eq!(ch, exp, "{i} {j}");
+            }
+        }
+    }
+
+    #[test_case("Hello, world!🌎".to_string(), Version::Normal(1), ECLevel::L, Palette::Mono)]
+    #[test_case("A11111111111111".repeat(11).to_string(), Version::Normal(7), ECLevel::M, Palette::Mono)]
+    #[test_case("1234567890".repeat(84).to_string(), Version::Normal(10), ECLevel::H, Palette::Poly)]
+    fn test_write_png(data: String, version: Version, ec_level: ECLevel, palette: Palette) {
+        let qr = QRBuilder::new(data.as_bytes())
+            .version(version)
+            .ec_level(ec_level)
+            .palette(palette)
+            .build()
+            .unwrap();
+        let path = std::env::temp_dir().join(format!("qr_pro_max_write_png_{}.png", *version));
+
+        qr.write_png(&path, 3).unwrap();
+        let img = image::open(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let decoded_data = match palette {
+            Palette::Mono => QRReader::read_from_image(&img.to_luma8(), version),
+            Palette::Poly => QRReader::read_from_rgb(&img.to_rgb8(), version),
+        };
+        assert_eq!(decoded_data.unwrap(), data);
+    }
+
+    #[test]
+    fn test_render_with_logo() {
+        let data = "Hello, world!🌎";
+        let version = Version::Normal(5);
+        let qr =
+            QRBuilder::new(data.as_bytes()).version(version).ec_level(ECLevel::H).build().unwrap();
+        let logo = RgbImage::from_fn(64, 64, |x, y| Rgb([(x * 4) as u8, (y * 4) as u8, 128]));
+
+        let img = qr.render_with_logo(10, &logo, 0.1).unwrap();
+        assert_ne!(img, qr.render_color(10));
+
+        let mut img = rqrr::PreparedImage::prepare(DynamicImage::ImageRgb8(img).to_luma8());
+        let grids = img.detect_grids();
+        assert_eq!(grids.len(), 1);
+        let (meta, content) = grids[0].decode().unwrap();
+        assert_eq!(meta.version.0, *version);
+        assert_eq!(content, data);
+    }
+
+    #[test]
+    fn test_render_with_logo_too_large() {
+        let qr = QRBuilder::new("Hello, world!🌎".as_bytes())
+            .version(Version::Normal(5))
+            .ec_level(ECLevel::H)
+            .build()
+            .unwrap();
+        let logo = RgbImage::new(64, 64);
+        assert_eq!(qr.render_with_logo(10, &logo, 0.5).unwrap_err(), QRError::LogoTooLarge);
+
+        // Same coverage that fits H overruns L
+        let qr = QRBuilder::new("Hello, world!🌎".as_bytes())
+            .version(Version::Normal(5))
+            .ec_level(ECLevel::L)
+            .build()
+            .unwrap();
+        assert_eq!(qr.render_with_logo(10, &logo, 0.1).unwrap_err(), QRError::LogoTooLarge);
+
+        // Within what the 2 blocks of 3-H recover together, but past what one of them does alone
+        let qr = QRBuilder::new("Hello, world!🌎".as_bytes())
+            .version(Version::Normal(3))
+            .ec_level(ECLevel::H)
+            .build()
+            .unwrap();
+        assert!(qr.render_with_logo(10, &logo, 0.05).is_ok());
+        assert_eq!(qr.render_with_logo(10, &logo, 0.12).unwrap_err(), QRError::LogoTooLarge);
+    }
+
+    #[test]
+    fn test_render_smoothed() {
+        let data = "Hello, world!🌎";
+        let version = Version::Normal(3);
+        let qr =
+            QRBuilder::new(data.as_bytes()).version(version).ec_level(ECLevel::M).build().unwrap();
+
+        let img = qr.render_smoothed(6, 1.0).unwrap();
+        assert_ne!(img, qr.render_color(6));
+
+        let decoded_data =
+            QRReader::read_from_image(&DynamicImage::ImageRgb8(img).to_luma8(), version);
+        assert_eq!(decoded_data.unwrap(), data);
+    }
+
+    #[test]
+    fn test_render_smoothed_too_strong() {
+        let qr = QRBuilder::new("Hello, world!🌎".as_bytes())
+            .version(Version::Normal(3))
+            .ec_level(ECLevel::M)
+            .build()
+            .unwrap();
+        assert_eq!(qr.render_smoothed(2, 6.0).unwrap_err(), QRError::SmoothingTooStrong);
+    }
+
+    #[test_case(ModuleShape::Circle, ModuleShape::Square)]
+    #[test_case(ModuleShape::RoundedSquare(0.3), ModuleShape::Square)]
+    #[test_case(ModuleShape::Circle, ModuleShape::RoundedSquare(0.25))]
+    fn test_render_styled(shape: ModuleShape, finder_shape: ModuleShape) {
+        let data = "Hello, world!🌎";
+        let version = Version::Normal(3);
+        let qr =
+            QRBuilder::new(data.as_bytes()).version(version).ec_level(ECLevel::H).build().unwrap();
+
+        let img = qr.render_styled(10, shape, finder_shape);
+        assert_ne!(img, qr.render_color(10));
+
+        let mut img = rqrr::PreparedImage::prepare(DynamicImage::ImageRgb8(img).to_luma8());
+        let grids = img.detect_grids();
+        assert_eq!(grids.len(), 1);
+        let (meta, content) = grids[0].decode().unwrap();
+        assert_eq!(meta.version.0, *version);
+        assert_eq!(content, data);
+    }
+
+    #[test]
+    fn test_render_styled_square_matches_render_color() {
+        let qr = QRBuilder::new("Hello, world!🌎".as_bytes())
+            .version(Version::Normal(3))
+            .ec_level(ECLevel::H)
+            .build()
+            .unwrap();
+        let img = qr.render_styled(4, ModuleShape::Square, ModuleShape::Square);
+        assert_eq!(img, qr.render_color(4));
+    }
+
+    #[test]
+    fn test_write_png_smaller_than_rgb() {
+        let data = "A111111111111111".repeat(97);
+        let qr = QRBuilder::new(data.as_bytes())
+            .version(Version::Normal(40))
+            .ec_level(ECLevel::M)
+            .build()
+            .unwrap();
+        let dir = std::env::temp_dir();
+        let (indexed_path, rgb_path) =
+            (dir.join("qr_pro_max_indexed.png"), dir.join("qr_pro_max_rgb.png"));
+
+        qr.write_png(&indexed_path, 10).unwrap();
+        qr.render_color(10).save(&rgb_path).unwrap();
+        let indexed_len = std::fs::metadata(&indexed_path).unwrap().len();
+        let rgb_len = std::fs::metadata(&rgb_path).unwrap().len();
+        std::fs::remove_file(&indexed_path).unwrap();
+        std::fs::remove_file(&rgb_path).unwrap();
+
+        assert!(indexed_len * 4 < rgb_len, "Indexed {indexed_len}, Rgb {rgb_len}");
+    }
+
+    #[test]
+    fn test_write_png_missing_dir() {
+        let qr = QRBuilder::new(b"Hello, world!").build().unwrap();
+        let path = std::env::temp_dir().join("qr_pro_max_missing_dir").join("qr.png");
+        assert!(matches!(qr.write_png(&path, 3), Err(QRError::Image(_))));
+    }
+}
+
+// Error injection
+//------------------------------------------------------------------------------
+
+impl QR {
+    // Flips n distinct data modules picked from the seed, leaving function patterns, format and
+    // version info intact. Flips sharing a codeword, or landing on remainder bits, cost ecc less
+    pub fn corrupt_random(&mut self, n: usize, seed: u64) {
+        let w = self.width as i16;
+        let mut coords = (0..w)
+            .flat_map(|r| (0..w).map(move |c| (r, c)))
+            .filter(|&(r, c)| matches!(self.get(r, c), Module::Data(_)))
+            .collect::<Vec<_>>();
+        debug_assert!(n <= coords.len(), "Only {} data modules to flip: {n}", coords.len());
+
+        // Partial Fisher-Yates shuffle, so no module is flipped twice
+        let mut state = seed;
+        for i in 0..n.min(coords.len()) {
+            let j = i + (splitmix64(&mut state) % (coords.len() - i) as u64) as usize;
+            coords.swap(i, j);
+            let (r, c) = coords[i];
+            if let Module::Data(clr) = self.get(r, c) {
+                self.set(r, c, Module::Data(!clr));
+            }
+        }
+    }
+}
+
+fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+#[cfg(all(test, feature = "std"))]
+mod corrupt_tests {
+    use crate::{
+        builder::QRBuilder,
+        ec::error_correction_capacity,
+        metadata::{ECLevel, Version},
+        qr::{Module, QR},
+    };
+
+    const DATA: &str = "Hello, world!";
+
+    // Version 3 L is a single block, so capacity is the number of codeword errors it recovers
+    fn build() -> QR {
+        QRBuilder::new(DATA.as_bytes())
+            .version(Version::Normal(3))
+            .ec_level(ECLevel::L)
+            .build()
+            .unwrap()
+    }
+
+    fn corrupted_codewords(qr: &QR, corrupted: &QR) -> usize {
+        qr.codewords().iter().zip(corrupted.codewords()).filter(|(a, b)| *a != b).count()
+    }
+
+    // The in tree rectifier only detects errors, rqrr corrects them
+    fn decode(qr: &QR) -> Option<String> {
+        let mut img = rqrr::PreparedImage::prepare(qr.render(4));
+        let grids = img.detect_grids();
+        grids.first().and_then(|g| g.decode().ok()).map(|(_, content)| content)
+    }
+
+    #[test]
+    fn test_corrupt_random_flips_data_only() {
+        let qr = build();
+        let mut corrupted = qr.clone();
+        corrupted.corrupt_random(20, 7);
+
+        let w = qr.width() as i16;
+        let coords = (0..w).flat_map(|r| (0..w).map(move |c| (r, c)));
+        let flipped =
+            coords.filter(|&(r, c)| qr.get(r, c) != corrupted.get(r, c)).collect::<Vec<_>>();
+        assert_eq!(flipped.len(), 20);
+        assert!(flipped.iter().all(|&(r, c)| matches!(qr.get(r, c), Module::Data(_))));
+
+        let mut again = qr.clone();
+        again.corrupt_random(20, 7);
+        assert_eq!(again.to_debug_str(), corrupted.to_debug_str());
+    }
+
+    #[test]
+    fn test_corrupt_random_within_capacity() {
+        let qr = build();
+        let capacity = error_correction_capacity(Version::Normal(3), ECLevel::L);
+        for seed in 0..8 {
+            let mut corrupted = qr.clone();
+            corrupted.corrupt_random(capacity, seed);
+            assert_eq!(decode(&corrupted).as_deref(), Some(DATA), "Seed {seed}");
+        }
+    }
+
+    #[test]
+    fn test_corrupt_random_beyond_capacity() {
+        let qr = build();
+        let capacity = error_correction_capacity(Version::Normal(3), ECLevel::L);
+
+        // A seed whose flips all land in different codewords
+        let corrupted = (0..)
+            .map(|seed| {
+                let mut corrupted = qr.clone();
+                corrupted.corrupt_random(capacity + 1, seed);
+                corrupted
+            })
+            .find(|corrupted| corrupted_codewords(&qr, corrupted) == capacity + 1)
+            .unwrap();
+
+        assert_ne!(decode(&corrupted).as_deref(), Some(DATA));
+    }
+}
+
+// Noisy test images
+//------------------------------------------------------------------------------
+
+#[cfg(all(feature = "std", any(test, feature = "testkit")))]
+#[derive(Debug, Default, PartialEq, Copy, Clone)]
+pub struct NoiseModel {
+    // Gaussian blur sigma in pixels
+    pub blur: f32,
+    // Fraction of pixels flipped to pure black or white
+    pub salt_pepper: f32,
+    // Keystone warp, fraction of the width the top corners are pulled inwards by
+    pub warp: f32,
+}
+
+#[cfg(all(feature = "std", any(test, feature = "testkit")))]
+impl QR {
+    // Degrades a render in a reproducible way: warp, then blur, then salt & pepper from the seed
+    pub fn to_noisy_image(&self, module_size: u32, noise: NoiseModel, seed: u64) -> RgbImage {
+        let clean = self.render_color(module_size);
+        let (w, h) = clean.dimensions();
+
+        let mut canvas = RgbImage::from_pixel(w, h, image::Rgb([255, 255, 255]));
+        for y in 0..h {
+            let inset = noise.warp.clamp(0.0, 0.45) * w as f32 * (1.0 - y as f32 / h as f32);
+            let row_width = w as f32 - 2.0 * inset;
+            for x in 0..w {
+                let sx = (x as f32 + 0.5 - inset) * w as f32 / row_width;
+                if (0.0..w as f32).contains(&sx) {
+                    canvas.put_pixel(x, y, *clean.get_pixel(sx as u32, y));
+                }
+            }
+        }
+
+        if noise.blur > 0.0 {
+            canvas = blur(&canvas, noise.blur);
+        }
+
+        let mut state = seed;
+        for px in canvas.pixels_mut() {
+            let r = splitmix64(&mut state);
+            if ((r >> 11) as f64 / (1u64 << 53) as f64) < noise.salt_pepper as f64 {
+                *px = if r & 1 == 0 { image::Rgb([0, 0, 0]) } else { image::Rgb([255, 255, 255]) };
+            }
+        }
+
+        canvas
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod contact_sheet_tests {
+    use image::GenericImageView;
+
+    use super::QR;
+    use crate::{builder::QRBuilder, metadata::Version, reader::QRReader};
+
+    #[test]
+    fn test_contact_sheet() {
+        let inputs = [
+            ("Hello, world!", Version::Normal(1)),
+            ("0123456789", Version::Normal(1)),
+            ("HELLO WORLD", Version::Normal(2)),
+            ("Hello, world!🌎", Version::Normal(3)),
+        ];
+        let codes = inputs
+            .iter()
+            .map(|(data, v)| QRBuilder::new(data.as_bytes()).version(*v).build().unwrap())
+            .collect::<Vec<_>>();
+
+        let (module_size, gap) = (4, 10);
+        let sheet = QR::contact_sheet(&codes, 3, module_size, gap);
+        let cell_size = (29 + 8) * module_size;
+        assert_eq!(sheet.dimensions(), (3 * cell_size + 2 * gap, 2 * cell_size + gap));
+
+        for (i, (data, version)) in inputs.iter().enumerate() {
+            let x = (i % 3) as u32 * (cell_size + gap);
+            let y = (i / 3) as u32 * (cell_size + gap);
+            let size = (version.width() as u32 + 8) * module_size;
+            let cell = sheet.view(x, y, size, size).to_image();
+            assert_eq!(QRReader::read_from_rgb(&cell, *version).unwrap(), *data, "Cell {i}");
+        }
+    }
+
+    #[test]
+    fn test_contact_sheet_zero_cols() {
+        let codes = ["Hello", "World"]
+            .iter()
+            .map(|d| QRBuilder::new(d.as_bytes()).version(Version::Normal(1)).build().unwrap())
+            .collect::<Vec<_>>();
+        let sheet = QR::contact_sheet(&codes, 0, 4, 10);
+        let cell_size = (21 + 8) * 4;
+        assert_eq!(sheet.dimensions(), (cell_size, 2 * cell_size + 10));
+    }
+
+    #[test]
+    fn test_contact_sheet_empty() {
+        let sheet = QR::contact_sheet(&[], 2, 4, 10);
+        assert_eq!(sheet.dimensions(), (0, 0));
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod noisy_image_tests {
+    use image::{DynamicImage, RgbImage};
+    use test_case::test_case;
+
+    use super::{NoiseModel, QR};
+    use crate::{
+        builder::QRBuilder,
+        metadata::{ECLevel, Version},
+    };
+
+    fn qr() -> QR {
+        QRBuilder::new("Hello, world!🌎".as_bytes())
+            .version(Version::Normal(3))
+            .ec_level(ECLevel::M)
+            .build()
+            .unwrap()
+    }
+
+    fn decode(img: RgbImage) -> Option<String> {
+        let mut img = rqrr::PreparedImage::prepare(DynamicImage::ImageRgb8(img).to_luma8());
+        let grids = img.detect_grids();
+        grids.first().and_then(|g| g.decode().ok()).map(|(_, content)| content)
+    }
+
+    #[test]
+    fn test_noisy_image_deterministic() {
+        let qr = qr();
+        let noise = NoiseModel { blur: 1.0, salt_pepper: 0.05, warp: 0.1 };
+        assert_eq!(qr.to_noisy_image(4, noise, 7), qr.to_noisy_image(4, noise, 7));
+        assert_ne!(qr.to_noisy_image(4, noise, 7), qr.to_noisy_image(4, noise, 8));
+        assert_eq!(qr.to_noisy_image(4, NoiseModel::default(), 7), qr.render_color(4));
+    }
+
+    // Thresholds for v3-M at 6px modules with seed 42, the first failing level in brackets:
+    // blur sigma 2.0 (2.25), salt & pepper 6% (8%), keystone warp 0.08 (0.10)
+    #[test_case(NoiseModel { blur: 1.0, ..Default::default() }, true)]
+    #[test_case(NoiseModel { blur: 2.0, ..Default::default() }, true)]
+    #[test_case(NoiseModel { blur: 3.0, ..Default::default() }, false)]
+    #[test_case(NoiseModel { salt_pepper: 0.02, ..Default::default() }, true)]
+    #[test_case(NoiseModel { salt_pepper: 0.06, ..Default::default() }, true)]
+    #[test_case(NoiseModel { salt_pepper: 0.12, ..Default::default() }, false)]
+    #[test_case(NoiseModel { warp: 0.04, ..Default::default() }, true)]
+    #[test_case(NoiseModel { warp: 0.08, ..Default::default() }, true)]
+    #[test_case(NoiseModel { warp: 0.15, ..Default::default() }, false)]
+    fn test_noisy_image_decode(noise: NoiseModel, decodes: bool) {
+        let decoded = decode(qr().to_noisy_image(6, noise, 42));
+        assert_eq!(decoded.as_deref() == Some("Hello, world!🌎"), decodes, "{noise:?}");
     }
 }
 
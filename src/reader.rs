@@ -1,25 +1,210 @@
-use image::GrayImage;
+use std::path::Path;
+
+use image::{GenericImageView, GrayImage, RgbImage};
 
 use crate::{
-    codec::decode,
+    codec::decode_with_segments,
     deqr::DeQR,
     ec::rectify,
     error::{QRError, QRResult},
-    metadata::{Palette, Version},
+    metadata::{Metadata, Palette, Version},
+    qr::QR,
 };
 
+pub use crate::deqr::{LumaSource, Quad, Rect, ThresholdMethod};
+
 pub struct QRReader();
 
 impl QRReader {
-    pub fn read(qr: GrayImage) -> String {
-        todo!()
+    // Reads a built grid directly, skipping all image sampling
+    pub fn read(qr: &QR) -> QRResult<String> {
+        println!("Reading QR...");
+        let deqr = DeQR::from_qr(qr);
+        Self::read_deqr(deqr, qr.version())
+    }
+
+    // Reads a built grid to its raw payload, for data that isn't utf-8
+    pub fn read_bytes(qr: &QR) -> QRResult<Vec<u8>> {
+        println!("Reading QR...");
+        let deqr = DeQR::from_qr(qr);
+        Self::read_deqr_bytes_with_metadata(deqr, qr.version()).map(|(data, _)| data)
     }
 
     // TODO: Remove version
     pub fn read_from_str(qr: &str, version: Version) -> QRResult<String> {
         println!("Reading QR...");
-        let mut deqr = DeQR::from_str(qr, version);
+        let deqr = DeQR::from_str(qr, version);
+        Self::read_deqr(deqr, version)
+    }
+
+    // Infers the version from the line width, which includes the quiet zone: 4 modules a side for
+    // normal codes and 2 for micro codes
+    pub fn read_from_str_auto(qr: &str) -> QRResult<String> {
+        let full_width = qr.lines().next().map_or(0, |l| l.chars().count());
+        let version = full_width
+            .checked_sub(8)
+            .and_then(Version::from_grid_size)
+            .filter(|v| matches!(v, Version::Normal(_)))
+            .or_else(|| full_width.checked_sub(4).and_then(Version::from_grid_size))
+            .ok_or(QRError::InvalidVersion)?;
+
+        Self::read_from_str(qr, version)
+    }
+
+    // Failing to open or decode the file surfaces as QRError::Image
+    pub fn read_from_path<P: AsRef<Path>>(path: P, version: Version) -> QRResult<String> {
+        let img = image::open(path)?.to_luma8();
+        Self::read_from_image(&img, version)
+    }
+
+    pub fn read_from_image<I: LumaSource>(qr: &I, version: Version) -> QRResult<String> {
+        println!("Reading QR...");
+        let deqr = DeQR::from_image(qr, version);
+        Self::read_sampled(deqr, version)
+    }
 
+    pub fn read_from_image_with_threshold<I: LumaSource>(
+        qr: &I,
+        version: Version,
+        threshold: ThresholdMethod,
+    ) -> QRResult<String> {
+        println!("Reading QR...");
+        let deqr = DeQR::from_image_with_threshold(qr, version, threshold);
+        Self::read_sampled(deqr, version)
+    }
+
+    // Reads only the region of a larger frame where the code was last seen, e.g. when tracking
+    // across video frames. The region should frame the code with its quiet zone. Bounds are in
+    // frame coordinates. A region missing the code errors with NoSymbolFound
+    pub fn read_roi(frame: &GrayImage, roi: Rect, version: Version) -> QRResult<(String, Quad)> {
+        let (w, h) = frame.dimensions();
+        let (x, y) = (roi.x.min(w), roi.y.min(h));
+        let (roi_w, roi_h) = (roi.w.min(w - x), roi.h.min(h - y));
+        if roi_w == 0 || roi_h == 0 {
+            return Err(QRError::NoSymbolFound);
+        }
+
+        println!("Reading QR in region {x} {y} {roi_w} {roi_h}...");
+        let crop = frame.view(x, y, roi_w, roi_h).to_image();
+        let deqr = DeQR::from_image(&crop, version);
+        let bounds = deqr.bounds().map(|(bx, by)| (bx + x as f32, by + y as f32));
+        Self::read_sampled(deqr, version).map(|data| (data, bounds))
+    }
+
+    // Rebuilds the sampled grid as a QR, still masked, for diffing against the ideal code to find
+    // modules that printed wrong. Ec level and mask come from the format info. Poly grids are
+    // sampled per rgb channel, and error with InvalidPalette when the source has no color
+    pub fn read_grid<I: LumaSource>(qr: &I, version: Version, palette: Palette) -> QRResult<QR> {
+        println!("Reading QR grid...");
+        let mut deqr = match palette {
+            Palette::Mono => DeQR::from_image(qr, version),
+            Palette::Poly => {
+                let rgb = qr.to_rgb().ok_or(QRError::InvalidPalette)?;
+                DeQR::from_rgb(&rgb, version)
+            }
+        };
+        if deqr.count_intact_finders() == 0 {
+            return Err(QRError::NoSymbolFound);
+        }
+
+        let w = version.width() as i16;
+        let colors = (0..w)
+            .flat_map(|r| (0..w).map(move |c| (r, c)))
+            .map(|(r, c)| *deqr.get(r, c))
+            .collect::<Vec<_>>();
+
+        let (ec_level, _) = deqr.read_format_info()?;
+        let mut grid = QR::from_colors(version, ec_level, palette, &colors)?;
+        if let Some(ec_levels) = deqr.channel_ec_levels() {
+            grid.set_channel_ec_levels(ec_levels);
+        }
+        Ok(grid)
+    }
+
+    // Also returns the decoded metadata, including the mode and char count of every segment
+    pub fn read_with_metadata<I: LumaSource>(
+        qr: &I,
+        version: Version,
+    ) -> QRResult<(String, Metadata)> {
+        println!("Reading QR...");
+        let deqr = DeQR::from_image(qr, version);
+        Self::read_sampled_with_metadata(deqr, version)
+    }
+
+    // Reads format and version info without touching the payload, to help tell why a scan fails.
+    // Errors with NoSymbolFound when no finder is found where the version puts them
+    pub fn read_metadata<I: LumaSource>(qr: &I, version: Version) -> QRResult<Metadata> {
+        println!("Reading QR metadata...");
+        let mut deqr = DeQR::from_image(qr, version);
+        if deqr.count_intact_finders() == 0 {
+            return Err(QRError::NoSymbolFound);
+        }
+
+        println!("Reading format info...");
+        deqr.read_format_info()?;
+
+        if let Version::Normal(7..=40) = version {
+            println!("Reading version info...");
+            if deqr.read_version_info()? != version {
+                return Err(QRError::InvalidVersionInfo);
+            }
+        }
+
+        Ok(deqr.metadata())
+    }
+
+    // Also returns the sampling confidence in 0.0..=1.0, so callers can reject low quality reads
+    pub fn read_with_confidence<I: LumaSource>(
+        qr: &I,
+        version: Version,
+    ) -> QRResult<(String, f32)> {
+        println!("Reading QR...");
+        let deqr = DeQR::from_image(qr, version);
+        let confidence = deqr.confidence();
+        Self::read_sampled(deqr, version).map(|data| (data, confidence))
+    }
+
+    pub fn read_from_rgb(qr: &RgbImage, version: Version) -> QRResult<String> {
+        println!("Reading QR...");
+        let deqr = DeQR::from_rgb(qr, version);
+        Self::read_sampled(deqr, version)
+    }
+
+    // Reads a row-major 8 bit grayscale buffer without constructing an image. Errors with
+    // InvalidBufferSize unless the buffer holds exactly w * h bytes
+    pub fn read_from_luma(data: &[u8], w: u32, h: u32, version: Version) -> QRResult<String> {
+        println!("Reading QR...");
+        let deqr = DeQR::from_luma(data, w, h, version)?;
+        Self::read_sampled(deqr, version)
+    }
+
+    fn read_deqr(deqr: DeQR, version: Version) -> QRResult<String> {
+        Self::read_deqr_with_metadata(deqr, version).map(|(data, _)| data)
+    }
+
+    fn read_sampled(deqr: DeQR, version: Version) -> QRResult<String> {
+        Self::read_sampled_with_metadata(deqr, version).map(|(data, _)| data)
+    }
+
+    // Images may hold no code at all, so sampled reads report NoSymbolFound when no finder is
+    // where the version puts them, and DecodeFailed for any later failure
+    fn read_sampled_with_metadata(deqr: DeQR, version: Version) -> QRResult<(String, Metadata)> {
+        if deqr.count_intact_finders() == 0 {
+            return Err(QRError::NoSymbolFound);
+        }
+        Self::read_deqr_with_metadata(deqr, version).or(Err(QRError::DecodeFailed))
+    }
+
+    fn read_deqr_with_metadata(deqr: DeQR, version: Version) -> QRResult<(String, Metadata)> {
+        let (data, metadata) = Self::read_deqr_bytes_with_metadata(deqr, version)?;
+        let data = String::from_utf8(data).or(Err(QRError::InvalidUTF8Sequence))?;
+        Ok((data, metadata))
+    }
+
+    fn read_deqr_bytes_with_metadata(
+        mut deqr: DeQR,
+        version: Version,
+    ) -> QRResult<(Vec<u8>, Metadata)> {
         println!("Reading format info...");
         let (ec_level, mask_pattern) = deqr.read_format_info()?;
 
@@ -38,26 +223,34 @@ impl QRReader {
         println!("Extracting payload...");
         let payload = deqr.extract_payload(version);
 
+        // Each poly channel is rectified independently at its own ec level, then chained back in
+        // channel order
         // TODO: Dynamically identify and enter palette type
-        let data_size = version.bit_capacity(ec_level, Palette::Mono) >> 3;
-        let block_info = version.data_codewords_per_block(ec_level);
-        let total_blocks = block_info.1 + block_info.3;
-        let epb = version.ecc_per_block(ec_level);
+        let mut data = Vec::with_capacity(payload.len());
+        for (ch, channel) in payload.chunks(version.channel_codewords()).enumerate() {
+            let ec_level = deqr.channel_ec_levels().map_or(ec_level, |l| l[ch]);
+            // M1 and M3 end their data on a 4 bit codeword, which still takes a byte
+            let data_size = version.bit_capacity(ec_level, Palette::Mono).div_ceil(8);
+            let block_info = version.data_codewords_per_block(ec_level);
+            let total_blocks = block_info.1 + block_info.3;
+            let epb = version.ecc_per_block(ec_level);
 
-        println!("Deinterleaving data and ecc...");
-        let data_blocks: Vec<Vec<u8>> = Self::deinterleave(&payload[..data_size], block_info);
-        let ecc_blocks: Vec<Vec<u8>> =
-            Self::deinterleave(&payload[data_size..], (epb, total_blocks, 0, 0));
+            println!("Deinterleaving data and ecc...");
+            let data_blocks: Vec<Vec<u8>> = Self::deinterleave(&channel[..data_size], block_info);
+            let ecc_blocks: Vec<Vec<u8>> =
+                Self::deinterleave(&channel[data_size..], (epb, total_blocks, 0, 0));
 
-        println!("Rectifying data...");
-        let data = rectify(&data_blocks, &ecc_blocks);
+            println!("Rectifying data...");
+            data.extend(rectify(&data_blocks, &ecc_blocks)?);
+        }
 
         println!("Decoding data blocks...");
-        let data = decode(&data, version);
+        let (data, segments) = decode_with_segments(&data, version);
+        let metadata = deqr.metadata().with_segments(segments);
 
-        println!("\n{}\n", deqr.metadata());
+        println!("\n{}\n", metadata);
 
-        String::from_utf8(data).or(Err(QRError::InvalidUTF8Sequence))
+        Ok((data, metadata))
     }
 
     fn deinterleave(data: &[u8], block_info: (usize, usize, usize, usize)) -> Vec<Vec<u8>> {
@@ -85,13 +278,21 @@ impl QRReader {
 
 #[cfg(test)]
 mod reader_tests {
+    use image::{
+        imageops::{flip_horizontal, invert, resize, FilterType},
+        GenericImage, GenericImageView, GrayImage, Luma, Rgb, RgbImage,
+    };
     use test_case::test_case;
 
     use super::QRReader;
     use crate::{
         builder::QRBuilder,
-        ec::blockify,
-        metadata::{ECLevel, Version},
+        codec::Mode,
+        deqr::{DeQR, Rect, ThresholdMethod},
+        ec::{blockify, error_correction_capacity, interleave},
+        error::QRError,
+        mask::MaskPattern,
+        metadata::{Color, ECLevel, Palette, Version},
     };
 
     #[test]
@@ -103,7 +304,7 @@ mod reader_tests {
 
         let data_blocks = blockify(data, version, ec_level);
 
-        let interleaved = QRBuilder::interleave(&data_blocks);
+        let interleaved = interleave(&data_blocks);
 
         let block_info = version.data_codewords_per_block(ec_level);
         let deinterleaved = QRReader::deinterleave(&interleaved, block_info);
@@ -142,4 +343,493 @@ mod reader_tests {
 
         assert_eq!(decoded_data, data);
     }
+
+    #[test_case("Hello, world!🌎".to_string(), Version::Normal(1), ECLevel::L)]
+    #[test_case("A11111111111111".repeat(11).to_string(), Version::Normal(7), ECLevel::M)]
+    #[test_case("1234567890".repeat(28).to_string(), Version::Normal(10), ECLevel::H)]
+    #[test_case("12345".to_string(), Version::Micro(1), ECLevel::L)]
+    #[test_case("HELLO".to_string(), Version::Micro(2), ECLevel::M)]
+    #[test_case("Hello, w".to_string(), Version::Micro(3), ECLevel::L)]
+    #[test_case("123".to_string(), Version::Micro(3), ECLevel::M)]
+    #[test_case("Hello, wo".to_string(), Version::Micro(4), ECLevel::Q)]
+    fn test_reader_from_str_auto(data: String, version: Version, ec_level: ECLevel) {
+        let qr = QRBuilder::new(data.as_bytes())
+            .version(version)
+            .ec_level(ec_level)
+            .build()
+            .unwrap()
+            .to_str(1);
+
+        let decoded_data = QRReader::read_from_str_auto(&qr).unwrap();
+
+        assert_eq!(decoded_data, data);
+    }
+
+    #[test]
+    fn test_reader_from_path() {
+        let data = "Hello, world!🌎";
+        let version = Version::Normal(1);
+        let path = std::env::temp_dir().join("qr_pro_max_read_from_path.png");
+        QRBuilder::new(data.as_bytes())
+            .version(version)
+            .ec_level(ECLevel::L)
+            .build()
+            .unwrap()
+            .render(3)
+            .save(&path)
+            .unwrap();
+
+        let decoded_data = QRReader::read_from_path(&path, version);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(decoded_data.unwrap(), data);
+    }
+
+    #[test]
+    fn test_reader_from_missing_path() {
+        let path = std::env::temp_dir().join("qr_pro_max_missing.png");
+        let res = QRReader::read_from_path(path, Version::Normal(1));
+        assert!(matches!(res, Err(QRError::Image(_))), "{res:?}");
+    }
+
+    #[test_case(30)]
+    #[test_case(3)]
+    fn test_reader_from_str_auto_unsupported(full_width: usize) {
+        let qr = ("█".repeat(full_width) + "\n").repeat(full_width);
+        assert_eq!(QRReader::read_from_str_auto(&qr), Err(QRError::InvalidVersion));
+    }
+
+    #[test_case("Hello, world!🌎".to_string(), Version::Normal(1), ECLevel::L)]
+    #[test_case("A11111111111111".repeat(11).to_string(), Version::Normal(7), ECLevel::M)]
+    #[test_case("1234567890".repeat(28).to_string(), Version::Normal(10), ECLevel::H)]
+    fn test_reader_from_luma(data: String, version: Version, ec_level: ECLevel) {
+        let img = QRBuilder::new(data.as_bytes())
+            .version(version)
+            .ec_level(ec_level)
+            .build()
+            .unwrap()
+            .render(2);
+        let (w, h) = img.dimensions();
+
+        let from_image = QRReader::read_from_image(&img, version).unwrap();
+        let from_luma = QRReader::read_from_luma(img.as_raw(), w, h, version).unwrap();
+
+        assert_eq!(from_image, data);
+        assert_eq!(from_luma, data);
+    }
+
+    #[test_case(10, 100, 100)]
+    #[test_case(10, 40, 40)]
+    #[test_case(1700, 40, 40)]
+    #[test_case(1, 0, 1)]
+    fn test_reader_from_luma_bad_buffer(len: usize, w: u32, h: u32) {
+        let data = vec![255; len];
+        let res = QRReader::read_from_luma(&data, w, h, Version::Normal(1));
+        assert_eq!(res, Err(QRError::InvalidBufferSize));
+    }
+
+    #[test]
+    fn test_reader_metadata_segments() {
+        let data = "hello 🌎 12345678901234567890";
+        let version = Version::Normal(2);
+        let img = QRBuilder::new(data.as_bytes())
+            .version(version)
+            .ec_level(ECLevel::L)
+            .build()
+            .unwrap()
+            .render(2);
+
+        let (decoded_data, metadata) = QRReader::read_with_metadata(&img, version).unwrap();
+
+        assert_eq!(decoded_data, data);
+        assert_eq!(metadata.segments(), &[(Mode::Byte, 11), (Mode::Numeric, 20)]);
+        assert_eq!(metadata.version(), Some(version));
+    }
+
+    #[test]
+    fn test_reader_confidence() {
+        let data = "Hello, world!🌎";
+        let version = Version::Normal(3);
+        let img = QRBuilder::new(data.as_bytes())
+            .version(version)
+            .ec_level(ECLevel::H)
+            .build()
+            .unwrap()
+            .render(2);
+
+        let (clean, clean_conf) = QRReader::read_with_confidence(&img, version).unwrap();
+        assert_eq!(clean, data);
+        assert!(clean_conf > 0.9, "{clean_conf}");
+
+        // Deterministic noise of up to +-90 per pixel
+        let mut noisy = img.clone();
+        let mut seed = 0x2545_f491_u32;
+        for p in noisy.pixels_mut() {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            let noise = (seed >> 16) as i32 % 181 - 90;
+            p.0[0] = (p.0[0] as i32 + noise).clamp(0, 255) as u8;
+        }
+
+        let (noisy, noisy_conf) = QRReader::read_with_confidence(&noisy, version).unwrap();
+        assert_eq!(noisy, data);
+        assert!(noisy_conf < clean_conf, "{noisy_conf} >= {clean_conf}");
+    }
+
+    #[test_case("Hello, world!🌎".to_string(), Version::Normal(1), ECLevel::L)]
+    #[test_case("B3@j🎮#Z%8v🍣K!🔑3zC^8📖&r💾F9*🔐b6🌼".repeat(5).to_string(), Version::Normal(7), ECLevel::L)]
+    #[test_case("A11111111111111".repeat(33).to_string(), Version::Normal(7), ECLevel::M)]
+    #[test_case("1234567890".repeat(84).to_string(), Version::Normal(10), ECLevel::H)]
+    #[test_case("aAAAAAAAAA111111111111111111AAAAAAAAAAa".repeat(60).to_string(), Version::Normal(27), ECLevel::Q)]
+    fn test_reader_poly(data: String, version: Version, ec_level: ECLevel) {
+        let img = QRBuilder::new(data.as_bytes())
+            .version(version)
+            .ec_level(ec_level)
+            .palette(Palette::Poly)
+            .build()
+            .unwrap()
+            .render_color(2);
+
+        let decoded_data = QRReader::read_from_rgb(&img, version).unwrap();
+
+        assert_eq!(decoded_data, data);
+    }
+
+    #[test_case("Hello, world!🌎".to_string(), Version::Normal(1), [ECLevel::H, ECLevel::L, ECLevel::M])]
+    #[test_case("A11111111111111".repeat(20).to_string(), Version::Normal(7), [ECLevel::L, ECLevel::Q, ECLevel::H])]
+    #[test_case("1234567890".repeat(60).to_string(), Version::Normal(10), [ECLevel::M, ECLevel::M, ECLevel::L])]
+    fn test_reader_poly_channel_ec_levels(data: String, version: Version, ec_levels: [ECLevel; 3]) {
+        let img = QRBuilder::new(data.as_bytes())
+            .version(version)
+            .channel_ec_levels(ec_levels)
+            .palette(Palette::Poly)
+            .build()
+            .unwrap()
+            .render_color(2);
+
+        let mut deqr = DeQR::from_rgb(&img, version);
+        deqr.read_format_info().unwrap();
+        assert_eq!(deqr.channel_ec_levels(), Some(ec_levels));
+
+        let decoded_data = QRReader::read_from_rgb(&img, version).unwrap();
+
+        assert_eq!(decoded_data, data);
+    }
+
+    #[test_case("Hello, world!🌎".to_string(), Version::Normal(1), ECLevel::L, Palette::Mono)]
+    #[test_case("TEST".to_string(), Version::Normal(1), ECLevel::M, Palette::Poly)]
+    #[test_case("A11111111111111".repeat(11).to_string(), Version::Normal(7), ECLevel::M, Palette::Mono)]
+    #[test_case("B3@j🎮#Z%8v🍣K!🔑3zC^8📖&r💾F9*🔐b6🌼".repeat(5).to_string(), Version::Normal(7), ECLevel::L, Palette::Poly)]
+    #[test_case("1234567890".repeat(28).to_string(), Version::Normal(10), ECLevel::H, Palette::Mono)]
+    #[test_case("1234567890".repeat(84).to_string(), Version::Normal(10), ECLevel::H, Palette::Poly)]
+    #[test_case("aAAAAAAAAA111111111111111111AAAAAAAAAAa".repeat(20).to_string(), Version::Normal(27), ECLevel::Q, Palette::Mono)]
+    #[test_case("A111111111111111".repeat(97).to_string(), Version::Normal(40), ECLevel::M, Palette::Mono)]
+    #[test_case("A111111111111111".repeat(291).to_string(), Version::Normal(40), ECLevel::M, Palette::Poly)]
+    fn test_reader_from_grid(data: String, version: Version, ec_level: ECLevel, palette: Palette) {
+        let qr = QRBuilder::new(data.as_bytes())
+            .version(version)
+            .ec_level(ec_level)
+            .palette(palette)
+            .build()
+            .unwrap();
+
+        let decoded_data = QRReader::read(&qr).unwrap();
+
+        assert_eq!(decoded_data, data);
+    }
+
+    #[test]
+    fn test_reader_inverted() {
+        let data = "Hello, world!🌎";
+        let version = Version::Normal(1);
+        let mut img = QRBuilder::new(data.as_bytes())
+            .version(version)
+            .ec_level(ECLevel::L)
+            .build()
+            .unwrap()
+            .render(2);
+        let (_, metadata) = QRReader::read_with_metadata(&img, version).unwrap();
+        assert!(!metadata.is_inverted());
+
+        invert(&mut img);
+        let (decoded_data, metadata) = QRReader::read_with_metadata(&img, version).unwrap();
+        assert_eq!(decoded_data, data);
+        assert!(metadata.is_inverted());
+    }
+
+    #[test]
+    fn test_reader_mirrored() {
+        let data = "Hello, world!🌎";
+        let version = Version::Normal(3);
+        let img = QRBuilder::new(data.as_bytes()).version(version).build().unwrap().render(3);
+
+        let (_, metadata) = QRReader::read_with_metadata(&img, version).unwrap();
+        assert!(!metadata.is_mirrored());
+
+        let img = flip_horizontal(&img);
+        let (decoded_data, metadata) = QRReader::read_with_metadata(&img, version).unwrap();
+        assert_eq!(decoded_data, data);
+        assert!(metadata.is_mirrored());
+    }
+
+    #[test_case("HELLO", Version::Normal(1), ECLevel::H)]
+    #[test_case("Hello, world!🌎", Version::Normal(3), ECLevel::L)]
+    #[test_case("Hello, world!🌎", Version::Normal(7), ECLevel::H)]
+    fn test_reader_low_resolution(data: &str, version: Version, ec_level: ECLevel) {
+        let qr =
+            QRBuilder::new(data.as_bytes()).version(version).ec_level(ec_level).build().unwrap();
+
+        // Distant capture, 1.5 px per module with the blur of a camera's optics
+        let size = (version.width() as u32 + 8) * 3 / 2;
+        let img = resize(&qr.render(3), size, size, FilterType::Gaussian);
+
+        let decoded_data = QRReader::read_from_image(&img, version).unwrap();
+        assert_eq!(decoded_data, data);
+    }
+
+    #[test]
+    fn test_reader_damaged_finder() {
+        let data = "Hello, world!🌎";
+        let version = Version::Normal(3);
+        let mut img = QRBuilder::new(data.as_bytes())
+            .version(version)
+            .ec_level(ECLevel::H)
+            .build()
+            .unwrap()
+            .render(3);
+
+        // Sampling is placed by geometry rather than finder detection, so blanking most of the
+        // top left finder only costs finder modules
+        let qz_size = 4 * 3;
+        for y in qz_size..qz_size + 6 * 3 {
+            for x in qz_size..qz_size + 6 * 3 {
+                img.put_pixel(x, y, Luma([255]));
+            }
+        }
+
+        let decoded_data = QRReader::read_from_image(&img, version).unwrap();
+
+        assert_eq!(decoded_data, data);
+    }
+
+    #[test]
+    fn test_reader_aspect_distorted() {
+        let data = "Hello, world!🌎";
+        let version = Version::Normal(3);
+        let qr =
+            QRBuilder::new(data.as_bytes()).version(version).ec_level(ECLevel::M).build().unwrap();
+        let img = qr.render(6);
+
+        // Stretched horizontally by 1.15, as a phone photo might be
+        let (w, h) = img.dimensions();
+        let img = resize(&img, (w as f32 * 1.15) as u32, h, FilterType::Triangle);
+
+        let decoded_data = QRReader::read_from_image(&img, version).unwrap();
+
+        assert_eq!(decoded_data, data);
+    }
+
+    #[test_case(Version::Normal(3), ECLevel::L, 2)]
+    #[test_case(Version::Normal(7), ECLevel::Q, 5)]
+    fn test_read_metadata_corrupted_data(version: Version, ec_level: ECLevel, mask: u8) {
+        let qr = QRBuilder::new(b"Hello, world!")
+            .version(version)
+            .ec_level(ec_level)
+            .mask(MaskPattern::new(mask))
+            .build()
+            .unwrap();
+        let mut img = qr.render(4);
+
+        // Blank the middle of the data region, more than ecc can recover
+        let (qz_size, w) = (4 * 4, version.width() as u32 * 4);
+        for y in qz_size + 10 * 4..qz_size + w - 10 * 4 {
+            for x in qz_size + 10 * 4..qz_size + w - 10 * 4 {
+                img.put_pixel(x, y, Luma([255]));
+            }
+        }
+
+        let metadata = QRReader::read_metadata(&img, version).unwrap();
+
+        assert_eq!(metadata.version(), Some(version));
+        assert_eq!(metadata.ec_level(), Some(ec_level));
+        assert_eq!(metadata.mask(), Some(MaskPattern::new(mask)));
+    }
+
+    #[test]
+    fn test_read_metadata_no_finders() {
+        let img = GrayImage::from_pixel(116, 116, Luma([255]));
+        let res = QRReader::read_metadata(&img, Version::Normal(3));
+        assert_eq!(res.unwrap_err(), QRError::NoSymbolFound);
+    }
+
+    // Blank, solid and empty frames carry no code, they should be rejected rather than panic
+    #[test_case(116, 255, Version::Normal(3))]
+    #[test_case(116, 0, Version::Normal(3))]
+    #[test_case(1, 255, Version::Normal(1))]
+    #[test_case(0, 255, Version::Normal(1))]
+    #[test_case(60, 255, Version::Micro(2))]
+    #[test_case(60, 0, Version::Micro(2))]
+    #[test_case(1, 0, Version::Micro(2))]
+    #[test_case(0, 0, Version::Micro(2))]
+    fn test_reader_degenerate_image(size: u32, luma: u8, version: Version) {
+        let img = GrayImage::from_pixel(size, size, Luma([luma]));
+        let no_symbol = Err(QRError::NoSymbolFound);
+        assert_eq!(QRReader::read_from_image(&img, version), no_symbol);
+        assert_eq!(QRReader::read_with_confidence(&img, version).map(|(d, _)| d), no_symbol);
+        assert_eq!(
+            QRReader::read_grid(&img, version, Palette::Mono).map(|_| String::new()),
+            no_symbol
+        );
+        assert_eq!(QRReader::read_from_luma(img.as_raw(), size, size, version), no_symbol);
+    }
+
+    #[test]
+    fn test_reader_no_symbol_vs_decode_failed() {
+        let version = Version::Normal(3);
+        let blank = GrayImage::from_pixel(116, 116, Luma([255]));
+        let res = QRReader::read_from_image(&blank, version);
+        assert_eq!(res.unwrap_err(), QRError::NoSymbolFound);
+
+        // Finders and format info survive, but the data region is beyond what ecc can recover
+        let qr = QRBuilder::new(b"Hello, world!").version(version).ec_level(ECLevel::L).build();
+        let mut img = qr.unwrap().render(4);
+        let (qz_size, w) = (4 * 4, version.width() as u32 * 4);
+        for y in qz_size + 10 * 4..qz_size + w - 10 * 4 {
+            for x in qz_size + 10 * 4..qz_size + w - 10 * 4 {
+                img.put_pixel(x, y, Luma([255]));
+            }
+        }
+        let res = QRReader::read_from_image(&img, version);
+        assert_eq!(res.unwrap_err(), QRError::DecodeFailed);
+    }
+
+    #[test]
+    fn test_reader_rgb_and_gray_sources() {
+        let data = "Hello, world!🌎";
+        let version = Version::Normal(3);
+        let qr = QRBuilder::new(data.as_bytes()).version(version).build().unwrap();
+
+        let gray = qr.render(4);
+        assert_eq!(QRReader::read_from_image(&gray, version).unwrap(), data);
+
+        // Navy on cream, weighted to luma rather than needing a grayscale copy up front
+        let rgb = RgbImage::from_fn(gray.width(), gray.height(), |x, y| {
+            if gray.get_pixel(x, y).0[0] < 128 {
+                Rgb([20, 30, 90])
+            } else {
+                Rgb([250, 240, 210])
+            }
+        });
+        assert_eq!(QRReader::read_from_image(&rgb, version).unwrap(), data);
+    }
+
+    // Cropped flush to the modules, so there's no quiet zone around the finders
+    #[test_case("Hello, world!", Version::Normal(1), 4)]
+    #[test_case("Hello, world!🌎", Version::Normal(3), 5)]
+    #[test_case("Hello, world!🌎", Version::Normal(7), 3)]
+    fn test_read_without_quiet_zone(data: &str, version: Version, module_size: u32) {
+        let qr = QRBuilder::new(data.as_bytes()).version(version).build().unwrap();
+        let qz_size = 4 * module_size;
+        let qr_size = version.width() as u32 * module_size;
+        let img = qr.render(module_size).view(qz_size, qz_size, qr_size, qr_size).to_image();
+
+        assert_eq!(QRReader::read_from_image(&img, version).unwrap(), data);
+    }
+
+    #[test]
+    fn test_read_grid_diff() {
+        let version = Version::Normal(2);
+        let qr = QRBuilder::new(b"Hello, world!").version(version).build().unwrap();
+        let mut img = qr.render(4);
+
+        let scanned = QRReader::read_grid(&img, version, Palette::Mono).unwrap();
+        assert!(qr.diff(&scanned).unwrap().is_empty());
+        assert_eq!(scanned.mask_pattern(), qr.mask_pattern());
+
+        // Misprint a single data module
+        let (r, c) = (12_u32, 15_u32);
+        let fill = if *qr.get(r as i16, c as i16) == Color::Dark { 255 } else { 0 };
+        for y in (4 + r) * 4..(5 + r) * 4 {
+            for x in (4 + c) * 4..(5 + c) * 4 {
+                img.put_pixel(x, y, Luma([fill]));
+            }
+        }
+
+        let scanned = QRReader::read_grid(&img, version, Palette::Mono).unwrap();
+        assert_eq!(qr.diff(&scanned), Ok(vec![(12, 15)]));
+    }
+
+    #[test]
+    fn test_read_grid_poly() {
+        let version = Version::Normal(3);
+        let ec_levels = [ECLevel::H, ECLevel::L, ECLevel::M];
+        let qr = QRBuilder::new(b"Hello, world!")
+            .version(version)
+            .palette(Palette::Poly)
+            .channel_ec_levels(ec_levels)
+            .build()
+            .unwrap();
+        let img = qr.render_color(4);
+
+        let scanned = QRReader::read_grid(&img, version, Palette::Poly).unwrap();
+        assert!(qr.diff(&scanned).unwrap().is_empty());
+        assert_eq!(scanned.palette(), Palette::Poly);
+        assert_eq!(scanned.channel_ec_levels(), Some(ec_levels));
+        assert_eq!(scanned.mask_pattern(), qr.mask_pattern());
+
+        // Gray images carry no channels to sample a poly grid from
+        let res = QRReader::read_grid(&qr.render(4), version, Palette::Poly);
+        assert_eq!(res.unwrap_err(), QRError::InvalidPalette);
+    }
+
+    #[test]
+    fn test_read_roi() {
+        let data = "Hello, world!🌎";
+        let version = Version::Normal(3);
+        let qr = QRBuilder::new(data.as_bytes()).version(version).build().unwrap();
+        let code = qr.render(4);
+        let mut frame = GrayImage::from_pixel(640, 480, Luma([255]));
+        frame.copy_from(&code, 300, 150).unwrap();
+
+        let roi = Rect { x: 300, y: 150, w: code.width(), h: code.height() };
+        let (decoded_data, bounds) = QRReader::read_roi(&frame, roi, version).unwrap();
+        assert_eq!(decoded_data, data);
+        assert_eq!(bounds[0], (316.0, 166.0));
+
+        let roi = Rect { x: 0, y: 0, w: 200, h: 200 };
+        let res = QRReader::read_roi(&frame, roi, version);
+        assert_eq!(res.unwrap_err(), QRError::NoSymbolFound);
+
+        let roi = Rect { x: 700, y: 0, w: 200, h: 200 };
+        let res = QRReader::read_roi(&frame, roi, version);
+        assert_eq!(res.unwrap_err(), QRError::NoSymbolFound);
+    }
+
+    #[test]
+    fn test_reader_low_contrast_otsu() {
+        let data = "Hello, world!🌎";
+        let version = Version::Normal(2);
+        let qr =
+            QRBuilder::new(data.as_bytes()).version(version).ec_level(ECLevel::L).build().unwrap();
+        let mut img = qr.render(1);
+
+        // Dark at 100 and light at 140 with deterministic noise of up to +-18. The mostly light
+        // quiet zone drags the mean into the light class, Otsu splits the two evenly
+        let mut seed = 0x2545_f491_u32;
+        for p in img.pixels_mut() {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            let noise = (seed >> 16) as i32 % 37 - 18;
+            p.0[0] = ((if p.0[0] < 128 { 100 } else { 140 }) + noise) as u8;
+        }
+
+        let otsu = QRReader::read_from_image_with_threshold(&img, version, ThresholdMethod::Otsu);
+        assert_eq!(otsu.unwrap(), data);
+
+        // Mean misreads more modules than ecc could absorb even if packed into whole codewords
+        let mean = DeQR::from_image_with_threshold(&img, version, ThresholdMethod::Mean);
+        let w = version.width() as i16;
+        let misread = (0..w * w).filter(|i| *qr.get(i / w, i % w) != *mean.get(i / w, i % w));
+        let capacity = error_correction_capacity(version, ECLevel::L);
+        let n = misread.count();
+        assert!(n > capacity * 8, "Misread {n}, Capacity {capacity}");
+    }
 }
@@ -0,0 +1,45 @@
+use crate::{
+    builder::QRBuilder,
+    metadata::{ECLevel, Palette},
+    reader::QRReader,
+};
+
+// Round trip
+//------------------------------------------------------------------------------
+
+// Builds data at the smallest version that fits, renders it, reads the image back and compares.
+// Any error along the way counts as a mismatch, while panics propagate so harnesses see them. Data
+// that isn't valid utf8 never matches since the reader returns strings
+pub fn roundtrip(data: &[u8], ec_level: ECLevel, palette: Palette) -> bool {
+    let Ok(qr) = QRBuilder::new(data).ec_level(ec_level).palette(palette).build() else {
+        return false;
+    };
+    let version = qr.version();
+    let decoded = match palette {
+        Palette::Mono => QRReader::read_from_image(&qr.render(4), version),
+        Palette::Poly => QRReader::read_from_rgb(&qr.render_color(4), version),
+    };
+    decoded.is_ok_and(|d| d.as_bytes() == data)
+}
+
+#[cfg(test)]
+mod testkit_tests {
+    use test_case::test_case;
+
+    use super::roundtrip;
+    use crate::metadata::{ECLevel, Palette};
+
+    #[test_case("Hello, world!🌎", ECLevel::L, Palette::Mono)]
+    #[test_case("0123456789".repeat(20), ECLevel::M, Palette::Mono)]
+    #[test_case("HELLO WORLD".repeat(10), ECLevel::Q, Palette::Poly)]
+    #[test_case("B3@j🎮#Z%8v🍣K!🔑3zC^8📖&r💾F9*🔐b6🌼".repeat(3), ECLevel::H, Palette::Poly)]
+    fn test_roundtrip(data: impl AsRef<str>, ec_level: ECLevel, palette: Palette) {
+        assert!(roundtrip(data.as_ref().as_bytes(), ec_level, palette));
+    }
+
+    #[test]
+    fn test_roundtrip_mismatch() {
+        assert!(!roundtrip(&[], ECLevel::L, Palette::Mono));
+        assert!(!roundtrip(&[0xFF, 0xFE], ECLevel::L, Palette::Mono));
+    }
+}
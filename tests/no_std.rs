@@ -0,0 +1,43 @@
+// Uses the encoding core from a no_std crate. Run with --no-default-features to link against the
+// library built without std as well
+#![no_std]
+
+extern crate alloc;
+extern crate std;
+
+use alloc::vec::Vec;
+
+use qr_pro_max::{
+    builder::QRBuilder,
+    metadata::{Color, ECLevel, Version},
+    qr::Module,
+};
+
+#[test]
+fn test_build_version_1() {
+    let qr =
+        QRBuilder::new(b"Hello, world!").version(Version::Normal(1)).ec_level(ECLevel::L).build();
+    let qr = qr.unwrap();
+    assert_eq!(qr.version(), Version::Normal(1));
+    assert_eq!(qr.width(), 21);
+    assert!(qr.mask_pattern().is_some());
+
+    // Finder rings at the three corners, with the timing pattern running between them
+    for (r, c) in [(0, 0), (0, 20), (20, 0)] {
+        assert_eq!(qr.get(r, c), Module::Func(Color::Dark), "{r} {c}");
+    }
+    assert_eq!(qr.get(1, 1), Module::Func(Color::Light));
+    for i in 8..13 {
+        let exp = if i & 1 == 0 { Color::Dark } else { Color::Light };
+        assert_eq!(qr.get(6, i), Module::Func(exp), "{i}");
+        assert_eq!(qr.get(i, 6), Module::Func(exp), "{i}");
+    }
+
+    // 26 codewords of 8 modules each, no remainder bits at version 1
+    let data_modules = (0..21)
+        .flat_map(|r| (0..21).map(move |c| (r, c)))
+        .filter(|&(r, c)| matches!(qr.get(r, c), Module::Data(_)))
+        .collect::<Vec<_>>();
+    assert_eq!(data_modules.len(), 26 * 8);
+    assert_eq!(qr.codewords().len(), 26);
+}
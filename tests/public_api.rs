@@ -0,0 +1,90 @@
+// Names types re-exported from private modules the way a downstream crate would
+
+use qr_pro_max::{
+    builder::QRBuilder,
+    metadata::{ECLevel, Palette, Version},
+    Encoder, Mode, SegmentStat,
+};
+
+#[test]
+fn test_mode_from_outside_crate() {
+    let data = b"HELLO WORLD";
+    let mut builder = QRBuilder::new(data);
+    builder.version(Version::Normal(1)).ec_level(ECLevel::L).mode(Mode::Alphanumeric);
+    let stats: Vec<SegmentStat> = builder.analyze().unwrap();
+    assert_eq!(stats.len(), 1);
+    assert_eq!(stats[0].mode, Mode::Alphanumeric);
+    assert_eq!(stats[0].char_count, data.len());
+
+    let cap = Version::Normal(1).capacity_in_mode(ECLevel::L, Mode::Alphanumeric, Palette::Mono);
+    assert_eq!(cap, 25);
+    assert!(builder.build().is_ok());
+}
+
+#[test]
+fn test_encoder_from_outside_crate() {
+    let encoder = Encoder::new(Version::Normal(1), ECLevel::M).unwrap();
+    let payload = encoder.encode_payload(&[0; 16]);
+    assert_eq!(payload.len(), 26);
+    assert!(Encoder::new(Version::Micro(1), ECLevel::H).is_err());
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_read_roi_from_outside_crate() {
+    use image::{GenericImage, GrayImage, Luma};
+    use qr_pro_max::reader::{QRReader, Quad, Rect};
+
+    let version = Version::Normal(2);
+    let qr = QRBuilder::new(b"Hello, world!").version(version).build().unwrap();
+    let code = qr.render(4);
+    let mut frame = GrayImage::from_pixel(400, 300, Luma([255]));
+    frame.copy_from(&code, 100, 50).unwrap();
+
+    let roi = Rect { x: 100, y: 50, w: code.width(), h: code.height() };
+    let (data, bounds): (String, Quad) = QRReader::read_roi(&frame, roi, version).unwrap();
+    assert_eq!(data, "Hello, world!");
+    assert_eq!(bounds[0], (116.0, 66.0));
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_threshold_method_from_outside_crate() {
+    use qr_pro_max::reader::{QRReader, ThresholdMethod};
+
+    let version = Version::Normal(2);
+    let qr = QRBuilder::new(b"Hello, world!").version(version).build().unwrap();
+    let img = qr.render(4);
+    for threshold in [ThresholdMethod::Otsu, ThresholdMethod::Fixed(128)] {
+        let data = QRReader::read_from_image_with_threshold(&img, version, threshold).unwrap();
+        assert_eq!(data, "Hello, world!");
+    }
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_luma_source_from_outside_crate() {
+    use std::borrow::Cow;
+
+    use image::GrayImage;
+    use qr_pro_max::reader::{LumaSource, QRReader};
+
+    // Camera frame holding one luma byte per pixel, row by row
+    struct Frame {
+        width: u32,
+        height: u32,
+        luma: Vec<u8>,
+    }
+
+    impl LumaSource for Frame {
+        fn to_luma(&self) -> Cow<'_, GrayImage> {
+            Cow::Owned(GrayImage::from_raw(self.width, self.height, self.luma.clone()).unwrap())
+        }
+    }
+
+    let version = Version::Normal(2);
+    let qr = QRBuilder::new(b"Hello, world!").version(version).build().unwrap();
+    let img = qr.render(4);
+    let frame = Frame { width: img.width(), height: img.height(), luma: img.into_raw() };
+    assert_eq!(QRReader::read_from_image(&frame, version).unwrap(), "Hello, world!");
+}
@@ -0,0 +1,13 @@
+// Reaches the round trip helper the way a downstream crate would, through the lib target
+#![cfg(feature = "testkit")]
+
+use qr_pro_max::{
+    metadata::{ECLevel, Palette},
+    testkit::roundtrip,
+};
+
+#[test]
+fn test_roundtrip_from_outside_crate() {
+    assert!(roundtrip(b"Hello, world!", ECLevel::M, Palette::Mono));
+    assert!(roundtrip(b"Hello, world!", ECLevel::M, Palette::Poly));
+}